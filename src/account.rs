@@ -0,0 +1,179 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Bech32 addresses of account-based networks
+//!
+//! Zilliqa, Crypto.com Chain, Zen Protocol and QRL use Bech32 to encode an
+//! account or contract payload rather than a witness program: there is no
+//! witness version, and the whole data part is the payload.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bech32::{self, FromBase32, ToBase32, Variant};
+
+use constants::{self, Network, PayloadFamily};
+use Error;
+
+/// A Bech32 address carrying an account or chain-specific payload
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct AccountAddress {
+    network: Network,
+    payload: Vec<u8>,
+    bech32: String,
+}
+
+impl AccountAddress {
+    /// Encodes a payload for an account or chain-specific network
+    pub fn new(payload: Vec<u8>, network: Network) -> Result<AccountAddress, Error> {
+        if constants::payload_family(&network) == PayloadFamily::Segwit {
+            return Err(Error::InvalidPayloadFamily);
+        }
+        let bech32 = bech32::encode(
+            &constants::hrp(&network),
+            payload.to_base32(),
+            Variant::Bech32,
+        )?;
+        Ok(AccountAddress {
+            network,
+            payload,
+            bech32,
+        })
+    }
+
+    /// Decodes an account address
+    pub fn from_address(address: &str) -> Result<AccountAddress, Error> {
+        AccountAddress::from_str(address)
+    }
+
+    /// Encodes the address as a string
+    pub fn to_address(&self) -> String {
+        self.to_string()
+    }
+
+    /// Network of the address
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Kind of payload the network uses
+    pub fn family(&self) -> PayloadFamily {
+        constants::payload_family(&self.network)
+    }
+
+    /// Decoded payload
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+impl fmt::Display for AccountAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.bech32)
+    }
+}
+
+impl FromStr for AccountAddress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<AccountAddress, Error> {
+        let (hrp, data, variant) = bech32::decode(s)?;
+        let network = constants::classify(&hrp).ok_or(Error::InvalidHumanReadablePart)?;
+        if constants::payload_family(&network) == PayloadFamily::Segwit {
+            return Err(Error::InvalidPayloadFamily);
+        }
+        if variant != Variant::Bech32 {
+            return Err(Error::InvalidChecksumVariant);
+        }
+        let payload = Vec::from_base32(&data)?;
+        Ok(AccountAddress {
+            network,
+            payload,
+            bech32: s.to_lowercase(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {WitnessProgram, WitnessVersion};
+
+    const ZIL: &str = "zil1fwh4ltdguhde9s7nysnp33d5wye6uqpugufkz7";
+
+    #[test]
+    fn account_round_trip() {
+        let address = AccountAddress::from_address(ZIL).unwrap();
+        assert_eq!(address.network(), Network::Zilliqa);
+        assert_eq!(address.family(), PayloadFamily::Account);
+        assert_eq!(address.payload().len(), 20);
+        assert_eq!(
+            AccountAddress::new(address.payload().to_vec(), Network::Zilliqa)
+                .unwrap()
+                .to_address(),
+            ZIL
+        );
+        assert_eq!(
+            AccountAddress::from_address(&ZIL.to_uppercase()).unwrap(),
+            address
+        );
+
+        let zen = AccountAddress::new(vec![0x00; 33], Network::ZenProtocol).unwrap();
+        assert_eq!(zen.family(), PayloadFamily::ChainSpecific);
+        assert_eq!(
+            AccountAddress::from_address(&zen.to_address()).unwrap(),
+            zen
+        );
+    }
+
+    #[test]
+    fn families_do_not_mix() {
+        assert_eq!(
+            WitnessProgram::from_address(ZIL),
+            Err(Error::InvalidPayloadFamily)
+        );
+        assert_eq!(
+            AccountAddress::from_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"),
+            Err(Error::InvalidPayloadFamily)
+        );
+        assert_eq!(
+            AccountAddress::new(vec![0; 20], Network::Bitcoin),
+            Err(Error::InvalidPayloadFamily)
+        );
+        let cro = AccountAddress::new(vec![0; 20], Network::CryptoComChain).unwrap();
+        assert_eq!(
+            WitnessProgram::from_address(&cro.to_address()),
+            Err(Error::InvalidPayloadFamily)
+        );
+
+        assert_eq!(
+            WitnessProgram::new(WitnessVersion::V0, vec![0; 20], Network::Zilliqa),
+            Err(Error::InvalidPayloadFamily)
+        );
+        assert_eq!(
+            WitnessProgram::p2wpkh([0; 20], Network::QuantumResistantLedger),
+            Err(Error::InvalidPayloadFamily)
+        );
+        assert_eq!(
+            WitnessProgram::p2wsh_from_script(&[0x51], Network::ZenProtocol),
+            Err(Error::InvalidPayloadFamily)
+        );
+    }
+}
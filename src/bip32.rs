@@ -0,0 +1,772 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Account-level extended public keys
+//!
+//! Decodes BIP-0032 extended public keys carrying the version bytes of
+//! [SLIP-0132](https://github.com/satoshilabs/slips/blob/master/slip-0132.md)
+//! and derives the receive and change witness programs of BIP-0084 (P2WPKH)
+//! and BIP-0086 (P2TR) accounts on any network using them.
+//!
+//! Most networks have no prefixes of their own and their wallets export keys
+//! with the Bitcoin `xpub`/`zpub` (or `tpub`/`vpub`) bytes. Such keys cannot
+//! tell which network they belong to: `ExtendedPubKey::decode` refuses them
+//! for any network but Bitcoin, and `ExtendedPubKey::decode_shared` accepts
+//! them when the caller knows the network from elsewhere.
+
+use hmac::{Hmac, Mac};
+use secp256k1::{PublicKey, Scalar, Secp256k1};
+use sha2::Sha512;
+
+use std::str::FromStr;
+use std::{error, fmt};
+
+use constants::{self, Network, NETWORKS};
+use hash::{hash160, HashFunction};
+use taproot::TaprootOutput;
+use WitnessProgram;
+
+/// First index of hardened derivation
+pub const HARDENED: u32 = 0x8000_0000;
+
+/// Length of a serialized extended key, without checksum
+const SERIALIZED_LEN: usize = 78;
+
+/// Output type derived from an account key
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum AccountType {
+    /// BIP-0084 pay to witness public key hash
+    P2wpkh,
+    /// BIP-0086 single key pay to Taproot
+    P2tr,
+}
+
+/// Version bytes of mainnet `xpub` keys, which imply no output type
+pub const XPUB: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+/// Version bytes of mainnet `zpub` keys for P2WPKH accounts
+pub const ZPUB: [u8; 4] = [0x04, 0xb2, 0x47, 0x46];
+/// Version bytes of testnet `tpub` keys, which imply no output type
+pub const TPUB: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+/// Version bytes of testnet `vpub` keys for P2WPKH accounts
+pub const VPUB: [u8; 4] = [0x04, 0x5f, 0x1c, 0xf6];
+/// Version bytes of Litecoin `Ltub` keys, which imply no output type
+pub const LTUB: [u8; 4] = [0x01, 0x9d, 0xa4, 0x62];
+/// Version bytes of Litecoin testnet `ttub` keys, which imply no output type
+pub const TTUB: [u8; 4] = [0x04, 0x36, 0xf6, 0xe1];
+
+/// Returns the extended public key version bytes registered for a network
+///
+/// Each entry lists the output type the version bytes imply, if any. Only
+/// Bitcoin, its test networks, Litecoin and Groestlcoin have prefixes of
+/// their own; Groestlcoin reuses the Bitcoin bytes under its own base58
+/// checksum hash. Bitcoin's test networks all share `tpub` and `vpub`.
+pub fn version_bytes(network: &Network) -> Vec<([u8; 4], Option<AccountType>)> {
+    match *network {
+        Network::Litecoin => vec![(LTUB, None)],
+        Network::LitecoinTestnet => vec![(TTUB, None)],
+        Network::Testnet | Network::Signet | Network::Regtest | Network::GroestlcoinTestnet => {
+            vec![(TPUB, None), (VPUB, Some(AccountType::P2wpkh))]
+        }
+        Network::Bitcoin | Network::Groestlcoin => {
+            vec![(XPUB, None), (ZPUB, Some(AccountType::P2wpkh))]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the Bitcoin version bytes that wallets of a network borrow
+///
+/// Keys serialized with these bytes carry nothing that tells the network
+/// apart from Bitcoin, so `ExtendedPubKey::decode` rejects them with
+/// `Error::AmbiguousNetwork` and only `ExtendedPubKey::decode_shared`
+/// accepts them. Networks whose addresses are not witness programs have no
+/// entries.
+pub fn shared_version_bytes(network: &Network) -> Vec<([u8; 4], Option<AccountType>)> {
+    match *network {
+        Network::LitecoinTestnet
+        | Network::BellcoinTestnet
+        | Network::BitZenyTestnet
+        | Network::CranePayTestnet
+        | Network::DigiByteTestnet
+        | Network::FujiCoinTestnet
+        | Network::MonacoinTestnet
+        | Network::MonacoinRegtest
+        | Network::MyriadTestnet
+        | Network::NamecoinTestnet
+        | Network::PeercoinTestnet
+        | Network::PKTTestnet
+        | Network::RavencoinTestnet
+        | Network::SusucoinTestnet
+        | Network::UniteTestnet
+        | Network::VertcoinTestnet
+        | Network::ViacoinTestnet
+        | Network::VIPSTARCOINTestnet => {
+            vec![(TPUB, None), (VPUB, Some(AccountType::P2wpkh))]
+        }
+        Network::Litecoin
+        | Network::Bellcoin
+        | Network::BitZeny
+        | Network::CranePay
+        | Network::DigiByte
+        | Network::FujiCoin
+        | Network::Monacoin
+        | Network::Myriad
+        | Network::Namecoin
+        | Network::Peercoin
+        | Network::PKT
+        | Network::Ravencoin
+        | Network::Susucoin
+        | Network::Unite
+        | Network::Vertcoin
+        | Network::Viacoin
+        | Network::VIPSTARCOIN => vec![(XPUB, None), (ZPUB, Some(AccountType::P2wpkh))],
+        Network::Bitcoin
+        | Network::Testnet
+        | Network::Signet
+        | Network::Regtest
+        | Network::Groestlcoin
+        | Network::GroestlcoinTestnet
+        | Network::CryptoComChain
+        | Network::CryptoComChainTestnet
+        | Network::Handshake
+        | Network::HandshakeTestnet
+        | Network::QuantumResistantLedger
+        | Network::QuantumResistantLedgerTestnet
+        | Network::ZenProtocol
+        | Network::ZenProtocolTestnet
+        | Network::Zilliqa
+        | Network::ZilliqaTestnet => Vec::new(),
+    }
+}
+
+/// A BIP-0032 extended public key
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct ExtendedPubKey {
+    /// Network the key is used on
+    network: Network,
+    /// Version bytes the key is serialized with
+    version: [u8; 4],
+    /// Output type implied by the version bytes
+    account_type: Option<AccountType>,
+    /// Number of derivations from the master key
+    depth: u8,
+    /// First four bytes of the parent key's HASH160
+    parent_fingerprint: [u8; 4],
+    /// Index of this key in its parent
+    child_number: u32,
+    /// Chain code
+    chain_code: [u8; 32],
+    /// Compressed public key
+    public_key: [u8; 33],
+}
+
+impl ExtendedPubKey {
+    /// Decodes an extended public key for the given network
+    ///
+    /// The version bytes must be listed for `network` in `version_bytes`, and
+    /// the checksum must use the network's base58 checksum hash. Keys using
+    /// the Bitcoin prefixes that other networks borrow fail with
+    /// `Error::AmbiguousNetwork`, as nothing in them names the network.
+    pub fn decode(s: &str, network: Network) -> Result<ExtendedPubKey, Error> {
+        ExtendedPubKey::decode_with(s, network, false)
+    }
+
+    /// Decodes an extended public key the caller asserts is for `network`
+    ///
+    /// Also accepts the version bytes listed in `shared_version_bytes`, so a
+    /// `zpub` decodes for Monacoin as well as for Bitcoin. The key itself
+    /// cannot confirm the network; it must come from the context the key
+    /// was found in.
+    pub fn decode_shared(s: &str, network: Network) -> Result<ExtendedPubKey, Error> {
+        ExtendedPubKey::decode_with(s, network, true)
+    }
+
+    fn decode_with(s: &str, network: Network, shared: bool) -> Result<ExtendedPubKey, Error> {
+        let versions = version_bytes(&network);
+        let shared_versions = shared_version_bytes(&network);
+        if versions.is_empty() && shared_versions.is_empty() {
+            return Err(Error::UnsupportedNetwork);
+        }
+        let data = decode_check(s, checksum_hash(&network)?)?;
+        if data.len() != SERIALIZED_LEN {
+            return Err(Error::InvalidLength);
+        }
+        let mut version = [0u8; 4];
+        version.copy_from_slice(&data[0..4]);
+        let account_type = match versions.iter().find(|&&(v, _)| v == version) {
+            Some(&(_, account_type)) => account_type,
+            None => match shared_versions.iter().find(|&&(v, _)| v == version) {
+                Some(&(_, account_type)) if shared => account_type,
+                Some(_) => return Err(Error::AmbiguousNetwork),
+                None => {
+                    return Err(
+                        if NETWORKS.iter().any(|n| {
+                            version_bytes(n)
+                                .iter()
+                                .chain(shared_version_bytes(n).iter())
+                                .any(|&(v, _)| v == version)
+                        }) {
+                            Error::NetworkMismatch
+                        } else {
+                            Error::UnknownVersion(version)
+                        },
+                    )
+                }
+            },
+        };
+        let mut parent_fingerprint = [0u8; 4];
+        parent_fingerprint.copy_from_slice(&data[5..9]);
+        let mut child_number = [0u8; 4];
+        child_number.copy_from_slice(&data[9..13]);
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&data[13..45]);
+        let mut public_key = [0u8; 33];
+        public_key.copy_from_slice(&data[45..78]);
+        PublicKey::from_slice(&public_key).map_err(|_| Error::InvalidPublicKey)?;
+        Ok(ExtendedPubKey {
+            network,
+            version,
+            account_type,
+            depth: data[4],
+            parent_fingerprint,
+            child_number: u32::from_be_bytes(child_number),
+            chain_code,
+            public_key,
+        })
+    }
+
+    /// Lists the networks an extended public key can be decoded for
+    ///
+    /// Only registered version bytes count, so a `zpub` gives Bitcoin alone
+    /// even though `decode_shared` accepts it for many other networks, and a
+    /// `tpub` gives all of Bitcoin's test networks. The result is empty when
+    /// neither the version bytes nor the checksum match any network.
+    pub fn detect_networks(s: &str) -> Vec<Network> {
+        NETWORKS
+            .iter()
+            .filter(|n| ExtendedPubKey::decode(s, **n).is_ok())
+            .cloned()
+            .collect()
+    }
+
+    /// Serializes the key with its version bytes and the network's checksum
+    pub fn encode(&self) -> String {
+        let mut data = Vec::with_capacity(SERIALIZED_LEN);
+        data.extend_from_slice(&self.version);
+        data.push(self.depth);
+        data.extend_from_slice(&self.parent_fingerprint);
+        data.extend_from_slice(&self.child_number.to_be_bytes());
+        data.extend_from_slice(&self.chain_code);
+        data.extend_from_slice(&self.public_key);
+        let checksum = checksum_hash(&self.network).expect("decoded keys have a checksum hash");
+        encode_check(&data, checksum)
+    }
+
+    /// Derives a non-hardened child key
+    pub fn derive_child(&self, index: u32) -> Result<ExtendedPubKey, Error> {
+        if index >= HARDENED {
+            return Err(Error::HardenedDerivation);
+        }
+        let mut mac =
+            Hmac::<Sha512>::new_from_slice(&self.chain_code).expect("HMAC accepts any key size");
+        mac.update(&self.public_key);
+        mac.update(&index.to_be_bytes());
+        let i = mac.finalize().into_bytes();
+
+        let mut tweak = [0u8; 32];
+        tweak.copy_from_slice(&i[..32]);
+        let tweak = Scalar::from_be_bytes(tweak).map_err(|_| Error::InvalidChild)?;
+        let secp = Secp256k1::verification_only();
+        let child = PublicKey::from_slice(&self.public_key)
+            .map_err(|_| Error::InvalidPublicKey)?
+            .add_exp_tweak(&secp, &tweak)
+            .map_err(|_| Error::InvalidChild)?;
+
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&i[32..]);
+        let mut parent_fingerprint = [0u8; 4];
+        parent_fingerprint.copy_from_slice(&hash160(&self.public_key)[..4]);
+        Ok(ExtendedPubKey {
+            network: self.network,
+            version: self.version,
+            account_type: self.account_type,
+            depth: self.depth.checked_add(1).ok_or(Error::InvalidChild)?,
+            parent_fingerprint,
+            child_number: index,
+            chain_code,
+            public_key: child.serialize(),
+        })
+    }
+
+    /// Derives a key along a path of non-hardened indices
+    pub fn derive_path(&self, path: &[u32]) -> Result<ExtendedPubKey, Error> {
+        let mut key = self.clone();
+        for index in path {
+            key = key.derive_child(*index)?;
+        }
+        Ok(key)
+    }
+
+    /// Witness program paying to this key with the given output type
+    ///
+    /// Fails with `Error::AccountTypeMismatch` when the version bytes imply
+    /// a different output type.
+    pub fn witness_program(&self, account_type: AccountType) -> Result<WitnessProgram, Error> {
+        self.check_account_type(account_type)?;
+        match account_type {
+            AccountType::P2wpkh => Ok(WitnessProgram::p2wpkh_from_pubkey(
+                &self.public_key,
+                self.network,
+            )?),
+            AccountType::P2tr => {
+                let mut internal_key = [0u8; 32];
+                internal_key.copy_from_slice(&self.public_key[1..]);
+                let output = TaprootOutput::new(internal_key, None, self.network)?;
+                Ok(output.witness_program().clone())
+            }
+        }
+    }
+
+    /// Derives a run of witness programs from an account key
+    ///
+    /// Programs are derived at `chain/index` for `count` consecutive indices
+    /// starting at `start`, where chain 0 holds receive addresses and chain 1
+    /// change addresses. Fails with `Error::IndexOverflow` when the run goes
+    /// past the last index.
+    pub fn account_programs(
+        &self,
+        account_type: AccountType,
+        chain: u32,
+        start: u32,
+        count: u32,
+    ) -> Result<Vec<WitnessProgram>, Error> {
+        self.check_account_type(account_type)?;
+        let chain_key = self.derive_child(chain)?;
+        let end = start.checked_add(count).ok_or(Error::IndexOverflow)?;
+        (start..end)
+            .map(|index| chain_key.derive_child(index)?.witness_program(account_type))
+            .collect()
+    }
+
+    /// Derives a run of receive programs, at `0/index`
+    pub fn receive_programs(
+        &self,
+        account_type: AccountType,
+        start: u32,
+        count: u32,
+    ) -> Result<Vec<WitnessProgram>, Error> {
+        self.account_programs(account_type, 0, start, count)
+    }
+
+    /// Derives a run of change programs, at `1/index`
+    pub fn change_programs(
+        &self,
+        account_type: AccountType,
+        start: u32,
+        count: u32,
+    ) -> Result<Vec<WitnessProgram>, Error> {
+        self.account_programs(account_type, 1, start, count)
+    }
+
+    fn check_account_type(&self, account_type: AccountType) -> Result<(), Error> {
+        match self.account_type {
+            Some(implied) if implied != account_type => Err(Error::AccountTypeMismatch),
+            _ => Ok(()),
+        }
+    }
+
+    /// Network the key is used on
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Version bytes the key is serialized with
+    pub fn version(&self) -> [u8; 4] {
+        self.version
+    }
+
+    /// Output type implied by the version bytes, if any
+    pub fn account_type(&self) -> Option<AccountType> {
+        self.account_type
+    }
+
+    /// Number of derivations from the master key
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    /// First four bytes of the parent key's HASH160
+    pub fn parent_fingerprint(&self) -> [u8; 4] {
+        self.parent_fingerprint
+    }
+
+    /// Index of this key in its parent
+    pub fn child_number(&self) -> u32 {
+        self.child_number
+    }
+
+    /// Chain code
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    /// Compressed public key
+    pub fn public_key(&self) -> &[u8; 33] {
+        &self.public_key
+    }
+}
+
+impl fmt::Display for ExtendedPubKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.encode())
+    }
+}
+
+/// Parses a path of non-hardened indices such as `0/5` or `m/1/2`
+pub fn parse_path(s: &str) -> Result<Vec<u32>, Error> {
+    let s = s.strip_prefix("m/").unwrap_or(s);
+    if s.is_empty() || s == "m" {
+        return Ok(Vec::new());
+    }
+    s.split('/')
+        .map(|step| {
+            if step.ends_with('\'') || step.ends_with('h') || step.ends_with('H') {
+                return Err(Error::HardenedDerivation);
+            }
+            match u32::from_str(step) {
+                Ok(index) if index < HARDENED => Ok(index),
+                Ok(_) => Err(Error::HardenedDerivation),
+                Err(_) => Err(Error::InvalidPath),
+            }
+        })
+        .collect()
+}
+
+fn checksum_hash(network: &Network) -> Result<HashFunction, Error> {
+    constants::hash_functions(network)
+        .base58_checksum
+        .ok_or(Error::UnsupportedNetwork)
+}
+
+/// Decodes base58 and verifies the trailing four-byte checksum
+pub(crate) fn decode_check(s: &str, checksum: HashFunction) -> Result<Vec<u8>, Error> {
+    let mut data = bs58::decode(s)
+        .into_vec()
+        .map_err(|_| Error::InvalidBase58)?;
+    if data.len() < 4 {
+        return Err(Error::InvalidLength);
+    }
+    let payload_len = data.len() - 4;
+    if checksum.digest(&data[..payload_len])[..4] != data[payload_len..] {
+        return Err(Error::InvalidChecksum);
+    }
+    data.truncate(payload_len);
+    Ok(data)
+}
+
+/// Encodes base58 with a trailing four-byte checksum
+pub(crate) fn encode_check(data: &[u8], checksum: HashFunction) -> String {
+    let mut data = data.to_vec();
+    let hash = checksum.digest(&data);
+    data.extend_from_slice(&hash[..4]);
+    bs58::encode(data).into_string()
+}
+
+/// Error types for extended public keys
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// Error building a witness program
+    WitnessProgram(::Error),
+    /// The string is not valid base58
+    InvalidBase58,
+    /// The base58 checksum does not match
+    InvalidChecksum,
+    /// The decoded key has the wrong length
+    InvalidLength,
+    /// The version bytes are not used by any network
+    UnknownVersion([u8; 4]),
+    /// The version bytes belong to a different network
+    NetworkMismatch,
+    /// The version bytes are borrowed from Bitcoin and do not name the network
+    AmbiguousNetwork,
+    /// The network has no extended public keys
+    UnsupportedNetwork,
+    /// The version bytes imply a different output type than requested
+    AccountTypeMismatch,
+    /// The serialized public key is not a valid secp256k1 point
+    InvalidPublicKey,
+    /// Public keys cannot derive hardened children
+    HardenedDerivation,
+    /// A run of child indices goes past the last index
+    IndexOverflow,
+    /// A derivation path step is not a number
+    InvalidPath,
+    /// The derived child key is invalid; the next index should be used
+    InvalidChild,
+}
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::WitnessProgram(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::WitnessProgram(ref e) => write!(f, "{}", e),
+            Error::InvalidBase58 => write!(f, "invalid base58"),
+            Error::InvalidChecksum => write!(f, "invalid base58 checksum"),
+            Error::InvalidLength => write!(f, "invalid extended key length"),
+            Error::UnknownVersion(ref v) => write!(
+                f,
+                "unknown version bytes {:02x}{:02x}{:02x}{:02x}",
+                v[0], v[1], v[2], v[3]
+            ),
+            Error::NetworkMismatch => write!(f, "version bytes belong to another network"),
+            Error::AmbiguousNetwork => write!(f, "version bytes shared with bitcoin"),
+            Error::UnsupportedNetwork => write!(f, "network has no extended public keys"),
+            Error::AccountTypeMismatch => write!(f, "version bytes imply another output type"),
+            Error::InvalidPublicKey => write!(f, "invalid public key"),
+            Error::HardenedDerivation => write!(f, "hardened derivation from public key"),
+            Error::IndexOverflow => write!(f, "child index overflow"),
+            Error::InvalidPath => write!(f, "invalid derivation path"),
+            Error::InvalidChild => write!(f, "invalid child key"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::WitnessProgram(_) => "witness program error",
+            Error::InvalidBase58 => "invalid base58",
+            Error::InvalidChecksum => "invalid base58 checksum",
+            Error::InvalidLength => "invalid extended key length",
+            Error::UnknownVersion(_) => "unknown version bytes",
+            Error::NetworkMismatch => "version bytes belong to another network",
+            Error::AmbiguousNetwork => "version bytes shared with bitcoin",
+            Error::UnsupportedNetwork => "network has no extended public keys",
+            Error::AccountTypeMismatch => "version bytes imply another output type",
+            Error::InvalidPublicKey => "invalid public key",
+            Error::HardenedDerivation => "hardened derivation from public key",
+            Error::IndexOverflow => "child index overflow",
+            Error::InvalidPath => "invalid derivation path",
+            Error::InvalidChild => "invalid child key",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::WitnessProgram(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BIP84_ZPUB: &str = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+
+    #[test]
+    fn bip84_account() {
+        let key = ExtendedPubKey::decode(BIP84_ZPUB, Network::Bitcoin).unwrap();
+        assert_eq!(key.account_type(), Some(AccountType::P2wpkh));
+        assert_eq!(key.to_string(), BIP84_ZPUB);
+
+        let receive = key.receive_programs(AccountType::P2wpkh, 0, 2).unwrap();
+        assert_eq!(
+            receive[0].to_address(),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+        assert_eq!(
+            receive[1].to_address(),
+            "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g"
+        );
+        let change = key.change_programs(AccountType::P2wpkh, 0, 1).unwrap();
+        assert_eq!(
+            change[0].to_address(),
+            "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el"
+        );
+
+        // The same key on Monacoin gives the same programs with another HRP
+        assert_eq!(
+            ExtendedPubKey::decode(BIP84_ZPUB, Network::Monacoin),
+            Err(Error::AmbiguousNetwork)
+        );
+        let key = ExtendedPubKey::decode_shared(BIP84_ZPUB, Network::Monacoin).unwrap();
+        let receive = key.receive_programs(AccountType::P2wpkh, 0, 1).unwrap();
+        assert_eq!(receive[0].network(), Network::Monacoin);
+        assert!(receive[0]
+            .to_address()
+            .starts_with("mona1qcr8te4kr609gcawutmrza0j4xv80jy8z"));
+
+        assert_eq!(
+            key.receive_programs(AccountType::P2tr, 0, 1),
+            Err(Error::AccountTypeMismatch)
+        );
+        assert_eq!(
+            key.receive_programs(AccountType::P2wpkh, u32::MAX, 2),
+            Err(Error::IndexOverflow)
+        );
+    }
+
+    #[test]
+    fn bip86_account() {
+        // BIP-0086 test vector, account m/86'/0'/0'
+        let key = ExtendedPubKey::decode(
+            "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ",
+            Network::Bitcoin,
+        )
+        .unwrap();
+        let receive = key.receive_programs(AccountType::P2tr, 0, 2).unwrap();
+        assert_eq!(
+            receive[0].to_address(),
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        );
+        assert_eq!(
+            receive[1].to_address(),
+            "bc1p4qhjn9zdvkux4e44uhx8tc55attvtyu358kutcqkudyccelu0was9fqzwh"
+        );
+        let change = key.change_programs(AccountType::P2tr, 0, 1).unwrap();
+        assert_eq!(
+            change[0].to_address(),
+            "bc1p3qkhfews2uk44qtvauqyr2ttdsw7svhkl9nkm9s9c3x4ax5h60wqwruhk7"
+        );
+    }
+
+    #[test]
+    fn public_derivation() {
+        // BIP-0032 test vector 1, from m/0H/1/2H
+        let key = ExtendedPubKey::decode(
+            "xpub6D4BDPcP2GT577Vvch3R8wDkScZWzQzMMUm3PWbmWvVJrZwQY4VUNgqFJPMM3No2dFDFGTsxxpG5uJh7n7epu4trkrX7x7DogT5Uv6fcLW5",
+            Network::Bitcoin,
+        )
+        .unwrap();
+        let child = key.derive_child(2).unwrap();
+        assert_eq!(
+            child.to_string(),
+            "xpub6FHa3pjLCk84BayeJxFW2SP4XRrFd1JYnxeLeU8EqN3vDfZmbqBqaGJAyiLjTAwm6ZLRQUMv1ZACTj37sR62cfN7fe5JnJ7dh8zL4fiyLHV"
+        );
+        let grandchild = key
+            .derive_path(&parse_path("2/1000000000").unwrap())
+            .unwrap();
+        assert_eq!(
+            grandchild.to_string(),
+            "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy"
+        );
+        assert_eq!(grandchild.depth(), 5);
+        assert_eq!(key.derive_child(HARDENED), Err(Error::HardenedDerivation));
+
+        // Generic keys can derive Taproot outputs
+        let program = child.witness_program(AccountType::P2tr).unwrap();
+        let mut internal_key = [0u8; 32];
+        internal_key.copy_from_slice(&child.public_key()[1..]);
+        assert_eq!(
+            &program,
+            TaprootOutput::new(internal_key, None, Network::Bitcoin)
+                .unwrap()
+                .witness_program()
+        );
+    }
+
+    #[test]
+    fn network_detection() {
+        // Networks borrowing the Bitcoin prefixes are not reported
+        assert_eq!(
+            ExtendedPubKey::detect_networks(BIP84_ZPUB),
+            vec![Network::Bitcoin]
+        );
+        assert_eq!(
+            ExtendedPubKey::decode(BIP84_ZPUB, Network::Litecoin),
+            Err(Error::AmbiguousNetwork)
+        );
+        assert!(ExtendedPubKey::decode_shared(BIP84_ZPUB, Network::Litecoin).is_ok());
+
+        assert_eq!(
+            ExtendedPubKey::decode(BIP84_ZPUB, Network::Testnet),
+            Err(Error::NetworkMismatch)
+        );
+        assert_eq!(
+            ExtendedPubKey::decode_shared(BIP84_ZPUB, Network::MonacoinTestnet),
+            Err(Error::NetworkMismatch)
+        );
+        assert_eq!(
+            ExtendedPubKey::decode(BIP84_ZPUB, Network::Groestlcoin),
+            Err(Error::InvalidChecksum)
+        );
+        assert_eq!(
+            ExtendedPubKey::decode(BIP84_ZPUB, Network::Zilliqa),
+            Err(Error::UnsupportedNetwork)
+        );
+
+        // Re-encoding a key for Groestlcoin only changes the checksum
+        let key = ExtendedPubKey::decode(BIP84_ZPUB, Network::Bitcoin).unwrap();
+        let data = decode_check(BIP84_ZPUB, HashFunction::DoubleSha256).unwrap();
+        let grs = encode_check(&data, HashFunction::DoubleGroestl512);
+        assert_eq!(
+            ExtendedPubKey::detect_networks(&grs),
+            vec![Network::Groestlcoin]
+        );
+        let grs_key = ExtendedPubKey::decode(&grs, Network::Groestlcoin).unwrap();
+        assert_eq!(grs_key.public_key(), key.public_key());
+        assert_eq!(grs_key.to_string(), grs);
+
+        // Litecoin's own prefix names the network
+        let mut ltub = data.clone();
+        ltub[0..4].copy_from_slice(&LTUB);
+        let ltub = encode_check(&ltub, HashFunction::DoubleSha256);
+        assert_eq!(
+            ExtendedPubKey::detect_networks(&ltub),
+            vec![Network::Litecoin]
+        );
+        assert_eq!(
+            ExtendedPubKey::decode(&ltub, Network::Bitcoin),
+            Err(Error::NetworkMismatch)
+        );
+
+        let mut unknown = data.clone();
+        unknown[0] = 0x07;
+        assert_eq!(
+            ExtendedPubKey::decode(
+                &encode_check(&unknown, HashFunction::DoubleSha256),
+                Network::Bitcoin
+            ),
+            Err(Error::UnknownVersion([0x07, 0xb2, 0x47, 0x46]))
+        );
+        assert_eq!(
+            ExtendedPubKey::decode("zpub0", Network::Bitcoin),
+            Err(Error::InvalidBase58)
+        );
+    }
+
+    #[test]
+    fn paths() {
+        assert_eq!(parse_path("0/5"), Ok(vec![0, 5]));
+        assert_eq!(parse_path("m/1/2"), Ok(vec![1, 2]));
+        assert_eq!(parse_path("m"), Ok(vec![]));
+        assert_eq!(parse_path("0'/1"), Err(Error::HardenedDerivation));
+        assert_eq!(parse_path("0h"), Err(Error::HardenedDerivation));
+        assert_eq!(parse_path("2147483648"), Err(Error::HardenedDerivation));
+        assert_eq!(parse_path("a/1"), Err(Error::InvalidPath));
+    }
+}
@@ -0,0 +1,467 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Offline scanning of `blk*.dat` block files
+//!
+//! Block files hold a sequence of blocks, each framed by the network magic
+//! and a little-endian length. Files written by Bitcoin Core 28 and later
+//! may be XOR-obfuscated with the 8-byte key stored in `xor.dat` in the same
+//! directory.
+//!
+//! Merge-mined networks (Namecoin and Myriad) may carry an auxiliary proof
+//! of work after the header, and Ravencoin uses a longer header once KAWPOW
+//! is active; both are skipped. Data after the transactions, such as a
+//! Peercoin block signature or a Litecoin MWEB block, is ignored.
+
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::{error, fmt};
+
+use constants::{self, Network};
+use encode::Reader;
+use transaction::{self, Transaction, Txid};
+use WitnessProgram;
+
+/// Largest block accepted, in bytes
+pub const MAX_BLOCK_SIZE: u32 = 0x0200_0000;
+
+/// Length of a Bitcoin block header
+const HEADER_SIZE: usize = 80;
+
+/// Length of a Ravencoin KAWPOW block header
+const KAWPOW_HEADER_SIZE: usize = 120;
+
+/// Version bit marking a merge-mined block with an auxiliary proof of work
+const AUXPOW_VERSION_FLAG: u32 = 1 << 8;
+
+/// Parses the transactions of a serialized block
+pub fn block_transactions(block: &[u8], network: Network) -> Result<Vec<Transaction>, Error> {
+    let mut reader = Reader::new(block);
+    read_header(&mut reader, network)?;
+    let count = reader.read_compact_size().ok_or(Error::UnexpectedEnd)?;
+    if count > reader.remaining() as u64 {
+        return Err(Error::UnexpectedEnd);
+    }
+    let mut transactions = Vec::new();
+    for _ in 0..count {
+        transactions.push(Transaction::read(&mut reader, network, false)?);
+    }
+    Ok(transactions)
+}
+
+/// Skips the block header, including any auxiliary proof of work
+fn read_header(reader: &mut Reader, network: Network) -> Result<(), Error> {
+    let header = reader.read_bytes(HEADER_SIZE).ok_or(Error::UnexpectedEnd)?;
+    let version = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let time = u32::from_le_bytes([header[68], header[69], header[70], header[71]]);
+    match network {
+        Network::Ravencoin | Network::RavencoinTestnet if time >= kawpow_activation(network) => {
+            reader
+                .read_bytes(KAWPOW_HEADER_SIZE - HEADER_SIZE)
+                .ok_or(Error::UnexpectedEnd)?;
+        }
+        Network::Namecoin | Network::NamecoinTestnet | Network::Myriad | Network::MyriadTestnet
+            if version & AUXPOW_VERSION_FLAG != 0 =>
+        {
+            // Parent coinbase, parent block hash, the two merkle branches
+            // and the parent header
+            Transaction::read(reader, Network::Bitcoin, false)?;
+            reader.read_hash().ok_or(Error::UnexpectedEnd)?;
+            for _ in 0..2 {
+                let len = reader.read_compact_size().ok_or(Error::UnexpectedEnd)?;
+                if len > (reader.remaining() / 32) as u64 {
+                    return Err(Error::UnexpectedEnd);
+                }
+                reader
+                    .read_bytes(len as usize * 32 + 4)
+                    .ok_or(Error::UnexpectedEnd)?;
+            }
+            reader.read_bytes(HEADER_SIZE).ok_or(Error::UnexpectedEnd)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Block time from which Ravencoin headers use the KAWPOW layout
+fn kawpow_activation(network: Network) -> u32 {
+    match network {
+        Network::RavencoinTestnet => 1_585_159_200,
+        _ => 1_588_788_000,
+    }
+}
+
+/// Streams the witness outputs of every transaction in a block file
+///
+/// Yields `(txid, vout, program)` for each output that locks to a witness
+/// program, in file order. Iteration stops at the end of the file, at the
+/// zero padding Bitcoin Core leaves after the last block, or after the first
+/// error.
+pub struct BlockFileScanner<R> {
+    reader: R,
+    network: Network,
+    magic: [u8; 4],
+    xor_key: [u8; 8],
+    offset: u64,
+    pending: VecDeque<(Txid, u32, WitnessProgram)>,
+    done: bool,
+}
+
+impl<R: Read> BlockFileScanner<R> {
+    /// Scans block files of `network`, framed by its known magic
+    pub fn new(reader: R, network: Network) -> Result<BlockFileScanner<R>, Error> {
+        let magic = constants::magic(&network).ok_or(Error::UnsupportedNetwork(network))?;
+        Ok(BlockFileScanner::with_magic(reader, network, magic))
+    }
+
+    /// Scans block files framed by an explicit magic
+    pub fn with_magic(reader: R, network: Network, magic: [u8; 4]) -> BlockFileScanner<R> {
+        BlockFileScanner {
+            reader,
+            network,
+            magic,
+            xor_key: [0; 8],
+            offset: 0,
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// Undoes the obfuscation of files written with the given `xor.dat` key
+    ///
+    /// Must be set before the first block is read, since the key is applied
+    /// by offset from the start of the file.
+    pub fn with_xor_key(mut self, key: [u8; 8]) -> BlockFileScanner<R> {
+        self.xor_key = key;
+        self
+    }
+
+    /// Fills `buf`, returning how many bytes were read before the end of
+    /// the file
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(Error::Io(e.kind())),
+            }
+        }
+        for (i, b) in buf[..filled].iter_mut().enumerate() {
+            *b ^= self.xor_key[((self.offset + i as u64) % 8) as usize];
+        }
+        self.offset += filled as u64;
+        Ok(filled)
+    }
+
+    /// Reads the next framed block, or `None` at the end of the data
+    fn next_block(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let mut magic = [0u8; 4];
+        match self.read(&mut magic)? {
+            0 => return Ok(None),
+            4 => {}
+            _ => return Err(Error::UnexpectedEnd),
+        }
+        if magic == [0; 4] {
+            return Ok(None);
+        }
+        if magic != self.magic {
+            return Err(Error::InvalidMagic(magic));
+        }
+        let mut len = [0u8; 4];
+        if self.read(&mut len)? != 4 {
+            return Err(Error::UnexpectedEnd);
+        }
+        let len = u32::from_le_bytes(len);
+        if len > MAX_BLOCK_SIZE {
+            return Err(Error::OversizedBlock(len));
+        }
+        let mut block = vec![0u8; len as usize];
+        if self.read(&mut block)? != block.len() {
+            return Err(Error::UnexpectedEnd);
+        }
+        Ok(Some(block))
+    }
+}
+
+impl<R: Read> Iterator for BlockFileScanner<R> {
+    type Item = Result<(Txid, u32, WitnessProgram), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(output) = self.pending.pop_front() {
+                return Some(Ok(output));
+            }
+            if self.done {
+                return None;
+            }
+            let block = match self.next_block() {
+                Ok(Some(block)) => block,
+                Ok(None) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+            match block_transactions(&block, self.network) {
+                Ok(transactions) => {
+                    for tx in transactions {
+                        let txid = tx.txid();
+                        for (vout, program) in tx.witness_outputs() {
+                            self.pending.push_back((txid, vout, program));
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Error types for block file scanning
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// Reading the file failed
+    Io(io::ErrorKind),
+    /// A block is not preceded by the network magic
+    InvalidMagic([u8; 4]),
+    /// A block length exceeds [`MAX_BLOCK_SIZE`]
+    OversizedBlock(u32),
+    /// The file or a block header ends early
+    UnexpectedEnd,
+    /// A transaction in a block could not be parsed
+    Transaction(transaction::Error),
+    /// The magic of the network is not known
+    UnsupportedNetwork(Network),
+}
+
+impl From<transaction::Error> for Error {
+    fn from(e: transaction::Error) -> Error {
+        Error::Transaction(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(kind) => write!(f, "I/O error: {:?}", kind),
+            Error::InvalidMagic(magic) => write!(f, "invalid block magic: {:02x?}", magic),
+            Error::OversizedBlock(len) => write!(f, "block too large: {} bytes", len),
+            Error::UnexpectedEnd => write!(f, "unexpected end of block data"),
+            Error::Transaction(ref e) => write!(f, "{}", e),
+            Error::UnsupportedNetwork(network) => {
+                write!(f, "no block file magic for {:?}", network)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(_) => "I/O error",
+            Error::InvalidMagic(_) => "invalid block magic",
+            Error::OversizedBlock(_) => "block too large",
+            Error::UnexpectedEnd => "unexpected end of block data",
+            Error::Transaction(_) => "transaction error",
+            Error::UnsupportedNetwork(_) => "no block file magic for network",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Transaction(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encode::write_var_bytes;
+    use hex;
+
+    const P2WPKH_SCRIPT: &str = "0014751e76e8199196d454941c45d1b3a323f1433bd6";
+    const P2TR_SCRIPT: &str =
+        "5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c";
+
+    /// Serializes a transaction paying to each script in turn
+    fn transaction(scripts: &[&str]) -> Vec<u8> {
+        let mut tx = 2u32.to_le_bytes().to_vec();
+        tx.push(1);
+        tx.extend_from_slice(&[0x22; 32]);
+        tx.extend_from_slice(&[0, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+        tx.push(scripts.len() as u8);
+        for script in scripts {
+            tx.extend_from_slice(&1000u64.to_le_bytes());
+            write_var_bytes(&mut tx, &hex::decode(script).unwrap());
+        }
+        tx.extend_from_slice(&[0, 0, 0, 0]);
+        tx
+    }
+
+    /// Serializes a block with the given version, time and transactions
+    fn block(version: u32, time: u32, auxpow: &[u8], transactions: &[&[u8]]) -> Vec<u8> {
+        let mut block = version.to_le_bytes().to_vec();
+        block.extend_from_slice(&[0u8; 64]);
+        block.extend_from_slice(&time.to_le_bytes());
+        block.extend_from_slice(&[0u8; 8]);
+        block.extend_from_slice(auxpow);
+        block.push(transactions.len() as u8);
+        for tx in transactions {
+            block.extend_from_slice(tx);
+        }
+        block
+    }
+
+    fn frame(magic: [u8; 4], blocks: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = Vec::new();
+        for block in blocks {
+            file.extend_from_slice(&magic);
+            file.extend_from_slice(&(block.len() as u32).to_le_bytes());
+            file.extend_from_slice(block);
+        }
+        file
+    }
+
+    #[test]
+    fn scan_blocks() {
+        let first = transaction(&[P2WPKH_SCRIPT, "6a00"]);
+        let second = transaction(&["51", P2TR_SCRIPT]);
+        let blocks = vec![
+            block(0x2000_0000, 0, &[], &[&first]),
+            block(0x2000_0000, 0, &[], &[&second]),
+        ];
+        let mut file = frame(constants::magic(&Network::Bitcoin).unwrap(), &blocks);
+        file.extend_from_slice(&[0u8; 16]);
+
+        let outputs: Vec<_> = BlockFileScanner::new(&file[..], Network::Bitcoin)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(outputs.len(), 2);
+        let first = Transaction::parse(&first, Network::Bitcoin).unwrap();
+        assert_eq!(outputs[0].0, first.txid());
+        assert_eq!(outputs[0].1, 0);
+        assert_eq!(
+            outputs[0].2.to_address(),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+        let second = Transaction::parse(&second, Network::Bitcoin).unwrap();
+        assert_eq!(outputs[1].0, second.txid());
+        assert_eq!(outputs[1].1, 1);
+    }
+
+    #[test]
+    fn xor_obfuscation() {
+        let key = [0x9a, 0x01, 0xff, 0x30, 0x00, 0x42, 0x17, 0xc3];
+        let blocks = vec![block(1, 0, &[], &[&transaction(&[P2WPKH_SCRIPT])])];
+        let mut file = frame(constants::magic(&Network::Litecoin).unwrap(), &blocks);
+        for (i, b) in file.iter_mut().enumerate() {
+            *b ^= key[i % 8];
+        }
+
+        let mut plain = BlockFileScanner::new(&file[..], Network::Litecoin).unwrap();
+        assert!(matches!(plain.next(), Some(Err(Error::InvalidMagic(_)))));
+        assert!(plain.next().is_none());
+
+        let scanner = BlockFileScanner::new(&file[..], Network::Litecoin)
+            .unwrap()
+            .with_xor_key(key);
+        let outputs: Vec<_> = scanner.collect::<Result<_, _>>().unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].2.network(), Network::Litecoin);
+    }
+
+    #[test]
+    fn altcoin_headers() {
+        let tx = transaction(&[P2WPKH_SCRIPT]);
+
+        let raven = block(0x2000_0000, 1_600_000_000, &[0u8; 40], &[&tx]);
+        assert_eq!(
+            block_transactions(&raven, Network::Ravencoin)
+                .unwrap()
+                .len(),
+            1
+        );
+        let raven = block(0x2000_0000, 1_500_000_000, &[], &[&tx]);
+        assert_eq!(
+            block_transactions(&raven, Network::Ravencoin)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let mut auxpow = transaction(&["51"]);
+        auxpow.extend_from_slice(&[0x33; 32]);
+        auxpow.push(1);
+        auxpow.extend_from_slice(&[0x44; 36]);
+        auxpow.extend_from_slice(&[0, 0, 0, 0, 0]);
+        auxpow.extend_from_slice(&[0x55; 80]);
+        let namecoin = block(0x0001_0101, 0, &auxpow, &[&tx]);
+        let transactions = block_transactions(&namecoin, Network::Namecoin).unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].witness_outputs().len(), 1);
+        let namecoin = block(0x0001_0001, 0, &[], &[&tx]);
+        assert_eq!(
+            block_transactions(&namecoin, Network::Namecoin)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn invalid_files() {
+        let magic = constants::magic(&Network::Bitcoin).unwrap();
+        let blocks = vec![block(1, 0, &[], &[&transaction(&[P2WPKH_SCRIPT])])];
+        let file = frame(magic, &blocks);
+
+        let mut scanner = BlockFileScanner::new(&file[..file.len() - 1], Network::Bitcoin).unwrap();
+        assert_eq!(scanner.next(), Some(Err(Error::UnexpectedEnd)));
+
+        let mut oversized = magic.to_vec();
+        oversized.extend_from_slice(&(MAX_BLOCK_SIZE + 1).to_le_bytes());
+        let mut scanner = BlockFileScanner::new(&oversized[..], Network::Bitcoin).unwrap();
+        assert_eq!(
+            scanner.next(),
+            Some(Err(Error::OversizedBlock(MAX_BLOCK_SIZE + 1)))
+        );
+
+        let mut truncated = file.clone();
+        truncated[4] -= 1;
+        truncated.pop();
+        let mut scanner = BlockFileScanner::new(&truncated[..], Network::Bitcoin).unwrap();
+        assert_eq!(
+            scanner.next(),
+            Some(Err(Error::Transaction(transaction::Error::UnexpectedEnd)))
+        );
+
+        assert!(BlockFileScanner::new(&file[..], Network::Handshake).is_err());
+    }
+}
@@ -23,6 +23,8 @@
 //! The authoratative list of Human-readable parts for Bech32 addresses is
 //! maintained in [SLIP-0173](https://github.com/satoshilabs/slips/blob/master/slip-0173.md).
 
+use hash::HashFunction;
+
 /// The cryptocurrency to act on
 #[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
 pub enum Network {
@@ -130,6 +132,61 @@ pub enum Network {
     ZilliqaTestnet,
 }
 
+/// Every network, in declaration order
+pub const NETWORKS: [Network; 51] = [
+    Network::Bitcoin,
+    Network::Testnet,
+    Network::Signet,
+    Network::Regtest,
+    Network::Bellcoin,
+    Network::BellcoinTestnet,
+    Network::BitZeny,
+    Network::BitZenyTestnet,
+    Network::CranePay,
+    Network::CranePayTestnet,
+    Network::CryptoComChain,
+    Network::CryptoComChainTestnet,
+    Network::DigiByte,
+    Network::DigiByteTestnet,
+    Network::FujiCoin,
+    Network::FujiCoinTestnet,
+    Network::Groestlcoin,
+    Network::GroestlcoinTestnet,
+    Network::Handshake,
+    Network::HandshakeTestnet,
+    Network::Litecoin,
+    Network::LitecoinTestnet,
+    Network::Monacoin,
+    Network::MonacoinTestnet,
+    Network::MonacoinRegtest,
+    Network::Myriad,
+    Network::MyriadTestnet,
+    Network::Namecoin,
+    Network::NamecoinTestnet,
+    Network::Peercoin,
+    Network::PeercoinTestnet,
+    Network::PKT,
+    Network::PKTTestnet,
+    Network::QuantumResistantLedger,
+    Network::QuantumResistantLedgerTestnet,
+    Network::Ravencoin,
+    Network::RavencoinTestnet,
+    Network::Susucoin,
+    Network::SusucoinTestnet,
+    Network::Unite,
+    Network::UniteTestnet,
+    Network::Vertcoin,
+    Network::VertcoinTestnet,
+    Network::Viacoin,
+    Network::ViacoinTestnet,
+    Network::VIPSTARCOIN,
+    Network::VIPSTARCOINTestnet,
+    Network::ZenProtocol,
+    Network::ZenProtocolTestnet,
+    Network::Zilliqa,
+    Network::ZilliqaTestnet,
+];
+
 /// Returns the Human-readable part for the given network
 pub fn hrp(network: &Network) -> String {
     match *network {
@@ -244,3 +301,148 @@ pub fn classify(hrp: &str) -> Option<Network> {
         _ => None,
     }
 }
+
+/// Hash functions a network uses to turn keys and scripts into addresses
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct HashFunctions {
+    /// Hash of a public key, used for P2WPKH-style programs
+    pub key_hash: HashFunction,
+    /// Hash of a witness script, used for P2WSH-style programs
+    pub script_hash: HashFunction,
+    /// Hash truncated to form base58 checksums, if the network uses base58
+    pub base58_checksum: Option<HashFunction>,
+}
+
+/// Returns the hashing parameters for the given network
+///
+/// Networks without their own witness program rules keep the Bitcoin
+/// parameters.
+pub fn hash_functions(network: &Network) -> HashFunctions {
+    match *network {
+        Network::Groestlcoin | Network::GroestlcoinTestnet => HashFunctions {
+            key_hash: HashFunction::Hash160,
+            script_hash: HashFunction::Sha256,
+            base58_checksum: Some(HashFunction::DoubleGroestl512),
+        },
+        Network::Handshake | Network::HandshakeTestnet => HashFunctions {
+            key_hash: HashFunction::Blake2b160,
+            script_hash: HashFunction::Sha3_256,
+            base58_checksum: None,
+        },
+        _ => HashFunctions {
+            key_hash: HashFunction::Hash160,
+            script_hash: HashFunction::Sha256,
+            base58_checksum: Some(HashFunction::DoubleSha256),
+        },
+    }
+}
+
+/// Returns the message start bytes that frame blocks in the network's
+/// `blk*.dat` files
+///
+/// Returns `None` for networks whose magic is not known here, and for
+/// networks that do not store blocks in that format.
+pub fn magic(network: &Network) -> Option<[u8; 4]> {
+    match *network {
+        Network::Bitcoin => Some([0xf9, 0xbe, 0xb4, 0xd9]),
+        Network::Testnet => Some([0x0b, 0x11, 0x09, 0x07]),
+        Network::Signet => Some([0x0a, 0x03, 0xcf, 0x40]),
+        Network::Regtest => Some([0xfa, 0xbf, 0xb5, 0xda]),
+        Network::DigiByte => Some([0xfa, 0xc3, 0xb6, 0xda]),
+        Network::DigiByteTestnet => Some([0xfd, 0xc8, 0xbd, 0xdd]),
+        Network::Groestlcoin => Some([0xf9, 0xbe, 0xb4, 0xd4]),
+        Network::GroestlcoinTestnet => Some([0x0b, 0x11, 0x09, 0x07]),
+        Network::Litecoin => Some([0xfb, 0xc0, 0xb6, 0xdb]),
+        Network::LitecoinTestnet => Some([0xfd, 0xd2, 0xc8, 0xf1]),
+        Network::Monacoin => Some([0xfb, 0xc0, 0xb6, 0xdb]),
+        Network::MonacoinTestnet => Some([0xfc, 0xc1, 0xb7, 0xdc]),
+        Network::MonacoinRegtest => Some([0xfa, 0xbf, 0xb5, 0xda]),
+        Network::Myriad => Some([0xaf, 0x45, 0x76, 0xee]),
+        Network::Namecoin => Some([0xf9, 0xbe, 0xb4, 0xfe]),
+        Network::NamecoinTestnet => Some([0xfa, 0xbf, 0xb5, 0xfe]),
+        Network::Peercoin => Some([0xe6, 0xe8, 0xe9, 0xe5]),
+        Network::PeercoinTestnet => Some([0xcb, 0xf2, 0xc0, 0xef]),
+        Network::Ravencoin => Some(*b"RAVN"),
+        Network::RavencoinTestnet => Some(*b"RVNT"),
+        Network::Vertcoin => Some([0xfa, 0xbf, 0xbf, 0xda]),
+        Network::VertcoinTestnet => Some(*b"vert"),
+        Network::Viacoin => Some([0x0f, 0x68, 0xc6, 0xcb]),
+        _ => None,
+    }
+}
+
+/// Returns the largest amount a single output may hold on the network, in
+/// its base unit
+///
+/// This is the network's `MAX_MONEY` consensus limit. Networks not listed
+/// use Bitcoin's 21 million coins.
+pub fn max_money(network: &Network) -> u64 {
+    const COIN: u64 = 100_000_000;
+    match *network {
+        Network::DigiByte
+        | Network::DigiByteTestnet
+        | Network::Ravencoin
+        | Network::RavencoinTestnet => 21_000_000_000 * COIN,
+        Network::Myriad | Network::MyriadTestnet => 2_000_000_000 * COIN,
+        // Peercoin amounts have six decimals
+        Network::Peercoin | Network::PeercoinTestnet => 2_000_000_000 * 1_000_000,
+        Network::Monacoin | Network::MonacoinTestnet | Network::MonacoinRegtest => {
+            105_120_000 * COIN
+        }
+        Network::Groestlcoin | Network::GroestlcoinTestnet => 105_000_000 * COIN,
+        Network::Litecoin
+        | Network::LitecoinTestnet
+        | Network::Vertcoin
+        | Network::VertcoinTestnet => 84_000_000 * COIN,
+        _ => 21_000_000 * COIN,
+    }
+}
+
+/// Returns whether outputs on the network are locked by Bitcoin script
+///
+/// Handshake pairs each address with a covenant instead, and the account
+/// based networks have no output scripts at all.
+pub fn has_bitcoin_script(network: &Network) -> bool {
+    !matches!(
+        *network,
+        Network::CryptoComChain
+            | Network::CryptoComChainTestnet
+            | Network::Handshake
+            | Network::HandshakeTestnet
+            | Network::QuantumResistantLedger
+            | Network::QuantumResistantLedgerTestnet
+            | Network::ZenProtocol
+            | Network::ZenProtocolTestnet
+            | Network::Zilliqa
+            | Network::ZilliqaTestnet
+    )
+}
+
+/// Kind of payload a network encodes in its Bech32 addresses
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum PayloadFamily {
+    /// A witness version followed by a witness program
+    Segwit,
+    /// A plain account hash with no version
+    Account,
+    /// A payload whose layout is particular to the network
+    ChainSpecific,
+}
+
+/// Returns the kind of payload carried by the network's addresses
+///
+/// Only `PayloadFamily::Segwit` networks have addresses that decode to a
+/// `WitnessProgram`; the others decode to an `AccountAddress`.
+pub fn payload_family(network: &Network) -> PayloadFamily {
+    match *network {
+        Network::CryptoComChain
+        | Network::CryptoComChainTestnet
+        | Network::Zilliqa
+        | Network::ZilliqaTestnet => PayloadFamily::Account,
+        Network::QuantumResistantLedger
+        | Network::QuantumResistantLedgerTestnet
+        | Network::ZenProtocol
+        | Network::ZenProtocolTestnet => PayloadFamily::ChainSpecific,
+        _ => PayloadFamily::Segwit,
+    }
+}
@@ -0,0 +1,504 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Cosmos SDK addresses
+//!
+//! Cosmos chains encode the same 20 or 32-byte payloads under several
+//! human-readable parts, one per role: accounts, validator operators and
+//! consensus nodes, each with a variant for public keys. Crypto.com Chain
+//! names its validators "council nodes", hence prefixes such as `crocncl`.
+//!
+//! Public keys are stored in their legacy Amino encoding: a four-byte type
+//! prefix and a length byte before the key, so a consensus key reads as
+//! `crocnclconspub1zcjduepq...`.
+
+use std::str::FromStr;
+use std::{error, fmt};
+
+use bech32::{self, FromBase32, ToBase32, Variant};
+
+use check_compressed_pubkey;
+use constants::{self, Network};
+use hash::hash160;
+
+/// The role an address plays on a Cosmos chain
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum Role {
+    /// An account holding funds
+    Account,
+    /// Public key of an account
+    AccountPub,
+    /// A validator operator
+    ValidatorOperator,
+    /// Public key of a validator operator
+    ValidatorOperatorPub,
+    /// A validator consensus node
+    Consensus,
+    /// Public key of a validator consensus node
+    ConsensusPub,
+}
+
+/// Every role, in the order of the prefix table
+pub const ROLES: [Role; 6] = [
+    Role::Account,
+    Role::AccountPub,
+    Role::ValidatorOperator,
+    Role::ValidatorOperatorPub,
+    Role::Consensus,
+    Role::ConsensusPub,
+];
+
+impl Role {
+    /// Whether the payload is a public key rather than an address hash
+    pub fn is_public_key(self) -> bool {
+        matches!(
+            self,
+            Role::AccountPub | Role::ValidatorOperatorPub | Role::ConsensusPub
+        )
+    }
+
+    /// Suffix appended to the chain's account prefix on Crypto.com Chain
+    fn crypto_com_suffix(self) -> &'static str {
+        match self {
+            Role::Account => "",
+            Role::AccountPub => "pub",
+            Role::ValidatorOperator => "cncl",
+            Role::ValidatorOperatorPub => "cnclpub",
+            Role::Consensus => "cnclcons",
+            Role::ConsensusPub => "cnclconspub",
+        }
+    }
+}
+
+/// Signature scheme of a public key
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum KeyType {
+    /// A compressed secp256k1 key, used by accounts
+    Secp256k1,
+    /// An Ed25519 key, used by consensus nodes
+    Ed25519,
+}
+
+impl KeyType {
+    /// Amino type prefix and length byte of the encoded key
+    fn amino_prefix(self) -> [u8; 5] {
+        match self {
+            KeyType::Secp256k1 => [0xeb, 0x5a, 0xe9, 0x87, 0x21],
+            KeyType::Ed25519 => [0x16, 0x24, 0xde, 0x64, 0x20],
+        }
+    }
+
+    /// Length of the key without its prefix
+    fn key_len(self) -> usize {
+        match self {
+            KeyType::Secp256k1 => 33,
+            KeyType::Ed25519 => 32,
+        }
+    }
+}
+
+/// Returns the human-readable part used for `role` on a Cosmos network
+///
+/// Returns `None` if the network is not Cosmos-based.
+pub fn prefix(network: Network, role: Role) -> Option<String> {
+    match network {
+        Network::CryptoComChain | Network::CryptoComChainTestnet => Some(format!(
+            "{}{}",
+            constants::hrp(&network),
+            role.crypto_com_suffix()
+        )),
+        _ => None,
+    }
+}
+
+/// Finds the network and role a human-readable part belongs to
+pub fn classify_prefix(hrp: &str) -> Option<(Network, Role)> {
+    let hrp = hrp.to_lowercase();
+    [Network::CryptoComChain, Network::CryptoComChainTestnet]
+        .iter()
+        .flat_map(|&network| ROLES.iter().map(move |&role| (network, role)))
+        .find(|&(network, role)| prefix(network, role).as_deref() == Some(&hrp[..]))
+}
+
+/// A Bech32 address of a Cosmos chain
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct CosmosAddress {
+    network: Network,
+    role: Role,
+    payload: Vec<u8>,
+    bech32: String,
+}
+
+impl CosmosAddress {
+    /// Encodes a payload under the prefix of `role`
+    ///
+    /// Addresses must be 20 or 32 bytes. Public keys must be Amino-encoded
+    /// compressed secp256k1 or Ed25519 keys; use `from_key` to encode a bare
+    /// key.
+    pub fn new(role: Role, payload: Vec<u8>, network: Network) -> Result<CosmosAddress, Error> {
+        let hrp = prefix(network, role).ok_or(Error::NotCosmos(network))?;
+        check_payload(role, &payload)?;
+        let bech32 =
+            bech32::encode(&hrp, payload.to_base32(), Variant::Bech32).map_err(::Error::Bech32)?;
+        Ok(CosmosAddress {
+            network,
+            role,
+            payload,
+            bech32,
+        })
+    }
+
+    /// Account address of a compressed secp256k1 public key
+    ///
+    /// The payload is the RIPEMD160 of the SHA256 of the key.
+    pub fn from_public_key(pubkey: &[u8], network: Network) -> Result<CosmosAddress, Error> {
+        check_compressed_pubkey(pubkey)?;
+        CosmosAddress::new(Role::Account, hash160(pubkey).to_vec(), network)
+    }
+
+    /// Encodes a bare public key under the prefix of a public key `role`
+    pub fn from_key(
+        role: Role,
+        key_type: KeyType,
+        key: &[u8],
+        network: Network,
+    ) -> Result<CosmosAddress, Error> {
+        if !role.is_public_key() {
+            return Err(Error::InvalidRoleConversion);
+        }
+        let mut payload = key_type.amino_prefix().to_vec();
+        payload.extend_from_slice(key);
+        CosmosAddress::new(role, payload, network)
+    }
+
+    /// Decodes an address of any role
+    pub fn from_address(address: &str) -> Result<CosmosAddress, Error> {
+        CosmosAddress::from_str(address)
+    }
+
+    /// The same payload under the prefix of another role
+    ///
+    /// Addresses convert between the address roles and public keys between
+    /// the public key roles.
+    pub fn with_role(&self, role: Role) -> Result<CosmosAddress, Error> {
+        if role.is_public_key() != self.role.is_public_key() {
+            return Err(Error::InvalidRoleConversion);
+        }
+        CosmosAddress::new(role, self.payload.clone(), self.network)
+    }
+
+    /// Encodes the address as a string
+    pub fn to_address(&self) -> String {
+        self.to_string()
+    }
+
+    /// Network of the address
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Role of the address
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Address hash, or public key with its Amino prefix
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Type and bare bytes of the public key, for the public key roles
+    pub fn public_key(&self) -> Option<(KeyType, &[u8])> {
+        if !self.role.is_public_key() {
+            return None;
+        }
+        amino_key(&self.payload)
+    }
+}
+
+impl fmt::Display for CosmosAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.bech32)
+    }
+}
+
+impl FromStr for CosmosAddress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<CosmosAddress, Error> {
+        let (hrp, data, variant) = bech32::decode(s).map_err(::Error::Bech32)?;
+        let (network, role) = classify_prefix(&hrp).ok_or(Error::UnknownPrefix)?;
+        if variant != Variant::Bech32 {
+            return Err(Error::Address(::Error::InvalidChecksumVariant));
+        }
+        let payload = Vec::from_base32(&data).map_err(::Error::Bech32)?;
+        check_payload(role, &payload)?;
+        Ok(CosmosAddress {
+            network,
+            role,
+            payload,
+            bech32: s.to_lowercase(),
+        })
+    }
+}
+
+/// Splits an Amino-encoded key into its type and bytes
+fn amino_key(payload: &[u8]) -> Option<(KeyType, &[u8])> {
+    [KeyType::Secp256k1, KeyType::Ed25519]
+        .iter()
+        .find(|key_type| payload.starts_with(&key_type.amino_prefix()))
+        .map(|&key_type| (key_type, &payload[5..]))
+}
+
+fn check_payload(role: Role, payload: &[u8]) -> Result<(), Error> {
+    if !role.is_public_key() {
+        return match payload.len() {
+            20 | 32 => Ok(()),
+            _ => Err(Error::InvalidLength),
+        };
+    }
+    let (key_type, key) = amino_key(payload).ok_or(Error::UnknownKeyType)?;
+    if key.len() != key_type.key_len() {
+        return Err(Error::InvalidLength);
+    }
+    if key_type == KeyType::Secp256k1 {
+        check_compressed_pubkey(key)?;
+    }
+    Ok(())
+}
+
+/// Error types for Cosmos addresses
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// The Bech32 encoding or the public key is invalid
+    Address(::Error),
+    /// The network is not Cosmos-based
+    NotCosmos(Network),
+    /// The human-readable part is not the prefix of any role
+    UnknownPrefix,
+    /// The payload length does not fit the role
+    InvalidLength,
+    /// A public key lacks a known Amino type prefix
+    UnknownKeyType,
+    /// Addresses and public keys cannot be converted into each other
+    InvalidRoleConversion,
+}
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::Address(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Address(ref e) => write!(f, "{}", e),
+            Error::NotCosmos(network) => write!(f, "{:?} is not Cosmos-based", network),
+            Error::UnknownPrefix => write!(f, "unknown address prefix"),
+            Error::InvalidLength => write!(f, "invalid payload length for role"),
+            Error::UnknownKeyType => write!(f, "unknown public key type"),
+            Error::InvalidRoleConversion => {
+                write!(f, "cannot convert between addresses and public keys")
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Address(_) => "address error",
+            Error::NotCosmos(_) => "network is not Cosmos-based",
+            Error::UnknownPrefix => "unknown address prefix",
+            Error::InvalidLength => "invalid payload length for role",
+            Error::UnknownKeyType => "unknown public key type",
+            Error::InvalidRoleConversion => "cannot convert between addresses and public keys",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Address(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+    use AccountAddress;
+
+    const KEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    #[test]
+    fn prefixes() {
+        let prefixes: Vec<String> = ROLES
+            .iter()
+            .map(|&role| prefix(Network::CryptoComChain, role).unwrap())
+            .collect();
+        assert_eq!(
+            prefixes,
+            vec![
+                "cro",
+                "cropub",
+                "crocncl",
+                "crocnclpub",
+                "crocnclcons",
+                "crocnclconspub"
+            ]
+        );
+        assert_eq!(
+            prefix(Network::CryptoComChainTestnet, Role::Consensus),
+            Some("tcrocnclcons".to_string())
+        );
+        assert_eq!(prefix(Network::Bitcoin, Role::Account), None);
+        assert_eq!(
+            classify_prefix("TCROCNCL"),
+            Some((Network::CryptoComChainTestnet, Role::ValidatorOperator))
+        );
+        assert_eq!(classify_prefix("crocnc"), None);
+    }
+
+    #[test]
+    fn derive_and_convert() {
+        let key = hex::decode(KEY).unwrap();
+        let account = CosmosAddress::from_public_key(&key, Network::CryptoComChain).unwrap();
+        assert_eq!(
+            hex::encode(account.payload()),
+            "751e76e8199196d454941c45d1b3a323f1433bd6"
+        );
+        assert!(account.to_address().starts_with("cro1"));
+        assert_eq!(
+            AccountAddress::from_address(&account.to_address())
+                .unwrap()
+                .payload(),
+            account.payload()
+        );
+
+        let operator = account.with_role(Role::ValidatorOperator).unwrap();
+        assert!(operator.to_address().starts_with("crocncl1"));
+        let parsed = CosmosAddress::from_address(&operator.to_address()).unwrap();
+        assert_eq!(parsed, operator);
+        assert_eq!(parsed.with_role(Role::Account).unwrap(), account);
+
+        let pubkey = CosmosAddress::from_key(
+            Role::AccountPub,
+            KeyType::Secp256k1,
+            &key,
+            Network::CryptoComChain,
+        )
+        .unwrap();
+        assert!(pubkey.to_address().starts_with("cropub1addwnpepq"));
+        assert_eq!(pubkey.public_key(), Some((KeyType::Secp256k1, &key[..])));
+        assert_eq!(
+            CosmosAddress::from_address(&pubkey.to_address()).unwrap(),
+            pubkey
+        );
+        assert_eq!(
+            pubkey.with_role(Role::ConsensusPub).unwrap().role(),
+            Role::ConsensusPub
+        );
+        assert_eq!(
+            pubkey.with_role(Role::Account),
+            Err(Error::InvalidRoleConversion)
+        );
+    }
+
+    #[test]
+    fn consensus_keys() {
+        // `gaiad tendermint show-validator` output from the Cosmos SDK docs;
+        // Crypto.com Chain encodes consensus keys the same way
+        let cosmos =
+            "cosmosvalconspub1zcjduepq0vu2zgkgk49efa0nqwzndanq5m4c7pa3u4apz4g2r9gspqg6g9cs3k9cuf";
+        let (_, data, _) = bech32::decode(cosmos).unwrap();
+        let payload = Vec::from_base32(&data).unwrap();
+        let key = "7b38a122c8b54b94f5f3038536f660a6eb8f07b1e57a11550a195100811a4171";
+
+        let address =
+            CosmosAddress::new(Role::ConsensusPub, payload, Network::CryptoComChain).unwrap();
+        assert!(address.to_address().starts_with("crocnclconspub1zcjduepq"));
+        let (key_type, bytes) = address.public_key().unwrap();
+        assert_eq!(key_type, KeyType::Ed25519);
+        assert_eq!(hex::encode(bytes), key);
+        assert_eq!(
+            CosmosAddress::from_address(&address.to_address()).unwrap(),
+            address
+        );
+        assert_eq!(
+            CosmosAddress::from_key(
+                Role::ConsensusPub,
+                KeyType::Ed25519,
+                &hex::decode(key).unwrap(),
+                Network::CryptoComChain
+            )
+            .unwrap(),
+            address
+        );
+        assert_eq!(
+            address.with_role(Role::Consensus),
+            Err(Error::InvalidRoleConversion)
+        );
+    }
+
+    #[test]
+    fn invalid_addresses() {
+        assert_eq!(
+            CosmosAddress::new(Role::Account, vec![0; 21], Network::CryptoComChain),
+            Err(Error::InvalidLength)
+        );
+        assert_eq!(
+            CosmosAddress::new(Role::Account, vec![0; 20], Network::Zilliqa),
+            Err(Error::NotCosmos(Network::Zilliqa))
+        );
+        assert_eq!(
+            CosmosAddress::from_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"),
+            Err(Error::UnknownPrefix)
+        );
+        assert_eq!(
+            CosmosAddress::from_public_key(&[0x02; 32], Network::CryptoComChain),
+            Err(Error::Address(::Error::InvalidPublicKey))
+        );
+        assert_eq!(
+            CosmosAddress::new(Role::ConsensusPub, vec![0; 32], Network::CryptoComChain),
+            Err(Error::UnknownKeyType)
+        );
+        assert_eq!(
+            CosmosAddress::from_key(
+                Role::ConsensusPub,
+                KeyType::Ed25519,
+                &[0; 33],
+                Network::CryptoComChain
+            ),
+            Err(Error::InvalidLength)
+        );
+        assert_eq!(
+            CosmosAddress::from_key(
+                Role::Account,
+                KeyType::Ed25519,
+                &[0; 32],
+                Network::CryptoComChain
+            ),
+            Err(Error::InvalidRoleConversion)
+        );
+    }
+}
@@ -0,0 +1,1049 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Output script descriptors
+//!
+//! Parses and prints the segwit subset of the descriptor language used by
+//! Bitcoin Core and its forks: `wpkh()`, `wsh(multi())`,
+//! `wsh(sortedmulti())`, `wsh()` and `tr()` with miniscript, `addr()` and
+//! `raw()`, including the descriptor checksum. Keys are hex public keys or extended public keys
+//! with an optional origin and derivation path, decoded for the network the
+//! descriptor is parsed for.
+
+use std::{error, fmt};
+
+use bip32::{self, ExtendedPubKey, HARDENED};
+use constants::Network;
+use miniscript::{self, Context, Miniscript};
+use taproot::{TapTree, TaprootOutput, TAPROOT_MAX_DEPTH};
+use {hex, script, WitnessProgram};
+
+/// Characters allowed in a descriptor, ordered for the checksum
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+/// Characters of the checksum, the bech32 alphabet
+const CHECKSUM_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Maximum number of keys in a `multi()` or `sortedmulti()` expression
+pub const MAX_MULTISIG_KEYS: usize = 20;
+
+/// One step of the descriptor checksum, a BCH code over GF(32)
+fn polymod(c: u64, val: u64) -> u64 {
+    let c0 = c >> 35;
+    let mut c = ((c & 0x7_ffff_ffff) << 5) ^ val;
+    if c0 & 1 != 0 {
+        c ^= 0xf5_dee5_1989;
+    }
+    if c0 & 2 != 0 {
+        c ^= 0xa9_fdca_3312;
+    }
+    if c0 & 4 != 0 {
+        c ^= 0x1b_ab10_e32d;
+    }
+    if c0 & 8 != 0 {
+        c ^= 0x37_06b1_677a;
+    }
+    if c0 & 16 != 0 {
+        c ^= 0x64_4d62_6ffd;
+    }
+    c
+}
+
+/// Computes the eight character checksum of a descriptor
+pub fn checksum(desc: &str) -> Result<String, Error> {
+    let mut c = 1u64;
+    let mut cls = 0u64;
+    let mut clscount = 0;
+    for ch in desc.chars() {
+        let pos = match INPUT_CHARSET.find(ch) {
+            Some(pos) => pos as u64,
+            None => return Err(Error::InvalidCharacter(ch)),
+        };
+        c = polymod(c, pos & 31);
+        cls = cls * 3 + (pos >> 5);
+        clscount += 1;
+        if clscount == 3 {
+            c = polymod(c, cls);
+            cls = 0;
+            clscount = 0;
+        }
+    }
+    if clscount > 0 {
+        c = polymod(c, cls);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+    let charset = CHECKSUM_CHARSET.as_bytes();
+    Ok((0..8)
+        .map(|j| charset[((c >> (5 * (7 - j))) & 31) as usize] as char)
+        .collect())
+}
+
+/// Strips and verifies a trailing `#checksum`, if present
+pub fn strip_checksum(s: &str) -> Result<&str, Error> {
+    match s.find('#') {
+        Some(pos) => {
+            let (desc, sum) = (&s[..pos], &s[pos + 1..]);
+            if sum.len() != 8 || checksum(desc)? != sum {
+                return Err(Error::InvalidChecksum);
+            }
+            Ok(desc)
+        }
+        None => {
+            checksum(s)?;
+            Ok(s)
+        }
+    }
+}
+
+/// Fingerprint and derivation path of the key a descriptor key descends from
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct KeyOrigin {
+    /// Fingerprint of the master key
+    fingerprint: [u8; 4],
+    /// Derivation path from the master key, hardened steps included
+    path: Vec<u32>,
+}
+
+impl KeyOrigin {
+    /// Fingerprint of the master key
+    pub fn fingerprint(&self) -> [u8; 4] {
+        self.fingerprint
+    }
+
+    /// Derivation path from the master key
+    pub fn path(&self) -> &[u32] {
+        &self.path
+    }
+}
+
+/// The key material of a descriptor key
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+enum KeyData {
+    /// A compressed or x-only public key
+    Single(Vec<u8>),
+    /// An extended public key, derived along `path` and then the index if
+    /// `wildcard` is set
+    Extended {
+        xpub: ExtendedPubKey,
+        path: Vec<u32>,
+        wildcard: bool,
+    },
+}
+
+/// A public key expression inside a descriptor
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct DescriptorKey {
+    /// Origin of the key, if given
+    origin: Option<KeyOrigin>,
+    /// Key material
+    data: KeyData,
+    /// The expression as written, used when printing
+    repr: String,
+}
+
+impl DescriptorKey {
+    /// Parses a key expression, decoding extended keys for `network`
+    ///
+    /// Extended keys may use the Bitcoin version bytes `network` borrows, as
+    /// the descriptor's network is given by the caller.
+    ///
+    /// Hex keys must be 33-byte compressed or 32-byte x-only keys, and
+    /// extended keys may only be followed by unhardened steps and an
+    /// optional `*` wildcard.
+    pub fn parse(s: &str, network: Network) -> Result<DescriptorKey, Error> {
+        let (origin, key) = if s.starts_with('[') {
+            let end = s.find(']').ok_or(Error::InvalidSyntax)?;
+            (Some(parse_origin(&s[1..end])?), &s[end + 1..])
+        } else {
+            (None, s)
+        };
+        let data = if let Some(bytes) = hex::decode(key) {
+            match (bytes.len(), bytes.first()) {
+                (33, Some(&0x02)) | (33, Some(&0x03)) | (32, _) => KeyData::Single(bytes),
+                _ => return Err(Error::InvalidKey),
+            }
+        } else {
+            let mut steps = key.split('/');
+            let xpub = ExtendedPubKey::decode_shared(steps.next().unwrap_or(""), network)?;
+            let mut path = Vec::new();
+            let mut wildcard = false;
+            for step in steps {
+                if wildcard {
+                    return Err(Error::InvalidSyntax);
+                }
+                match step {
+                    "*" => wildcard = true,
+                    "*'" | "*h" | "*H" => return Err(Error::HardenedDerivation),
+                    _ => match parse_step(step)? {
+                        index if index >= HARDENED => return Err(Error::HardenedDerivation),
+                        index => path.push(index),
+                    },
+                }
+            }
+            KeyData::Extended {
+                xpub,
+                path,
+                wildcard,
+            }
+        };
+        Ok(DescriptorKey {
+            origin,
+            data,
+            repr: s.to_string(),
+        })
+    }
+
+    /// Whether the key depends on a derivation index
+    pub fn is_ranged(&self) -> bool {
+        match self.data {
+            KeyData::Extended { wildcard, .. } => wildcard,
+            KeyData::Single(_) => false,
+        }
+    }
+
+    /// Origin of the key, if given
+    pub fn origin(&self) -> Option<&KeyOrigin> {
+        self.origin.as_ref()
+    }
+
+    /// Public key at a derivation index, 33 bytes compressed or 32 x-only
+    ///
+    /// The index is ignored for keys that are not ranged.
+    pub fn public_key(&self, index: u32) -> Result<Vec<u8>, Error> {
+        match self.data {
+            KeyData::Single(ref key) => Ok(key.clone()),
+            KeyData::Extended {
+                ref xpub,
+                ref path,
+                wildcard,
+            } => {
+                let mut key = xpub.derive_path(path)?;
+                if wildcard {
+                    if index >= HARDENED {
+                        return Err(Error::HardenedDerivation);
+                    }
+                    key = key.derive_child(index)?;
+                }
+                Ok(key.public_key().to_vec())
+            }
+        }
+    }
+
+    /// Compressed public key at a derivation index
+    pub fn compressed_key(&self, index: u32) -> Result<Vec<u8>, Error> {
+        let key = self.public_key(index)?;
+        if key.len() != 33 {
+            return Err(Error::InvalidKey);
+        }
+        Ok(key)
+    }
+
+    /// X-only public key at a derivation index
+    pub fn x_only_key(&self, index: u32) -> Result<[u8; 32], Error> {
+        let key = self.public_key(index)?;
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&key[key.len() - 32..]);
+        Ok(out)
+    }
+}
+
+impl fmt::Display for DescriptorKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.repr)
+    }
+}
+
+/// Parses the inside of a `[fingerprint/path]` origin
+fn parse_origin(s: &str) -> Result<KeyOrigin, Error> {
+    let mut steps = s.split('/');
+    let fingerprint = match steps.next().and_then(hex::decode) {
+        Some(ref bytes) if bytes.len() == 4 => [bytes[0], bytes[1], bytes[2], bytes[3]],
+        _ => return Err(Error::InvalidKey),
+    };
+    let path = steps.map(parse_step).collect::<Result<Vec<u32>, Error>>()?;
+    Ok(KeyOrigin { fingerprint, path })
+}
+
+/// Parses a derivation step, with `'`, `h` or `H` marking hardened steps
+fn parse_step(step: &str) -> Result<u32, Error> {
+    let (number, hardened) = match step.chars().last() {
+        Some('\'') | Some('h') | Some('H') => (&step[..step.len() - 1], true),
+        _ => (step, false),
+    };
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return Err(Error::InvalidSyntax);
+    }
+    match number.parse::<u32>() {
+        Ok(index) if index < HARDENED => Ok(if hardened { index + HARDENED } else { index }),
+        _ => Err(Error::InvalidSyntax),
+    }
+}
+
+/// The script inside a `wsh()` descriptor
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub enum WshScript {
+    /// `multi(k, keys...)`: keys in the given order
+    Multi(usize, Vec<DescriptorKey>),
+    /// `sortedmulti(k, keys...)`: keys sorted after derivation
+    SortedMulti(usize, Vec<DescriptorKey>),
+    /// Any other miniscript
+    Miniscript(Miniscript),
+}
+
+impl WshScript {
+    /// Witness script at a derivation index
+    pub fn witness_script(&self, index: u32) -> Result<Vec<u8>, Error> {
+        match *self {
+            WshScript::Multi(k, ref keys) => {
+                let keys = derive_compressed(keys, index)?;
+                Ok(script::multisig(k, &keys))
+            }
+            WshScript::SortedMulti(k, ref keys) => {
+                let mut keys = derive_compressed(keys, index)?;
+                keys.sort();
+                Ok(script::multisig(k, &keys))
+            }
+            WshScript::Miniscript(ref ms) => Ok(ms.encode(index)?),
+        }
+    }
+
+    fn is_ranged(&self) -> bool {
+        match *self {
+            WshScript::Multi(_, ref keys) | WshScript::SortedMulti(_, ref keys) => {
+                keys.iter().any(|key| key.is_ranged())
+            }
+            WshScript::Miniscript(ref ms) => ms.is_ranged(),
+        }
+    }
+}
+
+impl fmt::Display for WshScript {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, k, keys) = match *self {
+            WshScript::Multi(k, ref keys) => ("multi", k, keys),
+            WshScript::SortedMulti(k, ref keys) => ("sortedmulti", k, keys),
+            WshScript::Miniscript(ref ms) => return write!(f, "{}", ms),
+        };
+        write!(f, "{}({}", name, k)?;
+        for key in keys {
+            write!(f, ",{}", key)?;
+        }
+        write!(f, ")")
+    }
+}
+
+fn derive_compressed(keys: &[DescriptorKey], index: u32) -> Result<Vec<Vec<u8>>, Error> {
+    keys.iter().map(|key| key.compressed_key(index)).collect()
+}
+
+/// A script tree inside a `tr()` descriptor
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub enum TapTreeDescriptor {
+    /// A tapscript leaf
+    Leaf(Miniscript),
+    /// A `{left,right}` branch
+    Branch(Box<TapTreeDescriptor>, Box<TapTreeDescriptor>),
+}
+
+impl TapTreeDescriptor {
+    /// Script tree at a derivation index
+    pub fn tap_tree(&self, index: u32) -> Result<TapTree, Error> {
+        match *self {
+            TapTreeDescriptor::Leaf(ref ms) => Ok(TapTree::leaf(ms.tap_leaf(index)?)),
+            TapTreeDescriptor::Branch(ref left, ref right) => Ok(TapTree::branch(
+                left.tap_tree(index)?,
+                right.tap_tree(index)?,
+            )),
+        }
+    }
+
+    fn is_ranged(&self) -> bool {
+        match *self {
+            TapTreeDescriptor::Leaf(ref ms) => ms.is_ranged(),
+            TapTreeDescriptor::Branch(ref left, ref right) => left.is_ranged() || right.is_ranged(),
+        }
+    }
+}
+
+impl fmt::Display for TapTreeDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TapTreeDescriptor::Leaf(ref ms) => write!(f, "{}", ms),
+            TapTreeDescriptor::Branch(ref left, ref right) => write!(f, "{{{},{}}}", left, right),
+        }
+    }
+}
+
+/// The top-level expression of a descriptor
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub enum DescriptorType {
+    /// `wpkh(KEY)`
+    Wpkh(DescriptorKey),
+    /// `wsh(SCRIPT)`
+    Wsh(WshScript),
+    /// `tr(KEY)` or `tr(KEY,TREE)`
+    Tr(DescriptorKey, Option<TapTreeDescriptor>),
+    /// `addr(ADDRESS)`
+    Addr(WitnessProgram),
+    /// `raw(HEX)`
+    Raw(Vec<u8>),
+}
+
+/// An output script descriptor for a given network
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct Descriptor {
+    /// Network the descriptor expands on
+    network: Network,
+    /// The parsed expression
+    desc: DescriptorType,
+}
+
+impl Descriptor {
+    /// Parses a descriptor for the given network
+    ///
+    /// A trailing `#checksum` is verified if present. Extended keys are
+    /// decoded with the network's version bytes and checksum, and `addr()`
+    /// must hold an address of the same network.
+    pub fn parse(s: &str, network: Network) -> Result<Descriptor, Error> {
+        let s = strip_checksum(s)?;
+        let (name, args) = split_call(s)?;
+        let desc = match name {
+            "wpkh" => {
+                let key = DescriptorKey::parse(single_arg(&args)?, network)?;
+                require_compressed(&key)?;
+                DescriptorType::Wpkh(key)
+            }
+            "wsh" => DescriptorType::Wsh(parse_wsh_script(single_arg(&args)?, network)?),
+            "tr" => {
+                if args.is_empty() || args.len() > 2 {
+                    return Err(Error::InvalidSyntax);
+                }
+                let key = DescriptorKey::parse(args[0], network)?;
+                let tree = match args.get(1) {
+                    Some(tree) => Some(parse_tree(tree, network, 0)?),
+                    None => None,
+                };
+                DescriptorType::Tr(key, tree)
+            }
+            "addr" => {
+                let program = WitnessProgram::from_address(single_arg(&args)?)?;
+                if program.network() != network {
+                    return Err(Error::NetworkMismatch);
+                }
+                DescriptorType::Addr(program)
+            }
+            "raw" => DescriptorType::Raw(hex::decode(single_arg(&args)?).ok_or(Error::InvalidHex)?),
+            _ => return Err(Error::UnsupportedFunction(name.to_string())),
+        };
+        Ok(Descriptor { network, desc })
+    }
+
+    /// Network the descriptor expands on
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// The parsed expression
+    pub fn descriptor_type(&self) -> &DescriptorType {
+        &self.desc
+    }
+
+    /// Whether the descriptor depends on a derivation index
+    pub fn is_ranged(&self) -> bool {
+        match self.desc {
+            DescriptorType::Wpkh(ref key) => key.is_ranged(),
+            DescriptorType::Wsh(ref script) => script.is_ranged(),
+            DescriptorType::Tr(ref key, ref tree) => {
+                key.is_ranged() || tree.iter().any(|t| t.is_ranged())
+            }
+            DescriptorType::Addr(_) | DescriptorType::Raw(_) => false,
+        }
+    }
+
+    /// Witness script at a derivation index, for `wsh()` descriptors
+    pub fn witness_script(&self, index: u32) -> Result<Option<Vec<u8>>, Error> {
+        match self.desc {
+            DescriptorType::Wsh(ref script) => script.witness_script(index).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Witness program at a derivation index
+    ///
+    /// The index is ignored for descriptors that are not ranged. A `raw()`
+    /// script that is not a witness program fails with the error of
+    /// `WitnessProgram::from_scriptpubkey`.
+    pub fn witness_program(&self, index: u32) -> Result<WitnessProgram, Error> {
+        match self.desc {
+            DescriptorType::Wpkh(ref key) => Ok(WitnessProgram::p2wpkh_from_pubkey(
+                &key.compressed_key(index)?,
+                self.network,
+            )?),
+            DescriptorType::Wsh(ref script) => Ok(WitnessProgram::p2wsh_from_script(
+                &script.witness_script(index)?,
+                self.network,
+            )?),
+            DescriptorType::Tr(ref key, ref tree) => {
+                let tree = match *tree {
+                    Some(ref tree) => Some(tree.tap_tree(index)?),
+                    None => None,
+                };
+                let output =
+                    TaprootOutput::new(key.x_only_key(index)?, tree.as_ref(), self.network)?;
+                Ok(output.witness_program().clone())
+            }
+            DescriptorType::Addr(ref program) => Ok(program.clone()),
+            DescriptorType::Raw(ref script) => {
+                Ok(WitnessProgram::from_scriptpubkey(script, self.network)?)
+            }
+        }
+    }
+
+    /// Witness programs for `count` consecutive indices starting at `start`
+    pub fn witness_programs(&self, start: u32, count: u32) -> Result<Vec<WitnessProgram>, Error> {
+        let end = start.checked_add(count).ok_or(Error::HardenedDerivation)?;
+        (start..end)
+            .map(|index| self.witness_program(index))
+            .collect()
+    }
+
+    /// The descriptor without its checksum
+    pub fn to_string_without_checksum(&self) -> String {
+        match self.desc {
+            DescriptorType::Wpkh(ref key) => format!("wpkh({})", key),
+            DescriptorType::Wsh(ref script) => format!("wsh({})", script),
+            DescriptorType::Tr(ref key, None) => format!("tr({})", key),
+            DescriptorType::Tr(ref key, Some(ref tree)) => format!("tr({},{})", key, tree),
+            DescriptorType::Addr(ref program) => format!("addr({})", program),
+            DescriptorType::Raw(ref script) => format!("raw({})", hex::encode(script)),
+        }
+    }
+}
+
+impl fmt::Display for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let desc = self.to_string_without_checksum();
+        let sum = checksum(&desc).map_err(|_| fmt::Error)?;
+        write!(f, "{}#{}", desc, sum)
+    }
+}
+
+pub(crate) fn require_compressed(key: &DescriptorKey) -> Result<(), Error> {
+    match key.data {
+        KeyData::Single(ref bytes) if bytes.len() != 33 => Err(Error::InvalidKey),
+        _ => Ok(()),
+    }
+}
+
+fn parse_wsh_script(s: &str, network: Network) -> Result<WshScript, Error> {
+    let (name, args) = match split_call(s) {
+        Ok((name, args)) if name == "multi" || name == "sortedmulti" => (name, args),
+        _ => {
+            let ms = Miniscript::parse(s, network, Context::Segwitv0)?;
+            return Ok(WshScript::Miniscript(ms));
+        }
+    };
+    let sorted = name == "sortedmulti";
+    let (k, keys) = parse_multi_args(&args, network)?;
+    Ok(if sorted {
+        WshScript::SortedMulti(k, keys)
+    } else {
+        WshScript::Multi(k, keys)
+    })
+}
+
+/// Parses the `k,KEY,KEY...` arguments of a multisig expression
+pub(crate) fn parse_multi_args(
+    args: &[&str],
+    network: Network,
+) -> Result<(usize, Vec<DescriptorKey>), Error> {
+    let (k, keys) = args.split_first().ok_or(Error::InvalidSyntax)?;
+    if k.is_empty() || !k.chars().all(|c| c.is_ascii_digit()) {
+        return Err(Error::InvalidThreshold);
+    }
+    let k: usize = k.parse().map_err(|_| Error::InvalidThreshold)?;
+    if k == 0 || k > keys.len() || keys.len() > MAX_MULTISIG_KEYS {
+        return Err(Error::InvalidThreshold);
+    }
+    let keys = keys
+        .iter()
+        .map(|key| {
+            let key = DescriptorKey::parse(key, network)?;
+            require_compressed(&key)?;
+            Ok(key)
+        })
+        .collect::<Result<Vec<DescriptorKey>, Error>>()?;
+    Ok((k, keys))
+}
+
+/// Parses a script tree whose root is `depth` branches down
+fn parse_tree(s: &str, network: Network, depth: usize) -> Result<TapTreeDescriptor, Error> {
+    if s.starts_with('{') {
+        if depth >= TAPROOT_MAX_DEPTH {
+            return Err(Error::WitnessProgram(::Error::TaprootTreeTooDeep));
+        }
+        if !s.ends_with('}') {
+            return Err(Error::InvalidSyntax);
+        }
+        let children = split_args(&s[1..s.len() - 1])?;
+        if children.len() != 2 {
+            return Err(Error::InvalidSyntax);
+        }
+        return Ok(TapTreeDescriptor::Branch(
+            Box::new(parse_tree(children[0], network, depth + 1)?),
+            Box::new(parse_tree(children[1], network, depth + 1)?),
+        ));
+    }
+    let ms = Miniscript::parse(s, network, Context::Tapscript)?;
+    Ok(TapTreeDescriptor::Leaf(ms))
+}
+
+/// Splits `name(args)` into the name and its top-level arguments
+pub(crate) fn split_call(s: &str) -> Result<(&str, Vec<&str>), Error> {
+    let open = s.find('(').ok_or(Error::InvalidSyntax)?;
+    if !s.ends_with(')') {
+        return Err(Error::InvalidSyntax);
+    }
+    Ok((&s[..open], split_args(&s[open + 1..s.len() - 1])?))
+}
+
+/// Splits a string on the commas that are not nested in brackets
+pub(crate) fn split_args(s: &str) -> Result<Vec<&str>, Error> {
+    let mut args = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => depth = depth.checked_sub(1).ok_or(Error::InvalidSyntax)?,
+            ',' if depth == 0 => {
+                args.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(Error::InvalidSyntax);
+    }
+    if !s.is_empty() {
+        args.push(&s[start..]);
+    }
+    Ok(args)
+}
+
+fn single_arg<'a>(args: &[&'a str]) -> Result<&'a str, Error> {
+    match args.len() {
+        1 => Ok(args[0]),
+        _ => Err(Error::InvalidSyntax),
+    }
+}
+
+/// Error types for descriptors
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// Error building a witness program
+    WitnessProgram(::Error),
+    /// Error decoding or deriving an extended public key
+    Bip32(bip32::Error),
+    /// Error parsing or type checking a miniscript
+    Miniscript(Box<miniscript::Error>),
+    /// The descriptor contains a character outside the descriptor charset
+    InvalidCharacter(char),
+    /// The `#checksum` does not match the descriptor
+    InvalidChecksum,
+    /// Unbalanced brackets or a wrong number of arguments
+    InvalidSyntax,
+    /// The function is unknown or not supported in this position
+    UnsupportedFunction(String),
+    /// A key expression is malformed or not allowed in this position
+    InvalidKey,
+    /// Hardened derivation after an extended public key
+    HardenedDerivation,
+    /// The multisig threshold is zero or larger than the number of keys
+    InvalidThreshold,
+    /// `raw()` holds malformed hex
+    InvalidHex,
+    /// `addr()` holds an address of another network
+    NetworkMismatch,
+}
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::WitnessProgram(e)
+    }
+}
+
+impl From<miniscript::Error> for Error {
+    fn from(e: miniscript::Error) -> Error {
+        match e {
+            miniscript::Error::Descriptor(e) => e,
+            miniscript::Error::WitnessProgram(e) => Error::WitnessProgram(e),
+            e => Error::Miniscript(Box::new(e)),
+        }
+    }
+}
+
+impl From<bip32::Error> for Error {
+    fn from(e: bip32::Error) -> Error {
+        match e {
+            bip32::Error::HardenedDerivation => Error::HardenedDerivation,
+            e => Error::Bip32(e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::WitnessProgram(ref e) => write!(f, "{}", e),
+            Error::Bip32(ref e) => write!(f, "{}", e),
+            Error::Miniscript(ref e) => write!(f, "{}", e),
+            Error::InvalidCharacter(c) => write!(f, "invalid descriptor character {:?}", c),
+            Error::InvalidChecksum => write!(f, "invalid descriptor checksum"),
+            Error::InvalidSyntax => write!(f, "invalid descriptor syntax"),
+            Error::UnsupportedFunction(ref name) => {
+                write!(f, "unsupported descriptor function {:?}", name)
+            }
+            Error::InvalidKey => write!(f, "invalid key expression"),
+            Error::HardenedDerivation => write!(f, "hardened derivation from public key"),
+            Error::InvalidThreshold => write!(f, "invalid multisig threshold"),
+            Error::InvalidHex => write!(f, "invalid hex"),
+            Error::NetworkMismatch => write!(f, "address belongs to another network"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::WitnessProgram(_) => "witness program error",
+            Error::Bip32(_) => "extended public key error",
+            Error::Miniscript(_) => "miniscript error",
+            Error::InvalidCharacter(_) => "invalid descriptor character",
+            Error::InvalidChecksum => "invalid descriptor checksum",
+            Error::InvalidSyntax => "invalid descriptor syntax",
+            Error::UnsupportedFunction(_) => "unsupported descriptor function",
+            Error::InvalidKey => "invalid key expression",
+            Error::HardenedDerivation => "hardened derivation from public key",
+            Error::InvalidThreshold => "invalid multisig threshold",
+            Error::InvalidHex => "invalid hex",
+            Error::NetworkMismatch => "address belongs to another network",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::WitnessProgram(ref e) => Some(e),
+            Error::Bip32(ref e) => Some(e),
+            Error::Miniscript(ref e) => Some(&**e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use taproot::TapLeaf;
+    use AddressKind;
+
+    const BIP84_XPUB: &str = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+
+    #[test]
+    fn checksums() {
+        assert_eq!(
+            checksum("sh(multi(2,[00000000/111'/222]xprvA1RpRA33e1JQ7ifknakTFpgNXPmW2YvmhqLQYMmrj4xJXXWYpDPS3xz7iAxn8L39njGVyuoseXzU6rcxFLJ8HFsTjSyQbLYnMpCqE2VbFWc,xprv9uPDJpEQgRQfDcW7BkF7eTya6RPxXeJCqCJGHuCJ4GiRVLzkTXBAJMu2qaMWPrS7AANYqdq6vcBcBUdJCVVFceUvJFjaPdGZ2y9WACViL4L/0))").unwrap(),
+            "ggrsrxfy"
+        );
+        assert_eq!(
+            checksum("sh(multi(2,[00000000/111'/222]xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL,xpub68NZiKmJWnxxS6aaHmn81bvJeTESw724CRDs6HbuccFQN9Ku14VQrADWgqbhhTHBaohPX4CjNLf9fq9MYo6oDaPPLPxSb7gwQN3ih19Zm4Y/0))").unwrap(),
+            "tjg09x5t"
+        );
+        assert_eq!(checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert_eq!(
+            checksum("raw(\u{e9})"),
+            Err(Error::InvalidCharacter('\u{e9}'))
+        );
+        assert_eq!(
+            Descriptor::parse("raw(deadbeef)#89f8spxn", Network::Bitcoin),
+            Err(Error::InvalidChecksum)
+        );
+        assert_eq!(
+            Descriptor::parse("raw(deadbeef)#89f8spx", Network::Bitcoin),
+            Err(Error::InvalidChecksum)
+        );
+    }
+
+    #[test]
+    fn wpkh() {
+        let s = format!("wpkh([73c5da0a/84'/0'/0']{}/0/*)", BIP84_XPUB);
+        let desc = Descriptor::parse(&s, Network::Bitcoin).unwrap();
+        assert!(desc.is_ranged());
+        let programs = desc.witness_programs(0, 2).unwrap();
+        assert_eq!(
+            programs[0].to_address(),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+        assert_eq!(
+            programs[1].to_address(),
+            "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g"
+        );
+        assert_eq!(desc.to_string_without_checksum(), s);
+        let printed = desc.to_string();
+        assert_eq!(Descriptor::parse(&printed, Network::Bitcoin).unwrap(), desc);
+
+        let desc = Descriptor::parse(&s, Network::Litecoin).unwrap();
+        assert_eq!(
+            desc.witness_program(0).unwrap().network(),
+            Network::Litecoin
+        );
+        assert_eq!(
+            desc.witness_program(0).unwrap().program(),
+            programs[0].program()
+        );
+
+        let key = match *desc.descriptor_type() {
+            DescriptorType::Wpkh(ref key) => key.clone(),
+            _ => unreachable!(),
+        };
+        let origin = key.origin().unwrap();
+        assert_eq!(origin.fingerprint(), [0x73, 0xc5, 0xda, 0x0a]);
+        assert_eq!(origin.path(), &[HARDENED + 84, HARDENED, HARDENED][..]);
+
+        let single = Descriptor::parse(
+            "wpkh(0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798)",
+            Network::Bitcoin,
+        )
+        .unwrap();
+        assert!(!single.is_ranged());
+        assert_eq!(
+            single.witness_program(7).unwrap().to_address(),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+    }
+
+    #[test]
+    fn wsh_multi() {
+        let a = "03a0434d9e47f3c86235477c7b1ae6ae5d3442d49b1943c2b752a68e2a47e247c7";
+        let b = "022f8bde4d1a07209355b4a7250a5c5128e88b84bddc619ab7cba8d569b240efe4";
+        let multi =
+            Descriptor::parse(&format!("wsh(multi(1,{},{}))", a, b), Network::Monacoin).unwrap();
+        let script = multi.witness_script(0).unwrap().unwrap();
+        let mut expected = vec![0x51, 0x21];
+        expected.extend_from_slice(&hex::decode(a).unwrap());
+        expected.push(0x21);
+        expected.extend_from_slice(&hex::decode(b).unwrap());
+        expected.extend_from_slice(&[0x52, 0xae]);
+        assert_eq!(script, expected);
+        let program = multi.witness_program(0).unwrap();
+        assert_eq!(program.kind(), AddressKind::P2wsh);
+        assert_eq!(
+            program,
+            WitnessProgram::p2wsh_from_script(&expected, Network::Monacoin).unwrap()
+        );
+
+        let sorted = Descriptor::parse(
+            &format!("wsh(sortedmulti(1,{},{}))", a, b),
+            Network::Monacoin,
+        )
+        .unwrap();
+        let swapped =
+            Descriptor::parse(&format!("wsh(multi(1,{},{}))", b, a), Network::Monacoin).unwrap();
+        assert_eq!(
+            sorted.witness_program(0).unwrap(),
+            swapped.witness_program(0).unwrap()
+        );
+
+        let ranged = Descriptor::parse(
+            &format!("wsh(sortedmulti(2,{}/0/*,{}))", BIP84_XPUB, a),
+            Network::Bitcoin,
+        )
+        .unwrap();
+        assert!(ranged.is_ranged());
+        assert_ne!(
+            ranged.witness_program(0).unwrap(),
+            ranged.witness_program(1).unwrap()
+        );
+
+        assert_eq!(
+            Descriptor::parse(&format!("wsh(multi(3,{},{}))", a, b), Network::Bitcoin),
+            Err(Error::InvalidThreshold)
+        );
+        assert_eq!(
+            Descriptor::parse(&format!("wsh(multi(0,{}))", a), Network::Bitcoin),
+            Err(Error::InvalidThreshold)
+        );
+        assert_eq!(
+            Descriptor::parse(&format!("wsh(multi(1,{}))", &a[2..]), Network::Bitcoin),
+            Err(Error::InvalidKey)
+        );
+    }
+
+    #[test]
+    fn wsh_miniscript() {
+        let a = "03a0434d9e47f3c86235477c7b1ae6ae5d3442d49b1943c2b752a68e2a47e247c7";
+        let s = format!(
+            "wsh(or_d(pk({}),and_v(v:pk({}/0/*),older(1000))))",
+            a, BIP84_XPUB
+        );
+        let desc = Descriptor::parse(&s, Network::Litecoin).unwrap();
+        assert!(desc.is_ranged());
+        assert_eq!(desc.to_string_without_checksum(), s);
+        let script = desc.witness_script(3).unwrap().unwrap();
+        assert_eq!(
+            desc.witness_program(3).unwrap(),
+            WitnessProgram::p2wsh_from_script(&script, Network::Litecoin).unwrap()
+        );
+        assert_eq!(
+            Descriptor::parse(&format!("wsh(v:pk({}))", a), Network::Litecoin),
+            Err(Error::Miniscript(Box::new(
+                miniscript::Error::InvalidTopLevel
+            )))
+        );
+    }
+
+    #[test]
+    fn tr() {
+        let internal = "cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115";
+        let desc = Descriptor::parse(&format!("tr({})", internal), Network::Bitcoin).unwrap();
+        assert_eq!(
+            desc.witness_program(0).unwrap().to_address(),
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        );
+
+        let a = "a0434d9e47f3c86235477c7b1ae6ae5d3442d49b1943c2b752a68e2a47e247c7";
+        let b = "022f8bde4d1a07209355b4a7250a5c5128e88b84bddc619ab7cba8d569b240efe4";
+        let s = format!("tr({},{{pk({}),pk({})}})", internal, a, b);
+        let desc = Descriptor::parse(&s, Network::Groestlcoin).unwrap();
+        assert_eq!(desc.to_string_without_checksum(), s);
+        let mut leaf_a = vec![0x20];
+        leaf_a.extend_from_slice(&hex::decode(a).unwrap());
+        leaf_a.push(0xac);
+        let mut leaf_b = vec![0x20];
+        leaf_b.extend_from_slice(&hex::decode(&b[2..]).unwrap());
+        leaf_b.push(0xac);
+        let tree = TapTree::branch(
+            TapTree::leaf(TapLeaf::tapscript(leaf_a)),
+            TapTree::leaf(TapLeaf::tapscript(leaf_b)),
+        );
+        let mut internal_key = [0u8; 32];
+        internal_key.copy_from_slice(&hex::decode(internal).unwrap());
+        let output = TaprootOutput::new(internal_key, Some(&tree), Network::Groestlcoin).unwrap();
+        assert_eq!(&desc.witness_program(0).unwrap(), output.witness_program());
+
+        assert_eq!(
+            Descriptor::parse(&format!("tr({},{{pk({})}})", internal, a), Network::Bitcoin),
+            Err(Error::InvalidSyntax)
+        );
+        assert_eq!(
+            Descriptor::parse(&format!("tr({},v:pk({}))", internal, a), Network::Bitcoin),
+            Err(Error::Miniscript(Box::new(
+                miniscript::Error::InvalidTopLevel
+            )))
+        );
+
+        let nested = |depth: usize| {
+            let mut tree = format!("pk({})", a);
+            for _ in 0..depth {
+                tree = format!("{{{},pk({})}}", tree, a);
+            }
+            format!("tr({},{})", internal, tree)
+        };
+        let desc = Descriptor::parse(&nested(TAPROOT_MAX_DEPTH), Network::Bitcoin).unwrap();
+        assert!(desc.witness_program(0).is_ok());
+        for &depth in [TAPROOT_MAX_DEPTH + 1, 1_000].iter() {
+            assert_eq!(
+                Descriptor::parse(&nested(depth), Network::Bitcoin),
+                Err(Error::WitnessProgram(::Error::TaprootTreeTooDeep))
+            );
+        }
+    }
+
+    #[test]
+    fn addr_and_raw() {
+        let desc = Descriptor::parse(
+            "addr(mona1q4kpn6psthgd5ur894auhjj2g02wlgmp8ke08ne)",
+            Network::Monacoin,
+        )
+        .unwrap();
+        assert_eq!(
+            desc.witness_program(0).unwrap().to_address(),
+            "mona1q4kpn6psthgd5ur894auhjj2g02wlgmp8ke08ne"
+        );
+        assert_eq!(
+            Descriptor::parse(desc.to_string().as_str(), Network::Monacoin).unwrap(),
+            desc
+        );
+        assert_eq!(
+            Descriptor::parse(
+                "addr(mona1q4kpn6psthgd5ur894auhjj2g02wlgmp8ke08ne)",
+                Network::Bitcoin
+            ),
+            Err(Error::NetworkMismatch)
+        );
+
+        let desc = Descriptor::parse(
+            "raw(0014751e76e8199196d454941c45d1b3a323f1433bd6)",
+            Network::Bitcoin,
+        )
+        .unwrap();
+        assert_eq!(
+            desc.witness_program(0).unwrap().to_address(),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+        let desc = Descriptor::parse("raw(deadbeef)#89f8spxm", Network::Bitcoin).unwrap();
+        assert_eq!(desc.to_string(), "raw(deadbeef)#89f8spxm");
+        assert_eq!(
+            desc.witness_program(0),
+            Err(Error::WitnessProgram(::Error::ScriptPubkeyInvalidVersion))
+        );
+        assert_eq!(
+            Descriptor::parse("raw(xyz)", Network::Bitcoin),
+            Err(Error::InvalidHex)
+        );
+        assert_eq!(
+            Descriptor::parse("pkh(deadbeef)", Network::Bitcoin),
+            Err(Error::UnsupportedFunction("pkh".to_string()))
+        );
+        assert_eq!(
+            Descriptor::parse("wpkh(", Network::Bitcoin),
+            Err(Error::InvalidSyntax)
+        );
+    }
+
+    #[test]
+    fn hardened_keys() {
+        assert_eq!(
+            Descriptor::parse(&format!("wpkh({}/0'/*)", BIP84_XPUB), Network::Bitcoin),
+            Err(Error::HardenedDerivation)
+        );
+        assert_eq!(
+            Descriptor::parse(&format!("wpkh({}/0/*')", BIP84_XPUB), Network::Bitcoin),
+            Err(Error::HardenedDerivation)
+        );
+        assert_eq!(
+            Descriptor::parse(&format!("wpkh({}/*/0)", BIP84_XPUB), Network::Bitcoin),
+            Err(Error::InvalidSyntax)
+        );
+    }
+}
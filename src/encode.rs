@@ -0,0 +1,182 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Bitcoin consensus encoding primitives
+
+/// A cursor over consensus-encoded bytes
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Starts reading at the beginning of `data`
+    pub fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, pos: 0 }
+    }
+
+    /// Number of bytes left to read
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    /// Returns the next byte without consuming it
+    pub fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).cloned()
+    }
+
+    /// Consumes the next `n` bytes, or returns `None` if there are fewer left
+    pub fn read_bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if n > self.remaining() {
+            return None;
+        }
+        let bytes = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Some(bytes)
+    }
+
+    /// Consumes a single byte
+    pub fn read_u8(&mut self) -> Option<u8> {
+        self.read_bytes(1).map(|b| b[0])
+    }
+
+    /// Consumes a little-endian `u16`
+    pub fn read_u16(&mut self) -> Option<u16> {
+        let mut buf = [0u8; 2];
+        buf.copy_from_slice(self.read_bytes(2)?);
+        Some(u16::from_le_bytes(buf))
+    }
+
+    /// Consumes a little-endian `u32`
+    pub fn read_u32(&mut self) -> Option<u32> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.read_bytes(4)?);
+        Some(u32::from_le_bytes(buf))
+    }
+
+    /// Consumes a little-endian `u64`
+    pub fn read_u64(&mut self) -> Option<u64> {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(self.read_bytes(8)?);
+        Some(u64::from_le_bytes(buf))
+    }
+
+    /// Consumes a 32-byte hash
+    pub fn read_hash(&mut self) -> Option<[u8; 32]> {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(self.read_bytes(32)?);
+        Some(hash)
+    }
+
+    /// Consumes a compact size integer, rejecting non-canonical encodings
+    pub fn read_compact_size(&mut self) -> Option<u64> {
+        let n = match self.read_u8()? {
+            0xfd => {
+                let n = u64::from(self.read_u16()?);
+                if n < 0xfd {
+                    return None;
+                }
+                n
+            }
+            0xfe => {
+                let n = u64::from(self.read_u32()?);
+                if n <= 0xffff {
+                    return None;
+                }
+                n
+            }
+            0xff => {
+                let n = self.read_u64()?;
+                if n <= 0xffff_ffff {
+                    return None;
+                }
+                n
+            }
+            n => u64::from(n),
+        };
+        Some(n)
+    }
+
+    /// Consumes a compact size length followed by that many bytes
+    pub fn read_var_bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.read_compact_size()?;
+        if len > self.remaining() as u64 {
+            return None;
+        }
+        self.read_bytes(len as usize)
+    }
+}
+
+/// Appends a Bitcoin compact size integer
+pub fn write_compact_size(out: &mut Vec<u8>, n: u64) {
+    if n < 0xfd {
+        out.push(n as u8);
+    } else if n <= 0xffff {
+        out.push(0xfd);
+        out.extend_from_slice(&(n as u16).to_le_bytes());
+    } else if n <= 0xffff_ffff {
+        out.push(0xfe);
+        out.extend_from_slice(&(n as u32).to_le_bytes());
+    } else {
+        out.push(0xff);
+        out.extend_from_slice(&n.to_le_bytes());
+    }
+}
+
+/// Appends a compact size length followed by `data`
+pub fn write_var_bytes(out: &mut Vec<u8>, data: &[u8]) {
+    write_compact_size(out, data.len() as u64);
+    out.extend_from_slice(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_size_round_trip() {
+        for &n in &[
+            0u64,
+            0xfc,
+            0xfd,
+            0xffff,
+            0x1_0000,
+            0xffff_ffff,
+            0x1_0000_0000,
+        ] {
+            let mut out = Vec::new();
+            write_compact_size(&mut out, n);
+            let mut reader = Reader::new(&out);
+            assert_eq!(reader.read_compact_size(), Some(n));
+            assert_eq!(reader.remaining(), 0);
+        }
+    }
+
+    #[test]
+    fn non_canonical_compact_size() {
+        assert_eq!(Reader::new(&[0xfd, 0xfc, 0x00]).read_compact_size(), None);
+        assert_eq!(
+            Reader::new(&[0xfe, 0xff, 0xff, 0, 0]).read_compact_size(),
+            None
+        );
+        assert_eq!(Reader::new(&[0xfd, 0x01]).read_compact_size(), None);
+        assert_eq!(Reader::new(&[0x03, 0xaa]).read_var_bytes(), None);
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Grøstl-512 hash function
+//!
+//! Groestlcoin uses Grøstl instead of SHA256 for its base58 checksums. Only
+//! the 512-bit variant is needed, implemented here from the final round
+//! specification of the SHA-3 competition.
+
+/// Number of columns of the 1024-bit state
+const COLUMNS: usize = 16;
+/// Number of rounds of the P and Q permutations
+const ROUNDS: u8 = 14;
+/// Size of a message block in bytes
+const BLOCK_SIZE: usize = 128;
+
+/// Rotation applied to each row by ShiftBytes in P
+const SHIFT_P: [usize; 8] = [0, 1, 2, 3, 4, 5, 6, 11];
+/// Rotation applied to each row by ShiftBytes in Q
+const SHIFT_Q: [usize; 8] = [1, 3, 5, 11, 0, 2, 4, 6];
+/// First row of the circulant MixBytes matrix
+const MIX: [u8; 8] = [2, 2, 3, 4, 5, 3, 5, 7];
+
+/// The AES S-box
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+/// 1024-bit state, stored column by column as in the byte mapping of the spec
+type State = [[u8; 8]; COLUMNS];
+
+/// Multiplication in GF(2^8) modulo the AES polynomial
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Applies the P (`q == false`) or Q (`q == true`) permutation
+fn permute(state: &mut State, q: bool) {
+    for round in 0..ROUNDS {
+        // AddRoundConstant
+        for (column, bytes) in state.iter_mut().enumerate() {
+            let c = ((column as u8) << 4) ^ round;
+            if q {
+                for byte in bytes.iter_mut().take(7) {
+                    *byte ^= 0xff;
+                }
+                bytes[7] ^= 0xff ^ c;
+            } else {
+                bytes[0] ^= c;
+            }
+        }
+        // SubBytes
+        for bytes in state.iter_mut() {
+            for byte in bytes.iter_mut() {
+                *byte = SBOX[*byte as usize];
+            }
+        }
+        // ShiftBytes
+        let shift = if q { &SHIFT_Q } else { &SHIFT_P };
+        let previous = *state;
+        for (column, bytes) in state.iter_mut().enumerate() {
+            for (row, byte) in bytes.iter_mut().enumerate() {
+                *byte = previous[(column + shift[row]) % COLUMNS][row];
+            }
+        }
+        // MixBytes
+        for bytes in state.iter_mut() {
+            let previous = *bytes;
+            for (row, byte) in bytes.iter_mut().enumerate() {
+                *byte = (0..8).fold(0, |acc, k| {
+                    acc ^ gf_mul(MIX[(k + 8 - row) % 8], previous[k])
+                });
+            }
+        }
+    }
+}
+
+/// Loads a message block into a state
+fn load(block: &[u8]) -> State {
+    let mut state = [[0u8; 8]; COLUMNS];
+    for (column, bytes) in state.iter_mut().enumerate() {
+        bytes.copy_from_slice(&block[column * 8..column * 8 + 8]);
+    }
+    state
+}
+
+/// XORs `other` into `state`
+fn xor(state: &mut State, other: &State) {
+    for (bytes, other_bytes) in state.iter_mut().zip(other.iter()) {
+        for (byte, other_byte) in bytes.iter_mut().zip(other_bytes.iter()) {
+            *byte ^= *other_byte;
+        }
+    }
+}
+
+/// Grøstl-512 digest of `data`
+pub fn groestl512(data: &[u8]) -> [u8; 64] {
+    // Pad with a single 1 bit, zeros and the 64-bit block count
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % BLOCK_SIZE != BLOCK_SIZE - 8 {
+        message.push(0);
+    }
+    let blocks = (message.len() / BLOCK_SIZE + 1) as u64;
+    message.extend_from_slice(&blocks.to_be_bytes());
+
+    // The initial value encodes the output length in bits
+    let mut h = [[0u8; 8]; COLUMNS];
+    h[COLUMNS - 1][6] = 0x02;
+
+    for block in message.chunks(BLOCK_SIZE) {
+        let m = load(block);
+        let mut p = h;
+        xor(&mut p, &m);
+        permute(&mut p, false);
+        let mut q = m;
+        permute(&mut q, true);
+        xor(&mut h, &p);
+        xor(&mut h, &q);
+    }
+
+    // Output transformation: truncate P(h) xor h to the last 512 bits
+    let mut p = h;
+    permute(&mut p, false);
+    xor(&mut p, &h);
+    let mut out = [0u8; 64];
+    for (column, bytes) in p[COLUMNS / 2..].iter().enumerate() {
+        out[column * 8..column * 8 + 8].copy_from_slice(bytes);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bip32::{decode_check, Error};
+    use hash::HashFunction;
+    use hex;
+
+    #[test]
+    fn empty_message() {
+        assert_eq!(
+            hex::encode(&groestl512(b"")),
+            "6d3ad29d279110eef3adbd66de2a0345a77baede1557f5d099fce0c03d6dc2ba\
+             8e6d4a6633dfbd66053c20faa87d1a11f39a7fbe4a6c2f009801370308fc4ad8"
+        );
+    }
+    #[test]
+    fn known_answers() {
+        assert_eq!(
+            hex::encode(&groestl512(b"The quick brown fox jumps over the lazy dog")),
+            "badc1f70ccd69e0cf3760c3f93884289da84ec13c70b3d12a53a7a8a4a513f99\
+             715d46288f55e1dbf926e6d084a0538e4eebfc91cf2b21452921ccde9131718d"
+        );
+    }
+
+    #[test]
+    fn groestlcoin_checksum() {
+        // A Groestlcoin mainnet P2PKH address
+        let address = "FfgZPEfmvou5VxZRnTbRjPKhgVsrx7Qjq9";
+        let data = decode_check(address, HashFunction::DoubleGroestl512).unwrap();
+        assert_eq!(data.len(), 21);
+        assert_eq!(data[0], 36);
+        assert_eq!(
+            decode_check(address, HashFunction::DoubleSha256),
+            Err(Error::InvalidChecksum)
+        );
+    }
+}
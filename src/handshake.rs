@@ -0,0 +1,378 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Handshake output serialization
+//!
+//! Handshake outputs are not locked by a script. Each output holds an
+//! address, serialized as its version and hash, together with a covenant
+//! that restricts how the output may be spent in the name auctions:
+//!
+//! ```text
+//! value: u64 | version: u8 | hash length: u8 | hash | covenant type: u8 | items
+//! ```
+
+use std::convert::TryFrom;
+use std::{error, fmt};
+
+use constants::Network;
+use encode::{write_compact_size, write_var_bytes, Reader};
+use {WitnessProgram, WitnessVersion};
+
+/// Kind of restriction a covenant places on an output
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum CovenantType {
+    /// A plain payment
+    None,
+    /// Claim of a reserved name
+    Claim,
+    /// Opening of an auction
+    Open,
+    /// Blinded bid
+    Bid,
+    /// Reveal of a bid
+    Reveal,
+    /// Refund of a losing bid
+    Redeem,
+    /// Registration of a won name
+    Register,
+    /// Update of name data
+    Update,
+    /// Renewal of a name
+    Renew,
+    /// Start of a name transfer
+    Transfer,
+    /// Completion of a name transfer
+    Finalize,
+    /// Revocation of a name
+    Revoke,
+    /// A type reserved for future soft forks
+    Unknown(u8),
+}
+
+impl CovenantType {
+    /// Returns the type for its wire value
+    pub fn from_u8(n: u8) -> CovenantType {
+        match n {
+            0 => CovenantType::None,
+            1 => CovenantType::Claim,
+            2 => CovenantType::Open,
+            3 => CovenantType::Bid,
+            4 => CovenantType::Reveal,
+            5 => CovenantType::Redeem,
+            6 => CovenantType::Register,
+            7 => CovenantType::Update,
+            8 => CovenantType::Renew,
+            9 => CovenantType::Transfer,
+            10 => CovenantType::Finalize,
+            11 => CovenantType::Revoke,
+            n => CovenantType::Unknown(n),
+        }
+    }
+
+    /// Wire value of the type
+    pub fn to_u8(self) -> u8 {
+        match self {
+            CovenantType::None => 0,
+            CovenantType::Claim => 1,
+            CovenantType::Open => 2,
+            CovenantType::Bid => 3,
+            CovenantType::Reveal => 4,
+            CovenantType::Redeem => 5,
+            CovenantType::Register => 6,
+            CovenantType::Update => 7,
+            CovenantType::Renew => 8,
+            CovenantType::Transfer => 9,
+            CovenantType::Finalize => 10,
+            CovenantType::Revoke => 11,
+            CovenantType::Unknown(n) => n,
+        }
+    }
+}
+
+/// A covenant attached to a Handshake output
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct Covenant {
+    covenant_type: CovenantType,
+    items: Vec<Vec<u8>>,
+}
+
+impl Covenant {
+    /// Creates a covenant from its type and data items
+    pub fn new(covenant_type: CovenantType, items: Vec<Vec<u8>>) -> Covenant {
+        Covenant {
+            covenant_type,
+            items,
+        }
+    }
+
+    /// The covenant of a plain payment
+    pub fn none() -> Covenant {
+        Covenant::new(CovenantType::None, Vec::new())
+    }
+
+    /// Kind of covenant
+    pub fn covenant_type(&self) -> CovenantType {
+        self.covenant_type
+    }
+
+    /// Data items, such as the name hash and height
+    pub fn items(&self) -> &[Vec<u8>] {
+        &self.items
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.push(self.covenant_type.to_u8());
+        write_compact_size(out, self.items.len() as u64);
+        for item in &self.items {
+            write_var_bytes(out, item);
+        }
+    }
+}
+
+/// A Handshake transaction output
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct Output {
+    value: u64,
+    address: WitnessProgram,
+    covenant: Covenant,
+}
+
+impl Output {
+    /// Creates an output paying `value` to a Handshake address
+    pub fn new(value: u64, address: WitnessProgram, covenant: Covenant) -> Result<Output, Error> {
+        check_network(address.network())?;
+        Ok(Output {
+            value,
+            address,
+            covenant,
+        })
+    }
+
+    /// Parses a serialized output
+    pub fn parse(data: &[u8], network: Network) -> Result<Output, Error> {
+        check_network(network)?;
+        let mut reader = Reader::new(data);
+        let value = reader.read_u64().ok_or(Error::UnexpectedEnd)?;
+        let address = read_address(&mut reader, network)?;
+        let covenant_type = CovenantType::from_u8(reader.read_u8().ok_or(Error::UnexpectedEnd)?);
+        let count = reader.read_compact_size().ok_or(Error::UnexpectedEnd)?;
+        if count > reader.remaining() as u64 {
+            return Err(Error::UnexpectedEnd);
+        }
+        let items = (0..count)
+            .map(|_| {
+                reader
+                    .read_var_bytes()
+                    .map(|item| item.to_vec())
+                    .ok_or(Error::UnexpectedEnd)
+            })
+            .collect::<Result<_, _>>()?;
+        if reader.remaining() != 0 {
+            return Err(Error::TrailingData);
+        }
+        Ok(Output {
+            value,
+            address,
+            covenant: Covenant::new(covenant_type, items),
+        })
+    }
+
+    /// Serializes the output
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = self.value.to_le_bytes().to_vec();
+        out.extend_from_slice(&serialize_address_unchecked(&self.address));
+        self.covenant.write(&mut out);
+        out
+    }
+
+    /// Amount in dollarydoos
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    /// Address paid
+    pub fn address(&self) -> &WitnessProgram {
+        &self.address
+    }
+
+    /// Covenant restricting the output
+    pub fn covenant(&self) -> &Covenant {
+        &self.covenant
+    }
+}
+
+/// Serializes a Handshake address as its version, hash length and hash
+pub fn serialize_address(address: &WitnessProgram) -> Result<Vec<u8>, Error> {
+    check_network(address.network())?;
+    Ok(serialize_address_unchecked(address))
+}
+
+/// Parses a serialized Handshake address
+pub fn parse_address(data: &[u8], network: Network) -> Result<WitnessProgram, Error> {
+    check_network(network)?;
+    let mut reader = Reader::new(data);
+    let address = read_address(&mut reader, network)?;
+    if reader.remaining() != 0 {
+        return Err(Error::TrailingData);
+    }
+    Ok(address)
+}
+
+fn serialize_address_unchecked(address: &WitnessProgram) -> Vec<u8> {
+    let mut out = vec![address.version().to_u8(), address.program().len() as u8];
+    out.extend_from_slice(address.program());
+    out
+}
+
+fn read_address(reader: &mut Reader, network: Network) -> Result<WitnessProgram, Error> {
+    let version = reader.read_u8().ok_or(Error::UnexpectedEnd)?;
+    let len = reader.read_u8().ok_or(Error::UnexpectedEnd)?;
+    let hash = reader
+        .read_bytes(len as usize)
+        .ok_or(Error::UnexpectedEnd)?;
+    let version = WitnessVersion::try_from(version)?;
+    Ok(WitnessProgram::new(version, hash.to_vec(), network)?)
+}
+
+fn check_network(network: Network) -> Result<(), Error> {
+    match network {
+        Network::Handshake | Network::HandshakeTestnet => Ok(()),
+        network => Err(Error::NotHandshake(network)),
+    }
+}
+
+/// Error types for Handshake serialization
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// The address version or hash is invalid
+    WitnessProgram(::Error),
+    /// The network is not Handshake
+    NotHandshake(Network),
+    /// The data ends in the middle of the output
+    UnexpectedEnd,
+    /// Data remains after the output
+    TrailingData,
+}
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::WitnessProgram(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::WitnessProgram(ref e) => write!(f, "{}", e),
+            Error::NotHandshake(network) => write!(f, "{:?} is not Handshake", network),
+            Error::UnexpectedEnd => write!(f, "unexpected end of output data"),
+            Error::TrailingData => write!(f, "trailing data after output"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::WitnessProgram(_) => "witness program error",
+            Error::NotHandshake(_) => "network is not Handshake",
+            Error::UnexpectedEnd => "unexpected end of output data",
+            Error::TrailingData => "trailing data after output",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::WitnessProgram(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+
+    const KEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    fn address() -> WitnessProgram {
+        WitnessProgram::p2wpkh_from_pubkey(&hex::decode(KEY).unwrap(), Network::Handshake).unwrap()
+    }
+
+    #[test]
+    fn address_round_trip() {
+        let address = address();
+        let data = serialize_address(&address).unwrap();
+        assert_eq!(data.len(), 22);
+        assert_eq!(&data[..2], &[0x00, 0x14]);
+        assert_eq!(
+            parse_address(&data, Network::Handshake),
+            Ok(address.clone())
+        );
+        assert_eq!(address.to_scriptpubkey(), Err(::Error::ScriptNotSupported));
+        assert_eq!(
+            WitnessProgram::from_scriptpubkey(&data, Network::Handshake),
+            Err(::Error::ScriptNotSupported)
+        );
+        assert_eq!(
+            parse_address(&data, Network::Bitcoin),
+            Err(Error::NotHandshake(Network::Bitcoin))
+        );
+    }
+
+    #[test]
+    fn covenant_outputs() {
+        let name_hash = vec![0xab; 32];
+        let covenant = Covenant::new(
+            CovenantType::Open,
+            vec![name_hash, 0u32.to_le_bytes().to_vec(), b"example".to_vec()],
+        );
+        let output = Output::new(0, address(), covenant.clone()).unwrap();
+        let data = output.serialize();
+        assert_eq!(data[8 + 22], 2);
+        assert_eq!(data[8 + 23], 3);
+        let parsed = Output::parse(&data, Network::Handshake).unwrap();
+        assert_eq!(parsed, output);
+        assert_eq!(parsed.covenant().covenant_type(), CovenantType::Open);
+        assert_eq!(parsed.covenant().items()[2], b"example".to_vec());
+
+        let plain = Output::new(2_000_000, address(), Covenant::none()).unwrap();
+        assert_eq!(plain.serialize().len(), 8 + 22 + 2);
+        assert_eq!(
+            Output::parse(&data[..data.len() - 1], Network::Handshake),
+            Err(Error::UnexpectedEnd)
+        );
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert_eq!(
+            Output::parse(&trailing, Network::Handshake),
+            Err(Error::TrailingData)
+        );
+
+        let bitcoin =
+            WitnessProgram::from_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").unwrap();
+        assert_eq!(
+            Output::new(1, bitcoin, Covenant::none()),
+            Err(Error::NotHandshake(Network::Bitcoin))
+        );
+        assert_eq!(CovenantType::from_u8(42), CovenantType::Unknown(42));
+    }
+}
@@ -0,0 +1,145 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Hash functions used to derive witness programs
+
+use blake2::digest::consts::{U20, U32};
+use blake2::Blake2b;
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+use sha3::Sha3_256;
+
+use groestl::groestl512;
+
+/// A hash function used to derive programs or checksums on some network
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum HashFunction {
+    /// Single SHA256
+    Sha256,
+    /// Double SHA256
+    DoubleSha256,
+    /// RIPEMD160 of SHA256
+    Hash160,
+    /// BLAKE2b with a 160-bit output
+    Blake2b160,
+    /// BLAKE2b with a 256-bit output
+    Blake2b256,
+    /// SHA3-256
+    Sha3_256,
+    /// Double Grøstl-512
+    DoubleGroestl512,
+}
+
+impl HashFunction {
+    /// Hashes `data`
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            HashFunction::Sha256 => sha256(data).to_vec(),
+            HashFunction::DoubleSha256 => sha256d(data).to_vec(),
+            HashFunction::Hash160 => hash160(data).to_vec(),
+            HashFunction::Blake2b160 => Blake2b::<U20>::digest(data).to_vec(),
+            HashFunction::Blake2b256 => Blake2b::<U32>::digest(data).to_vec(),
+            HashFunction::Sha3_256 => Sha3_256::digest(data).to_vec(),
+            HashFunction::DoubleGroestl512 => groestl512(&groestl512(data)).to_vec(),
+        }
+    }
+
+    /// Length of the digest in bytes
+    pub fn output_len(self) -> usize {
+        match self {
+            HashFunction::Hash160 | HashFunction::Blake2b160 => 20,
+            HashFunction::DoubleGroestl512 => 64,
+            _ => 32,
+        }
+    }
+}
+
+/// Single SHA256
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&Sha256::digest(data));
+    out
+}
+
+/// Double SHA256
+pub fn sha256d(data: &[u8]) -> [u8; 32] {
+    sha256(&sha256(data))
+}
+
+/// RIPEMD160 of SHA256, as used for public key hashes
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    let mut out = [0u8; 20];
+    out.copy_from_slice(&Ripemd160::digest(Sha256::digest(data)));
+    out
+}
+
+/// BIP-0340 tagged hash: `SHA256(SHA256(tag) || SHA256(tag) || data)`
+pub fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    let mut engine = Sha256::new();
+    engine.update(tag_hash);
+    engine.update(tag_hash);
+    engine.update(data);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&engine.finalize());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+
+    #[test]
+    fn empty_message() {
+        let pairs: Vec<(HashFunction, &str)> = vec![
+            (
+                HashFunction::Sha256,
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                HashFunction::DoubleSha256,
+                "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456",
+            ),
+            (
+                HashFunction::Hash160,
+                "b472a266d0bd89c13706a4132ccfb16f7c3b9fcb",
+            ),
+            (
+                HashFunction::Blake2b160,
+                "3345524abf6bbe1809449224b5972c41790b6cf2",
+            ),
+            (
+                HashFunction::Blake2b256,
+                "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8",
+            ),
+            (
+                HashFunction::Sha3_256,
+                "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a",
+            ),
+        ];
+        for (function, digest) in pairs {
+            let hash = function.digest(b"");
+            assert_eq!(hash.len(), function.output_len());
+            assert_eq!(hex::encode(&hash), digest);
+        }
+        assert_eq!(HashFunction::DoubleGroestl512.digest(b"").len(), 64);
+    }
+}
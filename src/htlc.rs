@@ -0,0 +1,611 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Hashed timelock contracts for atomic swaps
+//!
+//! An HTLC pays to a recipient who reveals the preimage of a hash, or back
+//! to the sender once a timeout passes:
+//!
+//! ```text
+//! OP_IF
+//!     OP_SIZE 32 OP_EQUALVERIFY OP_SHA256 <hash> OP_EQUALVERIFY <recipient>
+//! OP_ELSE
+//!     <timeout> OP_CHECKLOCKTIMEVERIFY OP_DROP <refund>
+//! OP_ENDIF
+//! OP_CHECKSIG
+//! ```
+//!
+//! The preimage size check keeps a preimage that is valid on one chain
+//! from being rejected on the other. An atomic swap is two such contracts
+//! on two networks sharing the hash, the one created first timing out last.
+
+use std::{error, fmt};
+
+use constants::Network;
+use hash::{hash160, sha256};
+use script::{self, instructions, Instruction, Timelock};
+use {check_compressed_pubkey, WitnessProgram};
+
+/// Lock times from this value on are Unix timestamps rather than heights
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+/// Required preimage length
+pub const PREIMAGE_SIZE: usize = 32;
+
+/// The hash a recipient must reveal the preimage of
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum HashLock {
+    /// `OP_SHA256`
+    Sha256([u8; 32]),
+    /// `OP_HASH160`
+    Hash160([u8; 20]),
+}
+
+impl HashLock {
+    /// Whether `preimage` unlocks this hash
+    pub fn verify(&self, preimage: &[u8]) -> bool {
+        if preimage.len() != PREIMAGE_SIZE {
+            return false;
+        }
+        match *self {
+            HashLock::Sha256(ref h) => sha256(preimage) == *h,
+            HashLock::Hash160(ref h) => hash160(preimage) == *h,
+        }
+    }
+
+    fn opcode(&self) -> u8 {
+        match *self {
+            HashLock::Sha256(_) => script::OP_SHA256,
+            HashLock::Hash160(_) => script::OP_HASH160,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match *self {
+            HashLock::Sha256(ref h) => h,
+            HashLock::Hash160(ref h) => h,
+        }
+    }
+}
+
+/// A hashed timelock contract
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct Htlc {
+    /// Hash the recipient must reveal the preimage of
+    hash_lock: HashLock,
+    /// Compressed key of the recipient
+    recipient: Vec<u8>,
+    /// Compressed key the funds return to after the timeout
+    refund: Vec<u8>,
+    /// Timeout of the refund branch
+    timeout: Timelock,
+}
+
+impl Htlc {
+    /// Builds a contract, checking the keys and the timeout
+    pub fn new(
+        hash_lock: HashLock,
+        recipient: Vec<u8>,
+        refund: Vec<u8>,
+        timeout: Timelock,
+    ) -> Result<Htlc, Error> {
+        check_compressed_pubkey(&recipient)?;
+        check_compressed_pubkey(&refund)?;
+        timeout.validate()?;
+        Ok(Htlc {
+            hash_lock,
+            recipient,
+            refund,
+            timeout,
+        })
+    }
+
+    /// Encodes the witness script
+    pub fn to_script(&self) -> Vec<u8> {
+        let mut s = vec![script::OP_IF, script::OP_SIZE];
+        script::push_int(&mut s, PREIMAGE_SIZE as i64);
+        s.extend_from_slice(&[script::OP_EQUALVERIFY, self.hash_lock.opcode()]);
+        script::push_slice(&mut s, self.hash_lock.bytes());
+        s.push(script::OP_EQUALVERIFY);
+        script::push_slice(&mut s, &self.recipient);
+        s.push(script::OP_ELSE);
+        let timeout = match self.timeout {
+            Timelock::Absolute(n) | Timelock::Relative(n) => n,
+        };
+        script::push_int(&mut s, i64::from(timeout));
+        s.extend_from_slice(&[self.timeout.opcode(), script::OP_DROP]);
+        script::push_slice(&mut s, &self.refund);
+        s.extend_from_slice(&[script::OP_ENDIF, script::OP_CHECKSIG]);
+        s
+    }
+
+    /// Decodes an HTLC witness script, such as one taken from a spending
+    /// input's witness
+    ///
+    /// Scripts that are not minimally encoded are rejected.
+    pub fn from_script(s: &[u8]) -> Result<Htlc, Error> {
+        use script::Instruction::*;
+
+        let ins = instructions(s)?;
+        let number = |i: &Instruction| i.number().filter(|&n| n > 0 && n <= i64::from(u32::MAX));
+        let htlc = match *ins {
+            [Op(script::OP_IF), Op(script::OP_SIZE), ref size, Op(script::OP_EQUALVERIFY), Op(hash_op), Push(hash), Op(script::OP_EQUALVERIFY), Push(recipient), Op(script::OP_ELSE), ref timeout, Op(lock_op), Op(script::OP_DROP), Push(refund), Op(script::OP_ENDIF), Op(script::OP_CHECKSIG)] =>
+            {
+                if size.number() != Some(PREIMAGE_SIZE as i64) {
+                    return Err(Error::NotHtlc);
+                }
+                let hash_lock = match (hash_op, hash.len()) {
+                    (script::OP_SHA256, 32) => {
+                        let mut h = [0u8; 32];
+                        h.copy_from_slice(hash);
+                        HashLock::Sha256(h)
+                    }
+                    (script::OP_HASH160, 20) => {
+                        let mut h = [0u8; 20];
+                        h.copy_from_slice(hash);
+                        HashLock::Hash160(h)
+                    }
+                    _ => return Err(Error::NotHtlc),
+                };
+                let n = number(timeout).ok_or(Error::NotHtlc)? as u32;
+                let timeout = match lock_op {
+                    script::OP_CHECKLOCKTIMEVERIFY => Timelock::Absolute(n),
+                    script::OP_CHECKSEQUENCEVERIFY => Timelock::Relative(n),
+                    _ => return Err(Error::NotHtlc),
+                };
+                Htlc::new(hash_lock, recipient.to_vec(), refund.to_vec(), timeout)?
+            }
+            _ => return Err(Error::NotHtlc),
+        };
+        if htlc.to_script() != s {
+            return Err(Error::Script(script::Error::NonMinimalPush));
+        }
+        Ok(htlc)
+    }
+
+    /// P2WSH program paying to the contract on a network
+    pub fn witness_program(&self, network: Network) -> Result<WitnessProgram, Error> {
+        Ok(WitnessProgram::p2wsh_from_script(
+            &self.to_script(),
+            network,
+        )?)
+    }
+
+    /// Hash the recipient must reveal the preimage of
+    pub fn hash_lock(&self) -> HashLock {
+        self.hash_lock
+    }
+
+    /// Compressed key of the recipient
+    pub fn recipient(&self) -> &[u8] {
+        &self.recipient
+    }
+
+    /// Compressed key the funds return to after the timeout
+    pub fn refund(&self) -> &[u8] {
+        &self.refund
+    }
+
+    /// Timeout of the refund branch
+    pub fn timeout(&self) -> Timelock {
+        self.timeout
+    }
+}
+
+/// One side of an atomic swap: a contract on a network
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct SwapLeg {
+    /// Network the contract is funded on
+    network: Network,
+    /// The contract
+    htlc: Htlc,
+    /// P2WSH program of the contract
+    program: WitnessProgram,
+}
+
+impl SwapLeg {
+    /// Places a contract on a network, which must support P2WSH outputs
+    pub fn new(network: Network, htlc: Htlc) -> Result<SwapLeg, Error> {
+        let program = htlc.witness_program(network)?;
+        Ok(SwapLeg {
+            network,
+            htlc,
+            program,
+        })
+    }
+
+    /// Network the contract is funded on
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// The contract
+    pub fn htlc(&self) -> &Htlc {
+        &self.htlc
+    }
+
+    /// Witness script of the contract
+    pub fn witness_script(&self) -> Vec<u8> {
+        self.htlc.to_script()
+    }
+
+    /// P2WSH program of the contract on its network
+    pub fn witness_program(&self) -> &WitnessProgram {
+        &self.program
+    }
+
+    /// Address of the contract on its network
+    pub fn address(&self) -> String {
+        self.program.to_address()
+    }
+}
+
+/// A pair of contracts forming an atomic swap
+///
+/// The initiator knows the preimage and funds the first contract; the
+/// participant funds the second one, which must time out at least
+/// `margin` seconds earlier so that the initiator's claim, which reveals
+/// the preimage, leaves the participant time to claim the first contract.
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct AtomicSwap {
+    /// Contract funded by the initiator
+    initiator: SwapLeg,
+    /// Contract funded by the participant
+    participant: SwapLeg,
+}
+
+impl AtomicSwap {
+    /// Builds both contracts of a swap between two parties
+    ///
+    /// Each party is given as its compressed key, the network it funds its
+    /// contract on and the contract's timeout as a Unix timestamp. The
+    /// initiator's contract pays the participant and refunds the initiator,
+    /// and the participant's the other way round.
+    pub fn from_parties(
+        hash_lock: HashLock,
+        initiator: (Vec<u8>, Network, u32),
+        participant: (Vec<u8>, Network, u32),
+        margin: u32,
+    ) -> Result<AtomicSwap, Error> {
+        let (initiator_key, initiator_network, initiator_timeout) = initiator;
+        let (participant_key, participant_network, participant_timeout) = participant;
+        let initiator = SwapLeg::new(
+            initiator_network,
+            Htlc::new(
+                hash_lock,
+                participant_key.clone(),
+                initiator_key.clone(),
+                Timelock::Absolute(initiator_timeout),
+            )?,
+        )?;
+        let participant = SwapLeg::new(
+            participant_network,
+            Htlc::new(
+                hash_lock,
+                initiator_key,
+                participant_key,
+                Timelock::Absolute(participant_timeout),
+            )?,
+        )?;
+        AtomicSwap::new(initiator, participant, margin)
+    }
+
+    /// Pairs two contracts, checking the parties, the hashes and the
+    /// timeout ordering
+    ///
+    /// Each contract must pay the party the other one refunds, and the two
+    /// parties must differ. Block heights are not comparable between
+    /// chains, so both timeouts must be absolute Unix timestamps.
+    pub fn new(initiator: SwapLeg, participant: SwapLeg, margin: u32) -> Result<AtomicSwap, Error> {
+        if initiator.htlc.recipient != participant.htlc.refund
+            || initiator.htlc.refund != participant.htlc.recipient
+            || initiator.htlc.recipient == initiator.htlc.refund
+        {
+            return Err(Error::PartyMismatch);
+        }
+        if initiator.htlc.hash_lock != participant.htlc.hash_lock {
+            return Err(Error::HashMismatch);
+        }
+        match (initiator.htlc.timeout, participant.htlc.timeout) {
+            (Timelock::Absolute(first), Timelock::Absolute(second))
+                if first >= LOCKTIME_THRESHOLD && second >= LOCKTIME_THRESHOLD =>
+            {
+                if first < second.saturating_add(margin) {
+                    return Err(Error::UnsafeTimeouts);
+                }
+            }
+            _ => return Err(Error::IncomparableTimeouts),
+        }
+        Ok(AtomicSwap {
+            initiator,
+            participant,
+        })
+    }
+
+    /// Contract funded by the initiator
+    pub fn initiator(&self) -> &SwapLeg {
+        &self.initiator
+    }
+
+    /// Contract funded by the participant
+    pub fn participant(&self) -> &SwapLeg {
+        &self.participant
+    }
+}
+
+/// Error types for hashed timelock contracts
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// A public key is invalid
+    WitnessProgram(::Error),
+    /// The script could not be parsed or the timeout is out of range
+    Script(script::Error),
+    /// The script is not an HTLC
+    NotHtlc,
+    /// The two contracts of a swap do not pay each other's refund key
+    PartyMismatch,
+    /// The two contracts of a swap lock different hashes
+    HashMismatch,
+    /// The initiator's contract does not time out late enough after the
+    /// participant's
+    UnsafeTimeouts,
+    /// A timeout is not an absolute timestamp
+    IncomparableTimeouts,
+}
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::WitnessProgram(e)
+    }
+}
+
+impl From<script::Error> for Error {
+    fn from(e: script::Error) -> Error {
+        match e {
+            script::Error::WitnessProgram(e) => Error::WitnessProgram(e),
+            e => Error::Script(e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::WitnessProgram(ref e) => write!(f, "{}", e),
+            Error::Script(ref e) => write!(f, "{}", e),
+            Error::NotHtlc => write!(f, "script is not an HTLC"),
+            Error::PartyMismatch => write!(f, "swap contracts do not mirror each other"),
+            Error::HashMismatch => write!(f, "swap contracts lock different hashes"),
+            Error::UnsafeTimeouts => write!(f, "initiator contract times out too early"),
+            Error::IncomparableTimeouts => write!(f, "swap timeouts are not timestamps"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::WitnessProgram(_) => "witness program error",
+            Error::Script(_) => "script error",
+            Error::NotHtlc => "script is not an HTLC",
+            Error::PartyMismatch => "swap contracts do not mirror each other",
+            Error::HashMismatch => "swap contracts lock different hashes",
+            Error::UnsafeTimeouts => "initiator contract times out too early",
+            Error::IncomparableTimeouts => "swap timeouts are not timestamps",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::WitnessProgram(ref e) => Some(e),
+            Error::Script(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+
+    const ALICE: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const BOB: &str = "03a0434d9e47f3c86235477c7b1ae6ae5d3442d49b1943c2b752a68e2a47e247c7";
+    const DAY: u32 = 24 * 3600;
+    const START: u32 = 1_700_000_000;
+
+    fn key(s: &str) -> Vec<u8> {
+        hex::decode(s).unwrap()
+    }
+
+    fn lock() -> HashLock {
+        HashLock::Sha256(sha256(&[7u8; 32]))
+    }
+
+    #[test]
+    fn script_round_trip() {
+        let htlc = Htlc::new(
+            lock(),
+            key(BOB),
+            key(ALICE),
+            Timelock::Absolute(START + 2 * DAY),
+        )
+        .unwrap();
+        let script = htlc.to_script();
+        assert_eq!(&script[..5], &[0x63, 0x82, 0x01, 0x20, 0x88]);
+        assert_eq!(script[5], 0xa8);
+        assert_eq!(Htlc::from_script(&script), Ok(htlc.clone()));
+        assert!(htlc.hash_lock().verify(&[7u8; 32]));
+        assert!(!htlc.hash_lock().verify(&[7u8; 33]));
+
+        let relative = Htlc::new(
+            HashLock::Hash160(hash160(&[7u8; 32])),
+            key(BOB),
+            key(ALICE),
+            Timelock::Relative(144),
+        )
+        .unwrap();
+        assert_eq!(Htlc::from_script(&relative.to_script()), Ok(relative));
+
+        assert_eq!(Htlc::from_script(&script[1..]), Err(Error::NotHtlc));
+        let mut non_minimal = script.clone();
+        non_minimal.splice(2..4, vec![0x4c, 0x01, 0x20]);
+        assert_eq!(
+            Htlc::from_script(&non_minimal),
+            Err(Error::Script(script::Error::NonMinimalPush))
+        );
+        assert_eq!(
+            Htlc::new(lock(), key(&BOB[2..]), key(ALICE), Timelock::Absolute(1)),
+            Err(Error::WitnessProgram(::Error::InvalidPublicKey))
+        );
+        assert_eq!(
+            Htlc::new(lock(), key(BOB), key(ALICE), Timelock::Absolute(0)),
+            Err(Error::Script(script::Error::InvalidTimelock))
+        );
+    }
+
+    #[test]
+    fn atomic_swap() {
+        let initiator = SwapLeg::new(
+            Network::Bitcoin,
+            Htlc::new(
+                lock(),
+                key(BOB),
+                key(ALICE),
+                Timelock::Absolute(START + 2 * DAY),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let participant = SwapLeg::new(
+            Network::Monacoin,
+            Htlc::new(
+                lock(),
+                key(ALICE),
+                key(BOB),
+                Timelock::Absolute(START + DAY),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let swap = AtomicSwap::new(initiator.clone(), participant.clone(), DAY / 2).unwrap();
+        assert!(swap.initiator().address().starts_with("bc1q"));
+        assert!(swap.participant().address().starts_with("mona1q"));
+        assert_eq!(
+            swap.participant().witness_program(),
+            &WitnessProgram::p2wsh_from_script(&participant.witness_script(), Network::Monacoin)
+                .unwrap()
+        );
+
+        assert_eq!(
+            AtomicSwap::new(participant.clone(), initiator.clone(), 0),
+            Err(Error::UnsafeTimeouts)
+        );
+        assert_eq!(
+            AtomicSwap::new(initiator.clone(), participant.clone(), 2 * DAY),
+            Err(Error::UnsafeTimeouts)
+        );
+
+        let other_hash = SwapLeg::new(
+            Network::Litecoin,
+            Htlc::new(
+                HashLock::Sha256([0u8; 32]),
+                key(ALICE),
+                key(BOB),
+                Timelock::Absolute(START + DAY),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            AtomicSwap::new(initiator.clone(), other_hash, 0),
+            Err(Error::HashMismatch)
+        );
+        let heights = SwapLeg::new(
+            Network::Vertcoin,
+            Htlc::new(lock(), key(ALICE), key(BOB), Timelock::Absolute(800_000)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            AtomicSwap::new(initiator.clone(), heights, 0),
+            Err(Error::IncomparableTimeouts)
+        );
+
+        // Both contracts paying Alice is no swap
+        let same_party = SwapLeg::new(
+            Network::Monacoin,
+            Htlc::new(
+                lock(),
+                key(BOB),
+                key(ALICE),
+                Timelock::Absolute(START + DAY),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            AtomicSwap::new(initiator, same_party, 0),
+            Err(Error::PartyMismatch)
+        );
+    }
+
+    #[test]
+    fn swap_from_parties() {
+        let swap = AtomicSwap::from_parties(
+            lock(),
+            (key(ALICE), Network::Bitcoin, START + 2 * DAY),
+            (key(BOB), Network::Monacoin, START + DAY),
+            DAY / 2,
+        )
+        .unwrap();
+        assert_eq!(swap.initiator().network(), Network::Bitcoin);
+        assert_eq!(swap.initiator().htlc().recipient(), &key(BOB)[..]);
+        assert_eq!(swap.initiator().htlc().refund(), &key(ALICE)[..]);
+        assert_eq!(swap.participant().htlc().recipient(), &key(ALICE)[..]);
+        assert!(swap.participant().address().starts_with("mona1q"));
+
+        assert_eq!(
+            AtomicSwap::from_parties(
+                lock(),
+                (key(ALICE), Network::Bitcoin, START + DAY),
+                (key(BOB), Network::Monacoin, START + DAY),
+                DAY / 2,
+            ),
+            Err(Error::UnsafeTimeouts)
+        );
+        assert_eq!(
+            AtomicSwap::from_parties(
+                lock(),
+                (key(ALICE), Network::Bitcoin, START + 2 * DAY),
+                (key(ALICE), Network::Monacoin, START + DAY),
+                DAY / 2,
+            ),
+            Err(Error::PartyMismatch)
+        );
+        assert_eq!(
+            AtomicSwap::from_parties(
+                lock(),
+                (key(ALICE), Network::Zilliqa, START + 2 * DAY),
+                (key(BOB), Network::Monacoin, START + DAY),
+                DAY / 2,
+            ),
+            Err(Error::WitnessProgram(::Error::InvalidPayloadFamily))
+        );
+    }
+}
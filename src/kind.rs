@@ -0,0 +1,82 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Classification of witness programs by output type
+
+use std::fmt;
+
+use WitnessVersion;
+
+/// Witness program of a pay-to-anchor output (BIP-0433)
+pub const P2A_PROGRAM: [u8; 2] = [0x4e, 0x73];
+
+/// The kind of output a witness program pays to
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum AddressKind {
+    /// Pay to witness public key hash: version 0 with a 20-byte program
+    P2wpkh,
+    /// Pay to witness script hash: version 0 with a 32-byte program
+    P2wsh,
+    /// Pay to Taproot: version 1 with a 32-byte program
+    P2tr,
+    /// Pay to anchor: version 1 with the program `0x4e73`
+    P2a,
+    /// A program without defined semantics, reserved for future soft forks
+    Future {
+        /// Witness version of the program
+        version: WitnessVersion,
+        /// Length of the program in bytes
+        length: usize,
+    },
+}
+
+impl AddressKind {
+    /// Classifies a version and program
+    ///
+    /// The program is expected to have passed `WitnessProgram::validate`.
+    pub fn classify(version: WitnessVersion, program: &[u8]) -> AddressKind {
+        match (version, program.len()) {
+            (WitnessVersion::V0, 20) => AddressKind::P2wpkh,
+            (WitnessVersion::V0, 32) => AddressKind::P2wsh,
+            (WitnessVersion::V1, 32) => AddressKind::P2tr,
+            (WitnessVersion::V1, 2) if program == P2A_PROGRAM => AddressKind::P2a,
+            (version, length) => AddressKind::Future { version, length },
+        }
+    }
+
+    /// Whether the kind has consensus meaning today
+    pub fn is_known(&self) -> bool {
+        !matches!(*self, AddressKind::Future { .. })
+    }
+}
+
+impl fmt::Display for AddressKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddressKind::P2wpkh => write!(f, "p2wpkh"),
+            AddressKind::P2wsh => write!(f, "p2wsh"),
+            AddressKind::P2tr => write!(f, "p2tr"),
+            AddressKind::P2a => write!(f, "p2a"),
+            AddressKind::Future { version, length } => {
+                write!(f, "future version {} with length {}", version, length)
+            }
+        }
+    }
+}
@@ -24,16 +24,17 @@
 //! encoding scheme described in [BIP-0173](https://github.com/bitcoin/bips/blob/master/bip-0173.mediawiki),
 //! and segregated witness addresses encoded by Bech32 simply combine a coin-specific
 //! human-readable part with the data of the witness program as the Bech32 data
-//! payload.
+//! payload. Version 0 programs use the original Bech32 checksum, while version 1
+//! and later use the Bech32m checksum from [BIP-0350](https://github.com/bitcoin/bips/blob/master/bip-0350.mediawiki).
 //!
 //! # Examples
 //!
 //! ```rust
-//! use altcoin_bech32::{WitnessProgram, u5};
+//! use altcoin_bech32::{WitnessProgram, WitnessVersion};
 //! use altcoin_bech32::constants::Network;
 //!
 //! let witness_program = WitnessProgram::new(
-//!     u5::try_from_u8(0).unwrap(),
+//!     WitnessVersion::V0,
 //!     vec![
 //!         0x00, 0x00, 0x00, 0xc4, 0xa5, 0xca, 0xd4, 0x62,
 //!         0x21, 0xb2, 0xa1, 0x87, 0x90, 0x5e, 0x52, 0x66,
@@ -62,21 +63,59 @@
 #![cfg_attr(feature = "strict", deny(warnings))]
 
 extern crate bech32;
-pub use bech32::u5;
-use bech32::{decode, encode, FromBase32, ToBase32};
+extern crate blake2;
+extern crate bs58;
+extern crate hmac;
+extern crate ripemd;
+extern crate secp256k1;
+extern crate sha2;
+extern crate sha3;
+pub use bech32::Variant;
+use bech32::{decode, encode, u5, FromBase32, ToBase32};
 
+use std::convert::TryFrom;
 use std::str::FromStr;
 use std::string::ToString;
 use std::{error, fmt};
 
+pub mod bip32;
+pub mod blockfile;
 pub mod constants;
-use constants::Network;
+pub mod cosmos;
+use constants::{Network, PayloadFamily};
+pub mod descriptor;
+pub mod handshake;
+pub mod hash;
+pub mod htlc;
+pub mod miniscript;
+pub mod policy;
+pub mod psbt;
+pub mod qrl;
+pub mod script;
+pub mod taproot;
+pub mod transaction;
+pub mod utxo;
+pub mod witness;
+pub mod wrapped;
+pub mod zen;
+pub mod zilliqa;
+
+mod account;
+mod base64;
+mod encode;
+mod groestl;
+mod hex;
+mod kind;
+mod version;
+pub use account::AccountAddress;
+pub use kind::{AddressKind, P2A_PROGRAM};
+pub use version::WitnessVersion;
 
 /// Witness version and program data
 #[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
 pub struct WitnessProgram {
     /// Witness program version
-    version: u5,
+    version: WitnessVersion,
     /// Witness program content
     program: Vec<u8>,
     /// Cryptocurrency network
@@ -87,13 +126,17 @@ pub struct WitnessProgram {
 
 impl WitnessProgram {
     /// Construct a new WitnessProgram given the constituent version, witness program and network version
-    pub fn new(version: u5, program: Vec<u8>, network: Network) -> Result<WitnessProgram, Error> {
+    pub fn new(
+        version: WitnessVersion,
+        program: Vec<u8>,
+        network: Network,
+    ) -> Result<WitnessProgram, Error> {
         // Compute bech32
         let hrp = constants::hrp(&network);
-        let mut b32_data: Vec<u5> = vec![version];
+        let mut b32_data: Vec<u5> = vec![version.to_u5()];
         let p5 = program.to_base32();
         b32_data.extend_from_slice(&p5);
-        let bech32 = encode(&hrp, b32_data)?;
+        let bech32 = encode(&hrp, b32_data, version.variant())?;
 
         // Create return object
         let ret = WitnessProgram {
@@ -122,64 +165,137 @@ impl WitnessProgram {
         WitnessProgram::from_str(address)
     }
 
+    /// Decodes a segwit address, reporting which checksum variant it used
+    ///
+    /// In `DecodeMode::Strict` this behaves like `from_address`. With
+    /// `DecodeMode::AllowLegacy`, version 1+ programs carrying the original
+    /// Bech32 checksum are accepted as well and flagged with
+    /// `Warning::LegacyChecksum`; the returned program re-encodes them with
+    /// Bech32m.
+    pub fn from_address_with_mode(address: &str, mode: DecodeMode) -> Result<Decoded, Error> {
+        let (hrp, data, checksum_variant) = decode(address)?;
+        let network_classified = match constants::classify(&hrp) {
+            Some(nc) => nc,
+            None => return Err(Error::InvalidHumanReadablePart),
+        };
+        if constants::payload_family(&network_classified) != PayloadFamily::Segwit {
+            return Err(Error::InvalidPayloadFamily);
+        }
+        if data.is_empty() || data.len() > 65 {
+            return Err(Error::Bech32(bech32::Error::InvalidLength));
+        }
+        // Get the script version and program (converted from 5-bit to 8-bit)
+        let (version, program) = {
+            let (v, p5) = data.split_at(1);
+            let program = Vec::from_base32(p5)?;
+            (WitnessVersion::try_from(v[0].to_u8())?, program)
+        };
+        let wp = WitnessProgram {
+            version,
+            program,
+            network: network_classified,
+            bech32: address.to_string(),
+        };
+        wp.validate()?;
+
+        if checksum_variant == version.variant() {
+            return Ok(Decoded {
+                program: wp,
+                variant: checksum_variant,
+                warning: None,
+            });
+        }
+        match (mode, checksum_variant) {
+            (DecodeMode::AllowLegacy, Variant::Bech32) => Ok(Decoded {
+                program: WitnessProgram::new(wp.version, wp.program, wp.network)?,
+                variant: checksum_variant,
+                warning: Some(Warning::LegacyChecksum),
+            }),
+            _ => Err(Error::InvalidChecksumVariant),
+        }
+    }
+
+    /// Re-encodes an address with the checksum variant required by BIP-0350
+    ///
+    /// Version 1+ addresses stored with the legacy Bech32 checksum are
+    /// converted to Bech32m; addresses that are already correct are returned
+    /// in their canonical lowercase form.
+    pub fn convert_legacy_address(address: &str) -> Result<String, Error> {
+        let decoded = WitnessProgram::from_address_with_mode(address, DecodeMode::AllowLegacy)?;
+        Ok(decoded.program.to_address().to_lowercase())
+    }
+
     /// Converts a `WitnessProgram` to a script public key
     ///
     /// The format for the output is
-    /// `[version, program length, <program>]`
-    pub fn to_scriptpubkey(&self) -> Vec<u8> {
-        let mut pubkey: Vec<u8> = Vec::new();
-        let mut v: u8 = self.version.into();
-        if v > 0 {
-            v += 0x50;
+    /// `[version, program length, <program>]`. Networks that do not use
+    /// Bitcoin script, such as Handshake, have no script public key.
+    pub fn to_scriptpubkey(&self) -> Result<Vec<u8>, Error> {
+        if !constants::has_bitcoin_script(&self.network) {
+            return Err(Error::ScriptNotSupported);
         }
-        pubkey.push(v);
+        let mut pubkey: Vec<u8> = Vec::new();
+        pubkey.push(self.version.to_opcode());
         pubkey.push(self.program.len() as u8);
         pubkey.extend_from_slice(&self.program);
-        pubkey
+        Ok(pubkey)
     }
 
     /// Extracts a WitnessProgram out of a provided script public key
+    ///
+    /// The script must consist of a version opcode (`OP_0` or `OP_1` through
+    /// `OP_16`) followed by a single minimal push of the witness program.
+    /// Malformed scripts are reported as errors and never panic, so the input
+    /// may come from untrusted sources.
     pub fn from_scriptpubkey(pubkey: &[u8], network: Network) -> Result<WitnessProgram, Error> {
+        if !constants::has_bitcoin_script(&network) {
+            return Err(Error::ScriptNotSupported);
+        }
         // We need a version byte and a program length byte, with a program at
         // least 2 bytes long.
         if pubkey.len() < 4 {
             return Err(Error::ScriptPubkeyTooShort);
         }
-        let proglen: usize = pubkey[1] as usize;
+        // Process script version: only OP_0 and OP_1..OP_16 are allowed
+        let v = WitnessVersion::from_opcode(pubkey[0])?;
+        // The program must be pushed with a direct push opcode, which covers
+        // every valid program length
+        let proglen: usize = match pubkey[1] {
+            n @ 0x01..=0x4b => n as usize,
+            0x4c..=0x4e => return Err(Error::ScriptPubkeyNonMinimalPush),
+            _ => return Err(Error::ScriptPubkeyInvalidLength),
+        };
         // Check that program length byte is consistent with pubkey length
         if pubkey.len() != 2 + proglen {
             return Err(Error::ScriptPubkeyInvalidLength);
         }
-        // Process script version
-        let mut v: u8 = pubkey[0];
-        if v > 0x50 {
-            v -= 0x50;
-        }
 
-        let v = u5::try_from_u8(v).expect("range is already guaranteed by code above");
         let program = &pubkey[2..];
 
         WitnessProgram::new(v, program.to_vec(), network)
     }
 
-    /// Validates the WitnessProgram against version and length constraints
+    /// Validates the WitnessProgram against its network's payload family and
+    /// version and length constraints
     pub fn validate(&self) -> Result<(), Error> {
-        if self.version.to_u8() > 16 {
-            // Invalid script version
-            return Err(Error::InvalidScriptVersion);
+        if constants::payload_family(&self.network) != PayloadFamily::Segwit {
+            return Err(Error::InvalidPayloadFamily);
         }
         if self.program.len() < 2 || self.program.len() > 40 {
             return Err(Error::InvalidLength);
         }
         // Check proper script length
-        if self.version.to_u8() == 0 && self.program.len() != 20 && self.program.len() != 32 {
+        if self.version == WitnessVersion::V0
+            && self.program.len() != 20
+            && self.program.len() != 32
+        {
             return Err(Error::InvalidVersionLength);
         }
         Ok(())
     }
 
     /// Witness program version
-    pub fn version(&self) -> u5 {
+    pub fn version(&self) -> WitnessVersion {
         self.version
     }
 
@@ -192,11 +308,68 @@ impl WitnessProgram {
     pub fn network(&self) -> Network {
         self.network
     }
+
+    /// Classifies the program by the kind of output it pays to
+    pub fn kind(&self) -> AddressKind {
+        AddressKind::classify(self.version, &self.program)
+    }
+
+    /// Pay to witness public key hash program for a HASH160 of a public key
+    pub fn p2wpkh(hash: [u8; 20], network: Network) -> Result<WitnessProgram, Error> {
+        WitnessProgram::new(WitnessVersion::V0, hash.to_vec(), network)
+    }
+
+    /// Pay to witness script hash program for a SHA256 of a witness script
+    pub fn p2wsh(hash: [u8; 32], network: Network) -> Result<WitnessProgram, Error> {
+        WitnessProgram::new(WitnessVersion::V0, hash.to_vec(), network)
+    }
+
+    /// Pay to witness public key hash program for a compressed public key
+    ///
+    /// The key must be a 33-byte SEC1 compressed secp256k1 public key;
+    /// uncompressed keys are not allowed in version 0 witness programs. The
+    /// key is hashed with the function `constants::hash_functions` lists for
+    /// `network`.
+    pub fn p2wpkh_from_pubkey(pubkey: &[u8], network: Network) -> Result<WitnessProgram, Error> {
+        check_compressed_pubkey(pubkey)?;
+        let key_hash = constants::hash_functions(&network).key_hash;
+        WitnessProgram::new(WitnessVersion::V0, key_hash.digest(pubkey), network)
+    }
+
+    /// Pay to witness script hash program for a witness script
+    ///
+    /// The script is hashed with the function `constants::hash_functions`
+    /// lists for `network`.
+    pub fn p2wsh_from_script(script: &[u8], network: Network) -> Result<WitnessProgram, Error> {
+        let script_hash = constants::hash_functions(&network).script_hash;
+        WitnessProgram::new(WitnessVersion::V0, script_hash.digest(script), network)
+    }
+
+    /// Pay to Taproot program for an x-only output key
+    pub fn p2tr(output_key: [u8; 32], network: Network) -> Result<WitnessProgram, Error> {
+        WitnessProgram::new(WitnessVersion::V1, output_key.to_vec(), network)
+    }
+
+    /// Pay to anchor program
+    pub fn p2a(network: Network) -> Result<WitnessProgram, Error> {
+        WitnessProgram::new(WitnessVersion::V1, P2A_PROGRAM.to_vec(), network)
+    }
 }
 
-impl ToString for WitnessProgram {
-    fn to_string(&self) -> String {
-        self.bech32.to_string()
+/// Checks the encoding of a compressed secp256k1 public key
+pub(crate) fn check_compressed_pubkey(pubkey: &[u8]) -> Result<(), Error> {
+    match (pubkey.len(), pubkey.first()) {
+        (33, Some(&0x02)) | (33, Some(&0x03)) => Ok(()),
+        (65, Some(&0x04)) | (65, Some(&0x06)) | (65, Some(&0x07)) => {
+            Err(Error::UncompressedPublicKey)
+        }
+        _ => Err(Error::InvalidPublicKey),
+    }
+}
+
+impl fmt::Display for WitnessProgram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.bech32)
     }
 }
 
@@ -204,28 +377,66 @@ impl FromStr for WitnessProgram {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<WitnessProgram, Error> {
-        let (hrp, data) = decode(s)?;
-        let network_classified = match constants::classify(&hrp) {
-            Some(nc) => nc,
-            None => return Err(Error::InvalidHumanReadablePart),
-        };
-        if data.is_empty() || data.len() > 65 {
-            return Err(Error::Bech32(bech32::Error::InvalidLength));
+        WitnessProgram::from_address_with_mode(s, DecodeMode::Strict).map(|d| d.program)
+    }
+}
+
+/// Checksum handling when decoding an address
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum DecodeMode {
+    /// Only accept the checksum variant required by BIP-0350
+    Strict,
+    /// Also accept version 1+ programs encoded with the original BIP-0173
+    /// checksum, as produced by software predating BIP-0350
+    AllowLegacy,
+}
+
+/// Non-fatal problems found while decoding an address
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Warning {
+    /// A version 1+ program was encoded with the Bech32 checksum instead of
+    /// Bech32m
+    LegacyChecksum,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::LegacyChecksum => write!(f, "legacy bech32 checksum on version 1+ program"),
         }
-        // Get the script version and program (converted from 5-bit to 8-bit)
-        let (version, program) = {
-            let (v, p5) = data.split_at(1);
-            let program = Vec::from_base32(p5)?;
-            (v[0], program)
-        };
-        let wp = WitnessProgram {
-            version,
-            program,
-            network: network_classified,
-            bech32: s.to_string(),
-        };
-        wp.validate()?;
-        Ok(wp)
+    }
+}
+
+/// A witness program decoded together with the checksum variant of its address
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct Decoded {
+    /// Decoded witness program, always holding the correct modern encoding
+    program: WitnessProgram,
+    /// Checksum variant the decoded string used
+    variant: Variant,
+    /// Set when the string was only accepted because of `DecodeMode::AllowLegacy`
+    warning: Option<Warning>,
+}
+
+impl Decoded {
+    /// The decoded witness program
+    pub fn program(&self) -> &WitnessProgram {
+        &self.program
+    }
+
+    /// Consumes the result, returning the witness program
+    pub fn into_program(self) -> WitnessProgram {
+        self.program
+    }
+
+    /// Checksum variant used by the decoded string
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Warning raised while decoding, if any
+    pub fn warning(&self) -> Option<Warning> {
+        self.warning
     }
 }
 
@@ -243,6 +454,10 @@ pub enum Error {
     ScriptPubkeyTooShort,
     /// The provided length byte does not match the data
     ScriptPubkeyInvalidLength,
+    /// The first opcode is not `OP_0` or `OP_1` through `OP_16`
+    ScriptPubkeyInvalidVersion,
+    /// The program is pushed with `OP_PUSHDATA1/2/4` instead of a direct push
+    ScriptPubkeyNonMinimalPush,
     /// Denotes that the WitnessProgram is too long or too short
     ///
     /// Programs must be between 2 and 40 bytes
@@ -253,6 +468,31 @@ pub enum Error {
     InvalidVersionLength,
     /// Script version must be 0 to 16 inclusive
     InvalidScriptVersion,
+    /// The checksum variant does not match the witness version
+    ///
+    /// Version 0 programs must use Bech32, all later versions Bech32m
+    InvalidChecksumVariant,
+    /// The public key is not a valid SEC1 encoded secp256k1 key
+    InvalidPublicKey,
+    /// The public key is uncompressed, which segwit does not allow
+    UncompressedPublicKey,
+    /// Taproot leaf versions must be even and must not be 0x50
+    InvalidLeafVersion,
+    /// A Taproot script tree is deeper than 128 levels
+    TaprootTreeTooDeep,
+    /// The Taproot tweak is out of range or produced an invalid key
+    InvalidTaprootTweak,
+    /// A Taproot control block is malformed or does not match its output key
+    InvalidControlBlock,
+    /// An input witness has no stack items
+    EmptyWitness,
+    /// The network does not lock outputs with Bitcoin script
+    ScriptNotSupported,
+    /// The network's addresses carry a different kind of payload
+    ///
+    /// Account and chain-specific networks decode to an `AccountAddress`,
+    /// segwit networks to a `WitnessProgram`.
+    InvalidPayloadFamily,
 }
 
 impl From<bech32::Error> for Error {
@@ -268,9 +508,23 @@ impl fmt::Display for Error {
             Error::InvalidHumanReadablePart => write!(f, "invalid human-readable part"),
             Error::ScriptPubkeyTooShort => write!(f, "scriptpubkey too short"),
             Error::ScriptPubkeyInvalidLength => write!(f, "scriptpubkey length mismatch"),
+            Error::ScriptPubkeyInvalidVersion => write!(f, "invalid scriptpubkey version opcode"),
+            Error::ScriptPubkeyNonMinimalPush => write!(f, "non-minimal scriptpubkey program push"),
             Error::InvalidLength => write!(f, "invalid length"),
             Error::InvalidVersionLength => write!(f, "program length incompatible with version"),
             Error::InvalidScriptVersion => write!(f, "invalid script versio"),
+            Error::InvalidChecksumVariant => {
+                write!(f, "checksum variant incompatible with version")
+            }
+            Error::InvalidPublicKey => write!(f, "invalid public key"),
+            Error::UncompressedPublicKey => write!(f, "uncompressed public key"),
+            Error::InvalidLeafVersion => write!(f, "invalid taproot leaf version"),
+            Error::TaprootTreeTooDeep => write!(f, "taproot script tree too deep"),
+            Error::InvalidTaprootTweak => write!(f, "invalid taproot tweak"),
+            Error::InvalidControlBlock => write!(f, "invalid taproot control block"),
+            Error::EmptyWitness => write!(f, "empty witness"),
+            Error::ScriptNotSupported => write!(f, "network does not use Bitcoin script"),
+            Error::InvalidPayloadFamily => write!(f, "address payload does not match network"),
         }
     }
 }
@@ -282,9 +536,21 @@ impl error::Error for Error {
             Error::InvalidHumanReadablePart => "invalid human-readable part",
             Error::ScriptPubkeyTooShort => "scriptpubkey too short",
             Error::ScriptPubkeyInvalidLength => "scriptpubkey length mismatch",
+            Error::ScriptPubkeyInvalidVersion => "invalid scriptpubkey version opcode",
+            Error::ScriptPubkeyNonMinimalPush => "non-minimal scriptpubkey program push",
             Error::InvalidLength => "invalid length",
             Error::InvalidVersionLength => "program length incompatible with version",
             Error::InvalidScriptVersion => "invalid script version",
+            Error::InvalidChecksumVariant => "checksum variant incompatible with version",
+            Error::InvalidPublicKey => "invalid public key",
+            Error::UncompressedPublicKey => "uncompressed public key",
+            Error::InvalidLeafVersion => "invalid taproot leaf version",
+            Error::TaprootTreeTooDeep => "taproot script tree too deep",
+            Error::InvalidTaprootTweak => "invalid taproot tweak",
+            Error::InvalidControlBlock => "invalid taproot control block",
+            Error::EmptyWitness => "empty witness",
+            Error::ScriptNotSupported => "network does not use Bitcoin script",
+            Error::InvalidPayloadFamily => "address payload does not match network",
         }
     }
 
@@ -323,7 +589,7 @@ mod tests {
                 Network::Testnet,
             ),
             (
-                "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7k7grplx",
+                "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y",
                 vec![
                     0x51, 0x28, 0x75, 0x1e, 0x76, 0xe8, 0x19, 0x91, 0x96, 0xd4, 0x54, 0x94, 0x1c,
                     0x45, 0xd1, 0xb3, 0xa3, 0x23, 0xf1, 0x43, 0x3b, 0xd6, 0x75, 0x1e, 0x76, 0xe8,
@@ -333,12 +599,12 @@ mod tests {
                 Network::Bitcoin,
             ),
             (
-                "BC1SW50QA3JX3S",
+                "BC1SW50QGDZ25J",
                 vec![0x60, 0x02, 0x75, 0x1e],
                 Network::Bitcoin,
             ),
             (
-                "bc1zw508d6qejxtdg4y5r3zarvaryvg6kdaj",
+                "bc1zw508d6qejxtdg4y5r3zarvaryvaxxpcs",
                 vec![
                     0x52, 0x10, 0x75, 0x1e, 0x76, 0xe8, 0x19, 0x91, 0x96, 0xd4, 0x54, 0x94, 0x1c,
                     0x45, 0xd1, 0xb3, 0xa3, 0x23,
@@ -354,6 +620,24 @@ mod tests {
                 ],
                 Network::Testnet,
             ),
+            (
+                "tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c",
+                vec![
+                    0x51, 0x20, 0x00, 0x00, 0x00, 0xc4, 0xa5, 0xca, 0xd4, 0x62, 0x21, 0xb2, 0xa1,
+                    0x87, 0x90, 0x5e, 0x52, 0x66, 0x36, 0x2b, 0x99, 0xd5, 0xe9, 0x1c, 0x6c, 0xe2,
+                    0x4d, 0x16, 0x5d, 0xab, 0x93, 0xe8, 0x64, 0x33,
+                ],
+                Network::Testnet,
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                vec![
+                    0x51, 0x20, 0x79, 0xbe, 0x66, 0x7e, 0xf9, 0xdc, 0xbb, 0xac, 0x55, 0xa0, 0x62,
+                    0x95, 0xce, 0x87, 0x0b, 0x07, 0x02, 0x9b, 0xfc, 0xdb, 0x2d, 0xce, 0x28, 0xd9,
+                    0x59, 0xf2, 0x81, 0x5b, 0x16, 0xf8, 0x17, 0x98,
+                ],
+                Network::Bitcoin,
+            ),
             (
                 "bcrt1qn3h68k2u0rr49skx05qw7veynpf4lfppd2demt",
                 vec![
@@ -380,7 +664,7 @@ mod tests {
                 Network::MonacoinTestnet,
             ),
             (
-                "mona1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7k9xvmwr",
+                "mona1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7ks6uhtp",
                 vec![
                     0x51, 0x28, 0x75, 0x1e, 0x76, 0xe8, 0x19, 0x91, 0x96, 0xd4, 0x54, 0x94, 0x1c,
                     0x45, 0xd1, 0xb3, 0xa3, 0x23, 0xf1, 0x43, 0x3b, 0xd6, 0x75, 0x1e, 0x76, 0xe8,
@@ -390,12 +674,12 @@ mod tests {
                 Network::Monacoin,
             ),
             (
-                "mona1sw50qpvnxy8",
+                "mona1sw50q5sr2p9",
                 vec![0x60, 0x02, 0x75, 0x1e],
                 Network::Monacoin,
             ),
             (
-                "mona1zw508d6qejxtdg4y5r3zarvaryvhm3vz7",
+                "mona1zw508d6qejxtdg4y5r3zarvaryvz8pq8u",
                 vec![
                     0x52, 0x10, 0x75, 0x1e, 0x76, 0xe8, 0x19, 0x91, 0x96, 0xd4, 0x54, 0x94, 0x1c,
                     0x45, 0xd1, 0xb3, 0xa3, 0x23,
@@ -419,11 +703,11 @@ mod tests {
             } else {
                 scriptpubkey[0] - 0x50
             };
-            let dec_result = WitnessProgram::from_address(&address);
+            let dec_result = WitnessProgram::from_address(address);
             assert!(dec_result.is_ok());
 
             let prog = dec_result.unwrap();
-            let pubkey = prog.to_scriptpubkey();
+            let pubkey = prog.to_scriptpubkey().unwrap();
             assert_eq!(pubkey, scriptpubkey);
 
             assert_eq!(prog.network(), network);
@@ -446,20 +730,40 @@ mod tests {
     fn invalid_address() {
         let pairs: Vec<(&str, Error)> = vec![
             (
-                "tc1qw508d6qejxtdg4y5r3zarvary0c5xw7kg3g4ty",
+                "tc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq5zuyut",
                 Error::InvalidHumanReadablePart,
             ),
             (
-                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5",
-                Error::Bech32(bech32::Error::InvalidChecksum),
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd",
+                Error::InvalidChecksumVariant,
+            ),
+            (
+                "tb1z0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqglt7rf",
+                Error::InvalidChecksumVariant,
+            ),
+            (
+                "BC1S0XLXVLHEMJA6C4DQV22UAPCTQUPFHLXM9H8Z3K2E72Q4K9HCZ7VQ54WELL",
+                Error::InvalidChecksumVariant,
             ),
             (
-                "BC13W508D6QEJXTDG4Y5R3ZARVARY0C5XW7KN40WF2",
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh",
+                Error::InvalidChecksumVariant,
+            ),
+            (
+                "tb1q0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq24jc47",
+                Error::InvalidChecksumVariant,
+            ),
+            (
+                "bc1p38j9r5y49hruaue7wxjce0updqjuyyx0kh56v8s25huc6995vvpql3jow4",
+                Error::Bech32(bech32::Error::InvalidChar('o')),
+            ),
+            (
+                "BC130XLXVLHEMJA6C4DQV22UAPCTQUPFHLXM9H8Z3K2E72Q4K9HCZ7VQ7ZWS8R",
                 Error::InvalidScriptVersion,
             ),
-            ("bc1rw5uspcuh", Error::InvalidLength),
+            ("bc1pw5dgrnzv", Error::InvalidLength),
             (
-                "bc10w508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kw5rljs90",
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7v8n0nx0muaewav253zgeav",
                 Error::Bech32(bech32::Error::InvalidLength),
             ),
             (
@@ -467,30 +771,321 @@ mod tests {
                 Error::InvalidVersionLength,
             ),
             (
-                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sL5k7",
+                "tb1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq47Zagq",
                 Error::Bech32(bech32::Error::MixedCase),
             ),
             (
-                "tb1pw508d6qejxtdg4y5r3zarqfsj6c3",
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7v07qwwzcrf",
                 Error::Bech32(bech32::Error::InvalidPadding),
             ),
             (
-                "bc1zw508d6qejxtdg4y5r3zarvaryvqyzf3du",
+                "tb1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vpggkg4j",
                 Error::Bech32(bech32::Error::InvalidPadding),
             ),
+            ("bc1gmk9yu", Error::Bech32(bech32::Error::InvalidLength)),
+            (
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5",
+                Error::Bech32(bech32::Error::InvalidChecksum),
+            ),
             (
-                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3pjxtptv",
-                Error::Bech32(bech32::Error::InvalidPadding),
+                "mona1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7k9xvmwr",
+                Error::InvalidChecksumVariant,
             ),
-            ("bc1gmk9yu", Error::Bech32(bech32::Error::InvalidLength)),
         ];
         for p in pairs {
             let (address, desired_error) = p;
-            let dec_result = WitnessProgram::from_address(&address);
+            let dec_result = WitnessProgram::from_address(address);
             if dec_result.is_ok() {
                 panic!("Should be invalid: {:?}", address);
             }
             assert_eq!(dec_result.unwrap_err(), desired_error);
         }
     }
+
+    #[test]
+    fn scriptpubkey_version_prefixes() {
+        let hash = [0x75u8; 20];
+        for b in 0..=255u8 {
+            let mut script = vec![b, 0x14];
+            script.extend_from_slice(&hash);
+            let result = WitnessProgram::from_scriptpubkey(&script, Network::Bitcoin);
+            match b {
+                0x00 => assert_eq!(result.unwrap().version().to_u8(), 0),
+                0x51..=0x60 => assert_eq!(result.unwrap().version().to_u8(), b - 0x50),
+                _ => assert_eq!(result.unwrap_err(), Error::ScriptPubkeyInvalidVersion),
+            }
+            assert_eq!(
+                WitnessProgram::from_scriptpubkey(&[b], Network::Bitcoin).unwrap_err(),
+                Error::ScriptPubkeyTooShort
+            );
+        }
+    }
+
+    #[test]
+    fn scriptpubkey_push_prefixes() {
+        // Every opcode in the push position must fail cleanly unless it is
+        // the direct push of the remaining 20 bytes
+        for b in 0..=255u8 {
+            let mut script = vec![0x00, b];
+            script.extend_from_slice(&[0x75u8; 20]);
+            let result = WitnessProgram::from_scriptpubkey(&script, Network::Bitcoin);
+            match b {
+                0x14 => assert!(result.is_ok()),
+                0x4c..=0x4e => assert_eq!(result.unwrap_err(), Error::ScriptPubkeyNonMinimalPush),
+                _ => assert_eq!(result.unwrap_err(), Error::ScriptPubkeyInvalidLength),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_scriptpubkey() {
+        let scripts: Vec<(Vec<u8>, Error)> = vec![
+            (vec![0x00, 0x02, 0x75], Error::ScriptPubkeyTooShort),
+            (
+                vec![0x01, 0x02, 0x75, 0x1e],
+                Error::ScriptPubkeyInvalidVersion,
+            ),
+            (
+                vec![0x10, 0x02, 0x75, 0x1e],
+                Error::ScriptPubkeyInvalidVersion,
+            ),
+            (
+                vec![0x50, 0x02, 0x75, 0x1e],
+                Error::ScriptPubkeyInvalidVersion,
+            ),
+            (
+                vec![0x61, 0x02, 0x75, 0x1e],
+                Error::ScriptPubkeyInvalidVersion,
+            ),
+            (
+                vec![0x4c, 0x02, 0x75, 0x1e],
+                Error::ScriptPubkeyInvalidVersion,
+            ),
+            (
+                vec![0x51, 0x4c, 0x02, 0x75, 0x1e],
+                Error::ScriptPubkeyNonMinimalPush,
+            ),
+            (
+                vec![0x51, 0x03, 0x75, 0x1e],
+                Error::ScriptPubkeyInvalidLength,
+            ),
+            (
+                vec![0x51, 0x02, 0x75, 0x1e, 0x00],
+                Error::ScriptPubkeyInvalidLength,
+            ),
+            (
+                vec![0x51, 0x4f, 0x75, 0x1e],
+                Error::ScriptPubkeyInvalidLength,
+            ),
+            (vec![0x00, 0x02, 0x75, 0x1e], Error::InvalidVersionLength),
+            (
+                {
+                    let mut s = vec![0x51, 0x29];
+                    s.extend_from_slice(&[0u8; 41]);
+                    s
+                },
+                Error::InvalidLength,
+            ),
+        ];
+        for (script, desired_error) in scripts {
+            let result = WitnessProgram::from_scriptpubkey(&script, Network::Bitcoin);
+            assert_eq!(result.unwrap_err(), desired_error);
+        }
+    }
+
+    #[test]
+    fn legacy_checksum() {
+        let pairs: Vec<(&str, &str)> = vec![
+            (
+                "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7k7grplx",
+                "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y",
+            ),
+            ("BC1SW50QA3JX3S", "bc1sw50qgdz25j"),
+            (
+                "bc1zw508d6qejxtdg4y5r3zarvaryvg6kdaj",
+                "bc1zw508d6qejxtdg4y5r3zarvaryvaxxpcs",
+            ),
+            (
+                "mona1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7k9xvmwr",
+                "mona1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7ks6uhtp",
+            ),
+            ("mona1sw50qpvnxy8", "mona1sw50q5sr2p9"),
+        ];
+        for (legacy, modern) in pairs {
+            assert_eq!(
+                WitnessProgram::from_address(legacy).unwrap_err(),
+                Error::InvalidChecksumVariant
+            );
+
+            let decoded =
+                WitnessProgram::from_address_with_mode(legacy, DecodeMode::AllowLegacy).unwrap();
+            assert_eq!(decoded.variant(), Variant::Bech32);
+            assert_eq!(decoded.warning(), Some(Warning::LegacyChecksum));
+            assert_eq!(decoded.program().to_address(), modern);
+            assert_eq!(
+                decoded.into_program(),
+                WitnessProgram::from_address(modern).unwrap()
+            );
+
+            assert_eq!(
+                WitnessProgram::convert_legacy_address(legacy).unwrap(),
+                modern
+            );
+            assert_eq!(
+                WitnessProgram::convert_legacy_address(modern).unwrap(),
+                modern
+            );
+
+            let decoded =
+                WitnessProgram::from_address_with_mode(modern, DecodeMode::Strict).unwrap();
+            assert_eq!(decoded.variant(), Variant::Bech32m);
+            assert_eq!(decoded.warning(), None);
+        }
+
+        // Bech32m was never valid for version 0, so there is nothing to migrate
+        assert_eq!(
+            WitnessProgram::from_address_with_mode(
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh",
+                DecodeMode::AllowLegacy
+            )
+            .unwrap_err(),
+            Error::InvalidChecksumVariant
+        );
+        let decoded = WitnessProgram::from_address_with_mode(
+            "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4",
+            DecodeMode::AllowLegacy,
+        )
+        .unwrap();
+        assert_eq!(decoded.variant(), Variant::Bech32);
+        assert_eq!(decoded.warning(), None);
+    }
+
+    #[test]
+    fn address_kind() {
+        let pairs: Vec<(&str, AddressKind)> = vec![
+            (
+                "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4",
+                AddressKind::P2wpkh,
+            ),
+            (
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+                AddressKind::P2wsh,
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                AddressKind::P2tr,
+            ),
+            ("bc1pfeessrawgf", AddressKind::P2a),
+            (
+                "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y",
+                AddressKind::Future {
+                    version: WitnessVersion::V1,
+                    length: 40,
+                },
+            ),
+            (
+                "BC1SW50QGDZ25J",
+                AddressKind::Future {
+                    version: WitnessVersion::V16,
+                    length: 2,
+                },
+            ),
+        ];
+        for (address, kind) in pairs {
+            let prog = WitnessProgram::from_address(address).unwrap();
+            assert_eq!(prog.kind(), kind);
+            match kind {
+                AddressKind::Future { .. } => assert!(!kind.is_known()),
+                _ => assert!(kind.is_known()),
+            }
+        }
+
+        let hash = [
+            0x75, 0x1e, 0x76, 0xe8, 0x19, 0x91, 0x96, 0xd4, 0x54, 0x94, 0x1c, 0x45, 0xd1, 0xb3,
+            0xa3, 0x23, 0xf1, 0x43, 0x3b, 0xd6,
+        ];
+        let prog = WitnessProgram::p2wpkh(hash, Network::Bitcoin).unwrap();
+        assert_eq!(
+            prog.to_address(),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+        assert_eq!(prog.kind(), AddressKind::P2wpkh);
+        assert_eq!(
+            WitnessProgram::p2wsh([0; 32], Network::Litecoin)
+                .unwrap()
+                .kind(),
+            AddressKind::P2wsh
+        );
+        assert_eq!(
+            WitnessProgram::p2tr([0; 32], Network::Monacoin)
+                .unwrap()
+                .kind(),
+            AddressKind::P2tr
+        );
+        let anchor = WitnessProgram::p2a(Network::Bitcoin).unwrap();
+        assert_eq!(anchor.to_address(), "bc1pfeessrawgf");
+        assert_eq!(
+            anchor.to_scriptpubkey().unwrap(),
+            vec![0x51, 0x02, 0x4e, 0x73]
+        );
+    }
+
+    #[test]
+    fn pubkey_and_script_programs() {
+        let pubkey = [
+            0x02, 0x79, 0xbe, 0x66, 0x7e, 0xf9, 0xdc, 0xbb, 0xac, 0x55, 0xa0, 0x62, 0x95, 0xce,
+            0x87, 0x0b, 0x07, 0x02, 0x9b, 0xfc, 0xdb, 0x2d, 0xce, 0x28, 0xd9, 0x59, 0xf2, 0x81,
+            0x5b, 0x16, 0xf8, 0x17, 0x98,
+        ];
+        let prog = WitnessProgram::p2wpkh_from_pubkey(&pubkey, Network::Bitcoin).unwrap();
+        assert_eq!(
+            prog.to_address(),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+        let prog = WitnessProgram::p2wpkh_from_pubkey(&pubkey, Network::Monacoin).unwrap();
+        assert_eq!(prog.kind(), AddressKind::P2wpkh);
+        assert_eq!(prog.network(), Network::Monacoin);
+
+        let mut script = vec![0x21];
+        script.extend_from_slice(&pubkey);
+        script.push(0xac);
+        let prog = WitnessProgram::p2wsh_from_script(&script, Network::Testnet).unwrap();
+        assert_eq!(
+            prog.to_address(),
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7"
+        );
+
+        // Handshake hashes keys with BLAKE2b-160 and scripts with SHA3-256
+        let prog = WitnessProgram::p2wpkh_from_pubkey(&pubkey, Network::Handshake).unwrap();
+        assert_eq!(
+            prog.program(),
+            &hash::HashFunction::Blake2b160.digest(&pubkey)[..]
+        );
+        assert_eq!(prog.kind(), AddressKind::P2wpkh);
+        let prog = WitnessProgram::p2wsh_from_script(&script, Network::HandshakeTestnet).unwrap();
+        assert_eq!(
+            prog.program(),
+            &hash::HashFunction::Sha3_256.digest(&script)[..]
+        );
+        assert_eq!(prog.kind(), AddressKind::P2wsh);
+        let prog = WitnessProgram::p2wpkh_from_pubkey(&pubkey, Network::Groestlcoin).unwrap();
+        assert_eq!(prog.program(), &hash::hash160(&pubkey)[..]);
+
+        let mut uncompressed = vec![0x04];
+        uncompressed.extend_from_slice(&[0x79; 64]);
+        assert_eq!(
+            WitnessProgram::p2wpkh_from_pubkey(&uncompressed, Network::Bitcoin),
+            Err(Error::UncompressedPublicKey)
+        );
+        assert_eq!(
+            WitnessProgram::p2wpkh_from_pubkey(&pubkey[1..], Network::Bitcoin),
+            Err(Error::InvalidPublicKey)
+        );
+        let mut bad_prefix = pubkey;
+        bad_prefix[0] = 0x05;
+        assert_eq!(
+            WitnessProgram::p2wpkh_from_pubkey(&bad_prefix, Network::Bitcoin),
+            Err(Error::InvalidPublicKey)
+        );
+    }
 }
@@ -0,0 +1,1318 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Miniscript
+//!
+//! Parses, type checks and encodes [Miniscript](https://bitcoin.sipa.be/miniscript/)
+//! expressions for P2WSH witness scripts and tapscript leaves. Keys are
+//! descriptor key expressions, so the same expression can be expanded into
+//! witness programs for any network. Only the correctness part of the type
+//! system (the basic types and the `z`, `o`, `n`, `d` and `u` properties) is
+//! checked; malleability analysis is left to the signer.
+
+use std::{error, fmt};
+
+use constants::Network;
+use descriptor::{self, split_call, DescriptorKey};
+use hash::hash160;
+use taproot::{TapLeaf, TapTree, TaprootOutput};
+use {hex, script, WitnessProgram};
+
+/// Maximum number of keys in a `multi()` fragment
+pub const MAX_MULTI_KEYS: usize = 20;
+/// Maximum number of keys in a `multi_a()` fragment
+pub const MAX_MULTI_A_KEYS: usize = 999;
+/// Deepest nesting of fragments and wrappers accepted when parsing
+///
+/// Encoding and costing walk the expression recursively, so this keeps
+/// untrusted input well inside the stack of a spawned thread.
+pub const MAX_RECURSION_DEPTH: usize = 100;
+/// Largest P2WSH witness script that is standard to spend
+pub const MAX_STANDARD_P2WSH_SCRIPT_SIZE: usize = 3600;
+/// Largest tapscript, bounded only by the block weight
+pub const MAX_TAPSCRIPT_SIZE: usize = 4_000_000;
+
+/// Size of a DER signature with its sighash byte, at most
+const ECDSA_SIGNATURE_SIZE: usize = 73;
+/// Size of a BIP-0340 signature with its sighash byte, at most
+const SCHNORR_SIGNATURE_SIZE: usize = 65;
+
+/// Script context a miniscript is encoded for
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum Context {
+    /// Version 0 witness scripts (P2WSH)
+    Segwitv0,
+    /// BIP-0342 tapscript leaves
+    Tapscript,
+}
+
+/// Basic type of a miniscript expression
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum BaseType {
+    /// Takes its inputs from the stack and pushes a nonzero value on
+    /// satisfaction or an exact 0 on dissatisfaction
+    B,
+    /// Takes its inputs from the stack and pushes nothing, failing unless
+    /// satisfied
+    V,
+    /// Pushes a public key for a signature check
+    K,
+    /// Takes its inputs from below the top stack item
+    W,
+}
+
+/// Type of a miniscript expression
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub struct Type {
+    /// Basic type
+    pub base: BaseType,
+    /// Consumes exactly zero stack elements
+    pub z: bool,
+    /// Consumes exactly one stack element
+    pub o: bool,
+    /// The top input is nonzero when satisfied
+    pub n: bool,
+    /// Has a dissatisfaction
+    pub d: bool,
+    /// Pushes exactly 1 when satisfied
+    pub u: bool,
+}
+
+impl Type {
+    fn new(base: BaseType, z: bool, o: bool, n: bool, d: bool, u: bool) -> Type {
+        Type {
+            base,
+            z,
+            o,
+            n,
+            d,
+            u,
+        }
+    }
+}
+
+/// A miniscript fragment, whose children are typed miniscripts
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub enum Fragment {
+    /// `0`
+    False,
+    /// `1`
+    True,
+    /// `pk_k(KEY)`
+    PkK(DescriptorKey),
+    /// `pk_h(KEY)`
+    PkH(DescriptorKey),
+    /// `older(n)`: relative timelock
+    Older(u32),
+    /// `after(n)`: absolute timelock
+    After(u32),
+    /// `sha256(h)`
+    Sha256([u8; 32]),
+    /// `hash256(h)`
+    Hash256([u8; 32]),
+    /// `ripemd160(h)`
+    Ripemd160([u8; 20]),
+    /// `hash160(h)`
+    Hash160([u8; 20]),
+    /// `andor(X,Y,Z)`
+    AndOr(Box<Miniscript>, Box<Miniscript>, Box<Miniscript>),
+    /// `and_v(X,Y)`
+    AndV(Box<Miniscript>, Box<Miniscript>),
+    /// `and_b(X,Y)`
+    AndB(Box<Miniscript>, Box<Miniscript>),
+    /// `or_b(X,Z)`
+    OrB(Box<Miniscript>, Box<Miniscript>),
+    /// `or_c(X,Z)`
+    OrC(Box<Miniscript>, Box<Miniscript>),
+    /// `or_d(X,Z)`
+    OrD(Box<Miniscript>, Box<Miniscript>),
+    /// `or_i(X,Z)`
+    OrI(Box<Miniscript>, Box<Miniscript>),
+    /// `thresh(k,X1,...,Xn)`
+    Thresh(usize, Vec<Miniscript>),
+    /// `multi(k,KEY1,...,KEYn)`, P2WSH only
+    Multi(usize, Vec<DescriptorKey>),
+    /// `multi_a(k,KEY1,...,KEYn)`, tapscript only
+    MultiA(usize, Vec<DescriptorKey>),
+    /// `a:X`
+    Alt(Box<Miniscript>),
+    /// `s:X`
+    Swap(Box<Miniscript>),
+    /// `c:X`
+    Check(Box<Miniscript>),
+    /// `d:X`
+    DupIf(Box<Miniscript>),
+    /// `v:X`
+    Verify(Box<Miniscript>),
+    /// `j:X`
+    NonZero(Box<Miniscript>),
+    /// `n:X`
+    ZeroNotEqual(Box<Miniscript>),
+}
+
+/// A type checked miniscript expression
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct Miniscript {
+    /// The fragment and its children
+    fragment: Fragment,
+    /// Type of the fragment
+    ty: Type,
+    /// Context the fragment was checked for
+    ctx: Context,
+}
+
+/// Maximum witness size of a satisfaction or dissatisfaction
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+struct Cost {
+    /// Number of stack elements
+    elements: usize,
+    /// Serialized size of the elements, with their length prefixes
+    bytes: usize,
+}
+
+impl Cost {
+    /// No stack elements
+    const EMPTY: Cost = Cost {
+        elements: 0,
+        bytes: 0,
+    };
+
+    /// A single element of `len` bytes
+    fn push(len: usize) -> Cost {
+        Cost {
+            elements: 1,
+            bytes: 1 + len,
+        }
+    }
+
+    fn then(self, other: Cost) -> Cost {
+        Cost {
+            elements: self.elements + other.elements,
+            bytes: self.bytes + other.bytes,
+        }
+    }
+}
+
+/// Concatenates two witnesses if both exist
+fn and(a: Option<Cost>, b: Option<Cost>) -> Option<Cost> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.then(b)),
+        _ => None,
+    }
+}
+
+/// The larger of two alternative witnesses
+fn max(a: Option<Cost>, b: Option<Cost>) -> Option<Cost> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if b.bytes > a.bytes { b } else { a }),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+impl Miniscript {
+    /// Type checks a fragment for a context
+    pub fn new(fragment: Fragment, ctx: Context) -> Result<Miniscript, Error> {
+        let ty = type_check(&fragment, ctx)?;
+        Ok(Miniscript { fragment, ty, ctx })
+    }
+
+    /// Parses a top-level miniscript, which must have type `B`
+    pub fn parse(s: &str, network: Network, ctx: Context) -> Result<Miniscript, Error> {
+        let ms = parse_node(s, network, ctx, 0)?;
+        if ms.ty.base != BaseType::B {
+            return Err(Error::InvalidTopLevel);
+        }
+        ms.check_script_size()?;
+        Ok(ms)
+    }
+
+    /// Rejects scripts above the size limit of the context
+    pub(crate) fn check_script_size(&self) -> Result<(), Error> {
+        let max = match self.ctx {
+            Context::Segwitv0 => MAX_STANDARD_P2WSH_SCRIPT_SIZE,
+            Context::Tapscript => MAX_TAPSCRIPT_SIZE,
+        };
+        let size = self.script_size();
+        if size > max {
+            return Err(Error::ScriptTooLarge(size));
+        }
+        Ok(())
+    }
+
+    /// The fragment and its children
+    pub fn fragment(&self) -> &Fragment {
+        &self.fragment
+    }
+
+    /// Type of the expression
+    pub fn ty(&self) -> Type {
+        self.ty
+    }
+
+    /// Context the expression was checked for
+    pub fn context(&self) -> Context {
+        self.ctx
+    }
+
+    /// All keys of the expression, in order of appearance
+    pub fn keys(&self) -> Vec<&DescriptorKey> {
+        let mut keys = Vec::new();
+        self.collect_keys(&mut keys);
+        keys
+    }
+
+    fn collect_keys<'a>(&'a self, keys: &mut Vec<&'a DescriptorKey>) {
+        match self.fragment {
+            Fragment::PkK(ref key) | Fragment::PkH(ref key) => keys.push(key),
+            Fragment::Multi(_, ref k) | Fragment::MultiA(_, ref k) => keys.extend(k.iter()),
+            _ => {
+                for child in self.children() {
+                    child.collect_keys(keys);
+                }
+            }
+        }
+    }
+
+    fn children(&self) -> Vec<&Miniscript> {
+        match self.fragment {
+            Fragment::AndOr(ref x, ref y, ref z) => vec![x, y, z],
+            Fragment::AndV(ref x, ref y)
+            | Fragment::AndB(ref x, ref y)
+            | Fragment::OrB(ref x, ref y)
+            | Fragment::OrC(ref x, ref y)
+            | Fragment::OrD(ref x, ref y)
+            | Fragment::OrI(ref x, ref y) => vec![x, y],
+            Fragment::Thresh(_, ref subs) => subs.iter().collect(),
+            Fragment::Alt(ref x)
+            | Fragment::Swap(ref x)
+            | Fragment::Check(ref x)
+            | Fragment::DupIf(ref x)
+            | Fragment::Verify(ref x)
+            | Fragment::NonZero(ref x)
+            | Fragment::ZeroNotEqual(ref x) => vec![x],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether any key depends on a derivation index
+    pub fn is_ranged(&self) -> bool {
+        self.keys().iter().any(|key| key.is_ranged())
+    }
+
+    /// Script at a derivation index
+    pub fn encode(&self, index: u32) -> Result<Vec<u8>, Error> {
+        let ctx = self.ctx;
+        self.encode_with(&|key: &DescriptorKey| match ctx {
+            Context::Segwitv0 => Ok(key.compressed_key(index)?),
+            Context::Tapscript => Ok(key.x_only_key(index)?.to_vec()),
+        })
+    }
+
+    /// Length of the script in bytes
+    pub fn script_size(&self) -> usize {
+        let key_len = match self.ctx {
+            Context::Segwitv0 => 33,
+            Context::Tapscript => 32,
+        };
+        self.encode_with(&|_: &DescriptorKey| Ok(vec![0u8; key_len]))
+            .expect("placeholder keys always encode")
+            .len()
+    }
+
+    fn encode_with<F>(&self, key: &F) -> Result<Vec<u8>, Error>
+    where
+        F: Fn(&DescriptorKey) -> Result<Vec<u8>, Error>,
+    {
+        let mut s = Vec::new();
+        match self.fragment {
+            Fragment::False => s.push(script::OP_0),
+            Fragment::True => s.push(script::OP_1),
+            Fragment::PkK(ref k) => script::push_slice(&mut s, &key(k)?),
+            Fragment::PkH(ref k) => {
+                s.extend_from_slice(&[script::OP_DUP, script::OP_HASH160]);
+                script::push_slice(&mut s, &hash160(&key(k)?));
+                s.push(script::OP_EQUALVERIFY);
+            }
+            Fragment::Older(n) => {
+                script::push_int(&mut s, i64::from(n));
+                s.push(script::OP_CHECKSEQUENCEVERIFY);
+            }
+            Fragment::After(n) => {
+                script::push_int(&mut s, i64::from(n));
+                s.push(script::OP_CHECKLOCKTIMEVERIFY);
+            }
+            Fragment::Sha256(ref h) => encode_hash(&mut s, script::OP_SHA256, h),
+            Fragment::Hash256(ref h) => encode_hash(&mut s, script::OP_HASH256, h),
+            Fragment::Ripemd160(ref h) => encode_hash(&mut s, script::OP_RIPEMD160, h),
+            Fragment::Hash160(ref h) => encode_hash(&mut s, script::OP_HASH160, h),
+            Fragment::AndOr(ref x, ref y, ref z) => {
+                s.extend(x.encode_with(key)?);
+                s.push(script::OP_NOTIF);
+                s.extend(z.encode_with(key)?);
+                s.push(script::OP_ELSE);
+                s.extend(y.encode_with(key)?);
+                s.push(script::OP_ENDIF);
+            }
+            Fragment::AndV(ref x, ref y) => {
+                s.extend(x.encode_with(key)?);
+                s.extend(y.encode_with(key)?);
+            }
+            Fragment::AndB(ref x, ref y) => {
+                s.extend(x.encode_with(key)?);
+                s.extend(y.encode_with(key)?);
+                s.push(script::OP_BOOLAND);
+            }
+            Fragment::OrB(ref x, ref z) => {
+                s.extend(x.encode_with(key)?);
+                s.extend(z.encode_with(key)?);
+                s.push(script::OP_BOOLOR);
+            }
+            Fragment::OrC(ref x, ref z) => {
+                s.extend(x.encode_with(key)?);
+                s.push(script::OP_NOTIF);
+                s.extend(z.encode_with(key)?);
+                s.push(script::OP_ENDIF);
+            }
+            Fragment::OrD(ref x, ref z) => {
+                s.extend(x.encode_with(key)?);
+                s.extend_from_slice(&[script::OP_IFDUP, script::OP_NOTIF]);
+                s.extend(z.encode_with(key)?);
+                s.push(script::OP_ENDIF);
+            }
+            Fragment::OrI(ref x, ref z) => {
+                s.push(script::OP_IF);
+                s.extend(x.encode_with(key)?);
+                s.push(script::OP_ELSE);
+                s.extend(z.encode_with(key)?);
+                s.push(script::OP_ENDIF);
+            }
+            Fragment::Thresh(k, ref subs) => {
+                for (i, sub) in subs.iter().enumerate() {
+                    s.extend(sub.encode_with(key)?);
+                    if i > 0 {
+                        s.push(script::OP_ADD);
+                    }
+                }
+                script::push_int(&mut s, k as i64);
+                s.push(script::OP_EQUAL);
+            }
+            Fragment::Multi(k, ref keys) => {
+                let keys = keys.iter().map(key).collect::<Result<Vec<_>, _>>()?;
+                s = script::multisig(k, &keys);
+            }
+            Fragment::MultiA(k, ref keys) => {
+                for (i, k) in keys.iter().enumerate() {
+                    script::push_slice(&mut s, &key(k)?);
+                    s.push(if i == 0 {
+                        script::OP_CHECKSIG
+                    } else {
+                        script::OP_CHECKSIGADD
+                    });
+                }
+                script::push_int(&mut s, k as i64);
+                s.push(script::OP_NUMEQUAL);
+            }
+            Fragment::Alt(ref x) => {
+                s.push(script::OP_TOALTSTACK);
+                s.extend(x.encode_with(key)?);
+                s.push(script::OP_FROMALTSTACK);
+            }
+            Fragment::Swap(ref x) => {
+                s.push(script::OP_SWAP);
+                s.extend(x.encode_with(key)?);
+            }
+            Fragment::Check(ref x) => {
+                s.extend(x.encode_with(key)?);
+                s.push(script::OP_CHECKSIG);
+            }
+            Fragment::DupIf(ref x) => {
+                s.extend_from_slice(&[script::OP_DUP, script::OP_IF]);
+                s.extend(x.encode_with(key)?);
+                s.push(script::OP_ENDIF);
+            }
+            Fragment::Verify(ref x) => {
+                s.extend(x.encode_with(key)?);
+                match x.verify_opcode() {
+                    Some(opcode) => *s.last_mut().expect("non-empty script") = opcode,
+                    None => s.push(script::OP_VERIFY),
+                }
+            }
+            Fragment::NonZero(ref x) => {
+                s.extend_from_slice(&[script::OP_SIZE, script::OP_0NOTEQUAL, script::OP_IF]);
+                s.extend(x.encode_with(key)?);
+                s.push(script::OP_ENDIF);
+            }
+            Fragment::ZeroNotEqual(ref x) => {
+                s.extend(x.encode_with(key)?);
+                s.push(script::OP_0NOTEQUAL);
+            }
+        }
+        Ok(s)
+    }
+
+    /// The `VERIFY` form of the final opcode, if `v:` can merge into it
+    fn verify_opcode(&self) -> Option<u8> {
+        match self.fragment {
+            Fragment::Check(_) => Some(script::OP_CHECKSIGVERIFY),
+            Fragment::Sha256(_)
+            | Fragment::Hash256(_)
+            | Fragment::Ripemd160(_)
+            | Fragment::Hash160(_)
+            | Fragment::Thresh(..) => Some(script::OP_EQUALVERIFY),
+            Fragment::Multi(..) => Some(script::OP_CHECKMULTISIGVERIFY),
+            Fragment::MultiA(..) => Some(script::OP_NUMEQUALVERIFY),
+            Fragment::AndV(_, ref y) => y.verify_opcode(),
+            Fragment::Swap(ref x) => x.verify_opcode(),
+            _ => None,
+        }
+    }
+
+    /// Witness script of a P2WSH output at a derivation index
+    pub fn witness_program(&self, index: u32, network: Network) -> Result<WitnessProgram, Error> {
+        if self.ctx != Context::Segwitv0 {
+            return Err(Error::InvalidContext("wsh"));
+        }
+        Ok(WitnessProgram::p2wsh_from_script(
+            &self.encode(index)?,
+            network,
+        )?)
+    }
+
+    /// Tapscript leaf at a derivation index
+    pub fn tap_leaf(&self, index: u32) -> Result<TapLeaf, Error> {
+        if self.ctx != Context::Tapscript {
+            return Err(Error::InvalidContext("tr"));
+        }
+        Ok(TapLeaf::tapscript(self.encode(index)?))
+    }
+
+    /// Taproot output committing to this miniscript as its only leaf
+    pub fn taproot_output(
+        &self,
+        internal_key: [u8; 32],
+        index: u32,
+        network: Network,
+    ) -> Result<TaprootOutput, Error> {
+        let tree = TapTree::leaf(self.tap_leaf(index)?);
+        Ok(TaprootOutput::new(internal_key, Some(&tree), network)?)
+    }
+
+    /// Largest serialized size of the witness stack satisfying the script
+    ///
+    /// Each element is counted with its length prefix. The witness script
+    /// or control block itself is not included. Returns `None` if the
+    /// expression can never be satisfied.
+    pub fn max_satisfaction_size(&self) -> Option<usize> {
+        self.costs().0.map(|cost| cost.bytes)
+    }
+
+    /// Largest number of witness stack elements satisfying the script
+    pub fn max_satisfaction_witness_elements(&self) -> Option<usize> {
+        self.costs().0.map(|cost| cost.elements)
+    }
+
+    /// Largest serialized size of the witness stack dissatisfying the script
+    pub fn max_dissatisfaction_size(&self) -> Option<usize> {
+        self.costs().1.map(|cost| cost.bytes)
+    }
+
+    /// Satisfaction and dissatisfaction costs
+    fn costs(&self) -> (Option<Cost>, Option<Cost>) {
+        let (signature, key) = match self.ctx {
+            Context::Segwitv0 => (ECDSA_SIGNATURE_SIZE, 33),
+            Context::Tapscript => (SCHNORR_SIGNATURE_SIZE, 32),
+        };
+        let empty = Some(Cost::push(0));
+        let one = Some(Cost::push(1));
+        match self.fragment {
+            Fragment::False => (None, Some(Cost::EMPTY)),
+            Fragment::True => (Some(Cost::EMPTY), None),
+            Fragment::PkK(_) => (Some(Cost::push(signature)), empty),
+            Fragment::PkH(_) => (
+                Some(Cost::push(signature).then(Cost::push(key))),
+                Some(Cost::push(0).then(Cost::push(key))),
+            ),
+            Fragment::Older(_) | Fragment::After(_) => (Some(Cost::EMPTY), None),
+            Fragment::Sha256(_)
+            | Fragment::Hash256(_)
+            | Fragment::Ripemd160(_)
+            | Fragment::Hash160(_) => (Some(Cost::push(32)), Some(Cost::push(32))),
+            Fragment::AndOr(ref x, ref y, ref z) => {
+                let ((xs, xd), (ys, _), (zs, zd)) = (x.costs(), y.costs(), z.costs());
+                (max(and(ys, xs), and(zs, xd)), and(zd, xd))
+            }
+            Fragment::AndV(ref x, ref y) => (and(y.costs().0, x.costs().0), None),
+            Fragment::AndB(ref x, ref y) => {
+                let ((xs, xd), (ys, yd)) = (x.costs(), y.costs());
+                (and(ys, xs), and(yd, xd))
+            }
+            Fragment::OrB(ref x, ref z) => {
+                let ((xs, xd), (zs, zd)) = (x.costs(), z.costs());
+                (max(and(zd, xs), and(zs, xd)), and(zd, xd))
+            }
+            Fragment::OrC(ref x, ref z) => {
+                let ((xs, xd), (zs, _)) = (x.costs(), z.costs());
+                (max(xs, and(zs, xd)), None)
+            }
+            Fragment::OrD(ref x, ref z) => {
+                let ((xs, xd), (zs, zd)) = (x.costs(), z.costs());
+                (max(xs, and(zs, xd)), and(zd, xd))
+            }
+            Fragment::OrI(ref x, ref z) => {
+                let ((xs, xd), (zs, zd)) = (x.costs(), z.costs());
+                (
+                    max(and(xs, one), and(zs, empty)),
+                    max(and(xd, one), and(zd, empty)),
+                )
+            }
+            Fragment::Thresh(k, ref subs) => {
+                let costs = subs.iter().map(|sub| sub.costs()).collect::<Vec<_>>();
+                let dissat = costs
+                    .iter()
+                    .try_fold(Cost::EMPTY, |acc, c| c.1.map(|d| acc.then(d)));
+                // Satisfy the k subexpressions whose satisfactions add the most
+                let mut options = costs
+                    .iter()
+                    .filter_map(|&(sat, dissat)| match (sat, dissat) {
+                        (Some(sat), Some(dissat)) => Some((sat, dissat)),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                options.sort_by_key(|(sat, dissat)| {
+                    std::cmp::Reverse(sat.bytes as i64 - dissat.bytes as i64)
+                });
+                let sat = if options.len() == costs.len() {
+                    Some(
+                        options
+                            .iter()
+                            .enumerate()
+                            .fold(Cost::EMPTY, |acc, (i, &(sat, dissat))| {
+                                acc.then(if i < k { sat } else { dissat })
+                            }),
+                    )
+                } else {
+                    None
+                };
+                (sat, dissat)
+            }
+            Fragment::Multi(k, _) => (
+                Some((0..k).fold(Cost::push(0), |acc, _| acc.then(Cost::push(signature)))),
+                Some((0..=k).fold(Cost::EMPTY, |acc, _| acc.then(Cost::push(0)))),
+            ),
+            Fragment::MultiA(k, ref keys) => (
+                Some((0..keys.len()).fold(Cost::EMPTY, |acc, i| {
+                    acc.then(Cost::push(if i < k { signature } else { 0 }))
+                })),
+                Some((0..keys.len()).fold(Cost::EMPTY, |acc, _| acc.then(Cost::push(0)))),
+            ),
+            Fragment::Alt(ref x)
+            | Fragment::Swap(ref x)
+            | Fragment::Check(ref x)
+            | Fragment::ZeroNotEqual(ref x) => x.costs(),
+            Fragment::DupIf(ref x) => (and(x.costs().0, one), empty),
+            Fragment::Verify(ref x) => (x.costs().0, None),
+            Fragment::NonZero(ref x) => (x.costs().0, empty),
+        }
+    }
+
+    /// The wrapper letter this expression prints as, and the wrapped child
+    fn wrapper(&self) -> Option<(char, &Miniscript)> {
+        match self.fragment {
+            Fragment::Alt(ref x) => Some(('a', x)),
+            Fragment::Swap(ref x) => Some(('s', x)),
+            Fragment::Check(ref x) => match x.fragment {
+                Fragment::PkK(_) | Fragment::PkH(_) => None,
+                _ => Some(('c', x)),
+            },
+            Fragment::DupIf(ref x) => Some(('d', x)),
+            Fragment::Verify(ref x) => Some(('v', x)),
+            Fragment::NonZero(ref x) => Some(('j', x)),
+            Fragment::ZeroNotEqual(ref x) => Some(('n', x)),
+            Fragment::AndV(ref x, ref y) if y.fragment == Fragment::True => Some(('t', x)),
+            Fragment::OrI(ref x, ref z) if x.fragment == Fragment::False => Some(('l', z)),
+            Fragment::OrI(ref x, ref z) if z.fragment == Fragment::False => Some(('u', x)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Miniscript {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((c, child)) = self.wrapper() {
+            return match child.wrapper() {
+                Some(_) => write!(f, "{}{}", c, child),
+                None => write!(f, "{}:{}", c, child),
+            };
+        }
+        match self.fragment {
+            Fragment::False => write!(f, "0"),
+            Fragment::True => write!(f, "1"),
+            Fragment::PkK(ref key) => write!(f, "pk_k({})", key),
+            Fragment::PkH(ref key) => write!(f, "pk_h({})", key),
+            Fragment::Older(n) => write!(f, "older({})", n),
+            Fragment::After(n) => write!(f, "after({})", n),
+            Fragment::Sha256(ref h) => write!(f, "sha256({})", hex::encode(h)),
+            Fragment::Hash256(ref h) => write!(f, "hash256({})", hex::encode(h)),
+            Fragment::Ripemd160(ref h) => write!(f, "ripemd160({})", hex::encode(h)),
+            Fragment::Hash160(ref h) => write!(f, "hash160({})", hex::encode(h)),
+            Fragment::AndOr(ref x, ref y, ref z) => match z.fragment {
+                Fragment::False => write!(f, "and_n({},{})", x, y),
+                _ => write!(f, "andor({},{},{})", x, y, z),
+            },
+            Fragment::AndV(ref x, ref y) => write!(f, "and_v({},{})", x, y),
+            Fragment::AndB(ref x, ref y) => write!(f, "and_b({},{})", x, y),
+            Fragment::OrB(ref x, ref z) => write!(f, "or_b({},{})", x, z),
+            Fragment::OrC(ref x, ref z) => write!(f, "or_c({},{})", x, z),
+            Fragment::OrD(ref x, ref z) => write!(f, "or_d({},{})", x, z),
+            Fragment::OrI(ref x, ref z) => write!(f, "or_i({},{})", x, z),
+            Fragment::Thresh(k, ref subs) => {
+                write!(f, "thresh({}", k)?;
+                for sub in subs {
+                    write!(f, ",{}", sub)?;
+                }
+                write!(f, ")")
+            }
+            Fragment::Multi(k, ref keys) | Fragment::MultiA(k, ref keys) => {
+                let name = match self.fragment {
+                    Fragment::Multi(..) => "multi",
+                    _ => "multi_a",
+                };
+                write!(f, "{}({}", name, k)?;
+                for key in keys {
+                    write!(f, ",{}", key)?;
+                }
+                write!(f, ")")
+            }
+            Fragment::Check(ref x) => match x.fragment {
+                Fragment::PkK(ref key) => write!(f, "pk({})", key),
+                Fragment::PkH(ref key) => write!(f, "pkh({})", key),
+                _ => unreachable!("other checks print as wrappers"),
+            },
+            _ => unreachable!("wrappers are printed above"),
+        }
+    }
+}
+
+fn encode_hash(s: &mut Vec<u8>, opcode: u8, hash: &[u8]) {
+    s.push(script::OP_SIZE);
+    script::push_int(s, 32);
+    s.push(script::OP_EQUALVERIFY);
+    s.push(opcode);
+    script::push_slice(s, hash);
+    s.push(script::OP_EQUAL);
+}
+
+/// Computes the type of a fragment from the types of its children
+fn type_check(fragment: &Fragment, ctx: Context) -> Result<Type, Error> {
+    use self::BaseType::*;
+
+    let mismatch = |name| Err(Error::TypeMismatch(name));
+    Ok(match *fragment {
+        Fragment::False => Type::new(B, true, false, false, true, true),
+        Fragment::True => Type::new(B, true, false, false, false, true),
+        Fragment::PkK(ref key) => {
+            check_key(key, ctx)?;
+            Type::new(K, false, true, true, true, true)
+        }
+        Fragment::PkH(ref key) => {
+            check_key(key, ctx)?;
+            Type::new(K, false, false, true, true, true)
+        }
+        Fragment::Older(n) | Fragment::After(n) => {
+            if n == 0 || n >= 0x8000_0000 {
+                return Err(Error::InvalidTimelock(n));
+            }
+            Type::new(B, true, false, false, false, false)
+        }
+        Fragment::Sha256(_)
+        | Fragment::Hash256(_)
+        | Fragment::Ripemd160(_)
+        | Fragment::Hash160(_) => Type::new(B, false, true, true, true, true),
+        Fragment::AndOr(ref x, ref y, ref z) => {
+            let (x, y, z) = (x.ty, y.ty, z.ty);
+            if x.base != B || !x.d || !x.u || y.base != z.base || y.base == W {
+                return mismatch("andor");
+            }
+            Type::new(
+                y.base,
+                x.z && y.z && z.z,
+                (x.z && y.o && z.o) || (x.o && y.z && z.z),
+                x.n || (x.z && y.n && z.n),
+                z.d,
+                y.u && z.u,
+            )
+        }
+        Fragment::AndV(ref x, ref y) => {
+            let (x, y) = (x.ty, y.ty);
+            if x.base != V || y.base == W {
+                return mismatch("and_v");
+            }
+            Type::new(
+                y.base,
+                x.z && y.z,
+                (x.z && y.o) || (x.o && y.z),
+                x.n || (x.z && y.n),
+                false,
+                y.u,
+            )
+        }
+        Fragment::AndB(ref x, ref y) => {
+            let (x, y) = (x.ty, y.ty);
+            if x.base != B || y.base != W {
+                return mismatch("and_b");
+            }
+            Type::new(
+                B,
+                x.z && y.z,
+                (x.z && y.o) || (x.o && y.z),
+                x.n || (x.z && y.n),
+                x.d && y.d,
+                true,
+            )
+        }
+        Fragment::OrB(ref x, ref z) => {
+            let (x, z) = (x.ty, z.ty);
+            if x.base != B || !x.d || z.base != W || !z.d {
+                return mismatch("or_b");
+            }
+            Type::new(
+                B,
+                x.z && z.z,
+                (x.z && z.o) || (x.o && z.z),
+                false,
+                true,
+                true,
+            )
+        }
+        Fragment::OrC(ref x, ref z) => {
+            let (x, z) = (x.ty, z.ty);
+            if x.base != B || !x.d || !x.u || z.base != V {
+                return mismatch("or_c");
+            }
+            Type::new(V, x.z && z.z, x.o && z.z, false, false, false)
+        }
+        Fragment::OrD(ref x, ref z) => {
+            let (x, z) = (x.ty, z.ty);
+            if x.base != B || !x.d || !x.u || z.base != B {
+                return mismatch("or_d");
+            }
+            Type::new(B, x.z && z.z, x.o && z.z, false, z.d, z.u)
+        }
+        Fragment::OrI(ref x, ref z) => {
+            let (x, z) = (x.ty, z.ty);
+            if x.base != z.base || x.base == W {
+                return mismatch("or_i");
+            }
+            Type::new(x.base, false, x.z && z.z, false, x.d || z.d, x.u && z.u)
+        }
+        Fragment::Thresh(k, ref subs) => {
+            if k == 0 || k > subs.len() {
+                return Err(Error::InvalidThreshold);
+            }
+            for (i, sub) in subs.iter().enumerate() {
+                let expected = if i == 0 { B } else { W };
+                if sub.ty.base != expected || !sub.ty.d || !sub.ty.u {
+                    return mismatch("thresh");
+                }
+            }
+            let zeros = subs.iter().filter(|sub| sub.ty.z).count();
+            let ones = subs.iter().filter(|sub| sub.ty.o).count();
+            Type::new(
+                B,
+                zeros == subs.len(),
+                zeros + 1 == subs.len() && ones == 1,
+                false,
+                true,
+                true,
+            )
+        }
+        Fragment::Multi(k, ref keys) => {
+            if ctx != Context::Segwitv0 {
+                return Err(Error::InvalidContext("multi"));
+            }
+            if k == 0 || k > keys.len() || keys.len() > MAX_MULTI_KEYS {
+                return Err(Error::InvalidThreshold);
+            }
+            for key in keys {
+                check_key(key, ctx)?;
+            }
+            Type::new(B, false, false, true, true, true)
+        }
+        Fragment::MultiA(k, ref keys) => {
+            if ctx != Context::Tapscript {
+                return Err(Error::InvalidContext("multi_a"));
+            }
+            if k == 0 || k > keys.len() || keys.len() > MAX_MULTI_A_KEYS {
+                return Err(Error::InvalidThreshold);
+            }
+            Type::new(B, false, false, false, true, true)
+        }
+        Fragment::Alt(ref x) => {
+            let x = x.ty;
+            if x.base != B {
+                return mismatch("a:");
+            }
+            Type::new(W, false, false, false, x.d, x.u)
+        }
+        Fragment::Swap(ref x) => {
+            let x = x.ty;
+            if x.base != B || !x.o {
+                return mismatch("s:");
+            }
+            Type::new(W, false, false, false, x.d, x.u)
+        }
+        Fragment::Check(ref x) => {
+            let x = x.ty;
+            if x.base != K {
+                return mismatch("c:");
+            }
+            Type::new(B, false, x.o, x.n, x.d, true)
+        }
+        Fragment::DupIf(ref x) => {
+            let x = x.ty;
+            if x.base != V || !x.z {
+                return mismatch("d:");
+            }
+            Type::new(B, false, true, true, true, ctx == Context::Tapscript)
+        }
+        Fragment::Verify(ref x) => {
+            let x = x.ty;
+            if x.base != B {
+                return mismatch("v:");
+            }
+            Type::new(V, x.z, x.o, x.n, false, false)
+        }
+        Fragment::NonZero(ref x) => {
+            let x = x.ty;
+            if x.base != B || !x.n {
+                return mismatch("j:");
+            }
+            Type::new(B, false, x.o, true, true, x.u)
+        }
+        Fragment::ZeroNotEqual(ref x) => {
+            let x = x.ty;
+            if x.base != B {
+                return mismatch("n:");
+            }
+            Type::new(B, x.z, x.o, x.n, x.d, true)
+        }
+    })
+}
+
+/// P2WSH scripts only take compressed keys
+fn check_key(key: &DescriptorKey, ctx: Context) -> Result<(), Error> {
+    if ctx == Context::Segwitv0 {
+        descriptor::require_compressed(key)?;
+    }
+    Ok(())
+}
+
+/// Parses an expression nested `depth` levels deep, counting each wrapper
+/// as a level
+fn parse_node(s: &str, network: Network, ctx: Context, depth: usize) -> Result<Miniscript, Error> {
+    if depth > MAX_RECURSION_DEPTH {
+        return Err(Error::MaxDepthExceeded);
+    }
+    if let Some(colon) = s.find(':') {
+        if !s[..colon].contains('(') {
+            let wrappers = &s[..colon];
+            if wrappers.is_empty() {
+                return Err(Error::Descriptor(descriptor::Error::InvalidSyntax));
+            }
+            let mut ms = parse_node(&s[colon + 1..], network, ctx, depth + wrappers.len())?;
+            for c in wrappers.chars().rev() {
+                ms = wrap(c, ms)?;
+            }
+            return Ok(ms);
+        }
+    }
+    match s {
+        "0" => return Miniscript::new(Fragment::False, ctx),
+        "1" => return Miniscript::new(Fragment::True, ctx),
+        _ => {}
+    }
+    let (name, args) = split_call(s)?;
+    let arity = match name {
+        "pk_k" | "pk_h" | "pk" | "pkh" | "older" | "after" | "sha256" | "hash256" | "ripemd160"
+        | "hash160" => Some(1),
+        "and_v" | "and_b" | "and_n" | "or_b" | "or_c" | "or_d" | "or_i" => Some(2),
+        "andor" => Some(3),
+        "thresh" | "multi" | "multi_a" => None,
+        _ => return Err(Error::UnknownFragment(name.to_string())),
+    };
+    if let Some(arity) = arity {
+        if args.len() != arity {
+            return Err(Error::Descriptor(descriptor::Error::InvalidSyntax));
+        }
+    } else if args.len() < 2 {
+        return Err(Error::Descriptor(descriptor::Error::InvalidSyntax));
+    }
+    // Children are parsed here and the fragment built in `build_node`, which
+    // keeps the frames of this recursion small
+    let first_child = match name {
+        "andor" | "and_v" | "and_b" | "and_n" | "or_b" | "or_c" | "or_d" | "or_i" => 0,
+        "thresh" => 1,
+        _ => args.len(),
+    };
+    let children = args[first_child..]
+        .iter()
+        .map(|arg| parse_node(arg, network, ctx, depth + 1))
+        .collect::<Result<Vec<_>, _>>()?;
+    build_node(name, &args, children, network, ctx)
+}
+
+/// Builds a fragment from its arguments and already parsed children
+fn build_node(
+    name: &str,
+    args: &[&str],
+    children: Vec<Miniscript>,
+    network: Network,
+    ctx: Context,
+) -> Result<Miniscript, Error> {
+    let leaf = |fragment| Miniscript::new(fragment, ctx);
+    let key = |i: usize| DescriptorKey::parse(args[i], network);
+    let mut children = children.into_iter();
+    let mut node = || Box::new(children.next().expect("arity is checked"));
+    let fragment = match name {
+        "pk_k" => Fragment::PkK(key(0)?),
+        "pk_h" => Fragment::PkH(key(0)?),
+        "pk" => Fragment::Check(Box::new(leaf(Fragment::PkK(key(0)?))?)),
+        "pkh" => Fragment::Check(Box::new(leaf(Fragment::PkH(key(0)?))?)),
+        "older" => Fragment::Older(parse_timelock(args[0])?),
+        "after" => Fragment::After(parse_timelock(args[0])?),
+        "sha256" => Fragment::Sha256(parse_hash(args[0])?),
+        "hash256" => Fragment::Hash256(parse_hash(args[0])?),
+        "ripemd160" => Fragment::Ripemd160(parse_hash(args[0])?),
+        "hash160" => Fragment::Hash160(parse_hash(args[0])?),
+        "andor" => Fragment::AndOr(node(), node(), node()),
+        "and_v" => Fragment::AndV(node(), node()),
+        "and_b" => Fragment::AndB(node(), node()),
+        "and_n" => Fragment::AndOr(node(), node(), Box::new(leaf(Fragment::False)?)),
+        "or_b" => Fragment::OrB(node(), node()),
+        "or_c" => Fragment::OrC(node(), node()),
+        "or_d" => Fragment::OrD(node(), node()),
+        "or_i" => Fragment::OrI(node(), node()),
+        "thresh" => Fragment::Thresh(parse_count(args[0])?, children.collect()),
+        _ => {
+            let keys = (1..args.len()).map(key).collect::<Result<Vec<_>, _>>()?;
+            let k = parse_count(args[0])?;
+            if name == "multi" {
+                Fragment::Multi(k, keys)
+            } else {
+                Fragment::MultiA(k, keys)
+            }
+        }
+    };
+    leaf(fragment)
+}
+
+/// Applies a single-letter wrapper, expanding the `t:`, `l:` and `u:` sugar
+fn wrap(c: char, ms: Miniscript) -> Result<Miniscript, Error> {
+    let ctx = ms.ctx;
+    let x = Box::new(ms);
+    let fragment = match c {
+        'a' => Fragment::Alt(x),
+        's' => Fragment::Swap(x),
+        'c' => Fragment::Check(x),
+        'd' => Fragment::DupIf(x),
+        'v' => Fragment::Verify(x),
+        'j' => Fragment::NonZero(x),
+        'n' => Fragment::ZeroNotEqual(x),
+        't' => Fragment::AndV(x, Box::new(Miniscript::new(Fragment::True, ctx)?)),
+        'l' => Fragment::OrI(Box::new(Miniscript::new(Fragment::False, ctx)?), x),
+        'u' => Fragment::OrI(x, Box::new(Miniscript::new(Fragment::False, ctx)?)),
+        _ => return Err(Error::UnknownFragment(format!("{}:", c))),
+    };
+    Miniscript::new(fragment, ctx)
+}
+
+fn parse_count(s: &str) -> Result<usize, Error> {
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+        return Err(Error::InvalidThreshold);
+    }
+    s.parse().map_err(|_| Error::InvalidThreshold)
+}
+
+pub(crate) fn parse_timelock(s: &str) -> Result<u32, Error> {
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+        return Err(Error::Descriptor(descriptor::Error::InvalidSyntax));
+    }
+    match s.parse::<u32>() {
+        Ok(n) if n > 0 && n < 0x8000_0000 => Ok(n),
+        Ok(n) => Err(Error::InvalidTimelock(n)),
+        Err(_) => Err(Error::InvalidTimelock(u32::MAX)),
+    }
+}
+
+pub(crate) fn parse_hash<T: Default + AsMut<[u8]>>(s: &str) -> Result<T, Error> {
+    let bytes = hex::decode(s).ok_or(Error::Descriptor(descriptor::Error::InvalidHex))?;
+    let mut out = T::default();
+    if out.as_mut().len() != bytes.len() {
+        return Err(Error::InvalidHashLength);
+    }
+    out.as_mut().copy_from_slice(&bytes);
+    Ok(out)
+}
+
+/// Error types for miniscript and policies
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// Error in a key, hex string or the expression syntax
+    Descriptor(descriptor::Error),
+    /// Error building a witness program or Taproot output
+    WitnessProgram(::Error),
+    /// The fragment or wrapper is unknown
+    UnknownFragment(String),
+    /// The children of the named fragment have the wrong types
+    TypeMismatch(&'static str),
+    /// The named fragment is not available in this script context
+    InvalidContext(&'static str),
+    /// The top-level expression does not have type `B`
+    InvalidTopLevel,
+    /// A timelock is zero or not below 2^31
+    InvalidTimelock(u32),
+    /// A threshold is zero or larger than the number of subexpressions
+    InvalidThreshold,
+    /// A hash has the wrong length for its fragment
+    InvalidHashLength,
+    /// A policy branch weight is zero
+    InvalidWeight,
+    /// The expression is nested deeper than `MAX_RECURSION_DEPTH`
+    MaxDepthExceeded,
+    /// The script is larger than its context allows, the size given
+    ScriptTooLarge(usize),
+}
+
+impl From<descriptor::Error> for Error {
+    fn from(e: descriptor::Error) -> Error {
+        Error::Descriptor(e)
+    }
+}
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::WitnessProgram(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Descriptor(ref e) => write!(f, "{}", e),
+            Error::WitnessProgram(ref e) => write!(f, "{}", e),
+            Error::UnknownFragment(ref name) => write!(f, "unknown fragment {:?}", name),
+            Error::TypeMismatch(name) => write!(f, "invalid argument types for {}", name),
+            Error::InvalidContext(name) => write!(f, "{} is not valid in this context", name),
+            Error::InvalidTopLevel => write!(f, "top-level expression is not of type B"),
+            Error::InvalidTimelock(n) => write!(f, "invalid timelock {}", n),
+            Error::InvalidThreshold => write!(f, "invalid threshold"),
+            Error::InvalidHashLength => write!(f, "invalid hash length"),
+            Error::InvalidWeight => write!(f, "invalid branch weight"),
+            Error::MaxDepthExceeded => write!(f, "expression nested too deep"),
+            Error::ScriptTooLarge(size) => write!(f, "script of {} bytes is too large", size),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Descriptor(_) => "descriptor error",
+            Error::WitnessProgram(_) => "witness program error",
+            Error::UnknownFragment(_) => "unknown fragment",
+            Error::TypeMismatch(_) => "invalid argument types",
+            Error::InvalidContext(_) => "fragment not valid in this context",
+            Error::InvalidTopLevel => "top-level expression is not of type B",
+            Error::InvalidTimelock(_) => "invalid timelock",
+            Error::InvalidThreshold => "invalid threshold",
+            Error::InvalidHashLength => "invalid hash length",
+            Error::InvalidWeight => "invalid branch weight",
+            Error::MaxDepthExceeded => "expression nested too deep",
+            Error::ScriptTooLarge(_) => "script too large",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Descriptor(ref e) => Some(e),
+            Error::WitnessProgram(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const B: &str = "03a0434d9e47f3c86235477c7b1ae6ae5d3442d49b1943c2b752a68e2a47e247c7";
+
+    fn parse(s: &str) -> Result<Miniscript, Error> {
+        Miniscript::parse(s, Network::Bitcoin, Context::Segwitv0)
+    }
+
+    #[test]
+    fn wrappers() {
+        let ms = parse("lltvln:after(1231488000)").unwrap();
+        assert_eq!(
+            hex::encode(&ms.encode(0).unwrap()),
+            "6300676300676300670400046749b1926869516868"
+        );
+        assert_eq!(ms.to_string(), "lltvln:after(1231488000)");
+        assert_eq!(ms.script_size(), 21);
+
+        let ms = parse(&format!("and_v(v:pk({}),older(144))", A)).unwrap();
+        let mut expected = vec![0x21];
+        expected.extend_from_slice(&hex::decode(A).unwrap());
+        expected.extend_from_slice(&[0xad, 0x02, 0x90, 0x00, 0xb2]);
+        assert_eq!(ms.encode(0).unwrap(), expected);
+
+        let ms = parse(&format!("and_n(pk({}),older(6))", A)).unwrap();
+        assert_eq!(ms.to_string(), format!("and_n(pk({}),older(6))", A));
+        match *ms.fragment() {
+            Fragment::AndOr(_, _, ref z) => assert_eq!(*z.fragment(), Fragment::False),
+            _ => panic!("and_n is andor with 0"),
+        }
+    }
+
+    #[test]
+    fn types() {
+        let ms = parse(&format!("pk({})", A)).unwrap();
+        assert_eq!(
+            ms.ty(),
+            Type::new(BaseType::B, false, true, true, true, true)
+        );
+        let ms = parse(&format!("thresh(2,pk({}),s:pk({}),sln:older(10))", A, B)).unwrap();
+        assert_eq!(ms.ty().base, BaseType::B);
+        assert!(ms.ty().d && ms.ty().u);
+
+        assert_eq!(
+            parse(&format!("and_v(pk({}),pk({}))", A, B)),
+            Err(Error::TypeMismatch("and_v"))
+        );
+        assert_eq!(parse(&format!("v:pk({})", A)), Err(Error::InvalidTopLevel));
+        assert_eq!(
+            parse(&format!("thresh(2,pk({}),pk({}))", A, B)),
+            Err(Error::TypeMismatch("thresh"))
+        );
+        assert_eq!(
+            parse(&format!("multi_a(1,{})", A)),
+            Err(Error::InvalidContext("multi_a"))
+        );
+        assert_eq!(parse("older(0)"), Err(Error::InvalidTimelock(0)));
+        assert_eq!(
+            parse(&format!("pk({})", &A[2..])),
+            Err(Error::Descriptor(descriptor::Error::InvalidKey))
+        );
+        assert_eq!(
+            parse(&format!("x:pk({})", A)),
+            Err(Error::UnknownFragment("x:".to_string()))
+        );
+        assert_eq!(parse("sha256(00)"), Err(Error::InvalidHashLength));
+        assert_eq!(
+            parse("pk_q(00)"),
+            Err(Error::UnknownFragment("pk_q".to_string()))
+        );
+    }
+
+    #[test]
+    fn satisfaction_sizes() {
+        let s = format!("or_d(pk({}),and_v(v:pk({}),older(1000)))", A, B);
+        let ms = parse(&s).unwrap();
+        assert_eq!(ms.max_satisfaction_size(), Some(75));
+        assert_eq!(ms.max_satisfaction_witness_elements(), Some(2));
+        assert_eq!(ms.max_dissatisfaction_size(), None);
+
+        let tap = Miniscript::parse(&s, Network::Bitcoin, Context::Tapscript).unwrap();
+        assert_eq!(tap.max_satisfaction_size(), Some(67));
+
+        let ms = parse(&format!("multi(2,{},{})", A, B)).unwrap();
+        assert_eq!(ms.max_satisfaction_size(), Some(1 + 74 + 74));
+        assert_eq!(ms.max_dissatisfaction_size(), Some(3));
+
+        let ms = parse(&format!("pkh({})", A)).unwrap();
+        assert_eq!(ms.max_satisfaction_size(), Some(74 + 34));
+
+        let ms = parse("0").unwrap();
+        assert_eq!(ms.max_satisfaction_size(), None);
+    }
+
+    #[test]
+    fn programs() {
+        let s = format!("or_d(pk({}),and_v(v:pk({}),older(1000)))", A, B);
+        let ms = parse(&s).unwrap();
+        let script = ms.encode(0).unwrap();
+        for network in [Network::Monacoin, Network::Litecoin, Network::Groestlcoin].iter() {
+            assert_eq!(
+                ms.witness_program(0, *network).unwrap(),
+                WitnessProgram::p2wsh_from_script(&script, *network).unwrap()
+            );
+        }
+        assert_eq!(ms.tap_leaf(0), Err(Error::InvalidContext("tr")));
+
+        let tap = Miniscript::parse(&s, Network::Bitcoin, Context::Tapscript).unwrap();
+        let leaf = tap.tap_leaf(0).unwrap();
+        assert_eq!(leaf.script()[0], 0x20);
+        let mut internal = [0u8; 32];
+        internal.copy_from_slice(&hex::decode(&B[2..]).unwrap());
+        let output = tap.taproot_output(internal, 0, Network::Litecoin).unwrap();
+        assert_eq!(output.witness_program().network(), Network::Litecoin);
+        assert_eq!(
+            tap.witness_program(0, Network::Bitcoin),
+            Err(Error::InvalidContext("wsh"))
+        );
+    }
+
+    fn nested_and_v(levels: usize, key: &str) -> String {
+        let mut s = format!("pk({})", key);
+        for _ in 0..levels {
+            s = format!("and_v(v:pk({}),{})", key, s);
+        }
+        s
+    }
+
+    #[test]
+    fn limits() {
+        // The `v:` wrapper of the innermost left branch is the deepest node
+        let ms = parse(&nested_and_v(MAX_RECURSION_DEPTH - 1, A)).unwrap();
+        assert_eq!(ms.keys().len(), MAX_RECURSION_DEPTH);
+        assert!(ms.script_size() <= MAX_STANDARD_P2WSH_SCRIPT_SIZE);
+        assert!(ms.max_satisfaction_size().is_some());
+        let tap = nested_and_v(MAX_RECURSION_DEPTH - 1, &A[2..]);
+        assert!(Miniscript::parse(&tap, Network::Bitcoin, Context::Tapscript).is_ok());
+
+        assert_eq!(
+            parse(&nested_and_v(MAX_RECURSION_DEPTH, A)),
+            Err(Error::MaxDepthExceeded)
+        );
+        assert_eq!(parse(&nested_and_v(1_000, A)), Err(Error::MaxDepthExceeded));
+        let wrappers = format!("{}:pk({})", "n".repeat(MAX_RECURSION_DEPTH + 1), A);
+        assert_eq!(parse(&wrappers), Err(Error::MaxDepthExceeded));
+
+        let subs = vec![format!("s:pk({})", A); 110];
+        let wide = format!("thresh(1,pk({}),{})", A, subs.join(","));
+        match parse(&wide) {
+            Err(Error::ScriptTooLarge(size)) => assert!(size > MAX_STANDARD_P2WSH_SCRIPT_SIZE),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,475 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Spending policies
+//!
+//! A policy such as `or(pk(A),and(pk(B),older(1000)))` describes who may
+//! spend an output without fixing the script. `Policy::compile` picks a
+//! miniscript for it, trying the usual encodings of each `and` and `or` and
+//! keeping the one with the smallest script plus expected witness size.
+
+use std::fmt;
+
+use constants::Network;
+use descriptor::{self, split_call, DescriptorKey};
+use hex;
+use miniscript::{
+    parse_hash, parse_timelock, Context, Error, Fragment, Miniscript, MAX_RECURSION_DEPTH,
+};
+
+/// A spending policy
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub enum Policy {
+    /// `pk(KEY)`: a signature for the key
+    Key(DescriptorKey),
+    /// `after(n)`: an absolute timelock
+    After(u32),
+    /// `older(n)`: a relative timelock
+    Older(u32),
+    /// `sha256(h)`: a SHA256 preimage
+    Sha256([u8; 32]),
+    /// `hash256(h)`: a double SHA256 preimage
+    Hash256([u8; 32]),
+    /// `ripemd160(h)`: a RIPEMD160 preimage
+    Ripemd160([u8; 20]),
+    /// `hash160(h)`: a HASH160 preimage
+    Hash160([u8; 20]),
+    /// `and(A,B)`: both policies
+    And(Box<Policy>, Box<Policy>),
+    /// `or(w@A,w@B)`: either policy, with relative branch weights
+    Or(usize, Box<Policy>, usize, Box<Policy>),
+    /// `thresh(k,A,B,...)`: at least `k` of the policies
+    Thresh(usize, Vec<Policy>),
+}
+
+impl Policy {
+    /// Parses a policy, decoding extended keys for `network`
+    pub fn parse(s: &str, network: Network) -> Result<Policy, Error> {
+        Policy::parse_node(s, network, 0)
+    }
+
+    fn parse_node(s: &str, network: Network, depth: usize) -> Result<Policy, Error> {
+        if depth > MAX_RECURSION_DEPTH {
+            return Err(Error::MaxDepthExceeded);
+        }
+        let (name, args) = split_call(s)?;
+        let arity = match name {
+            "pk" | "after" | "older" | "sha256" | "hash256" | "ripemd160" | "hash160" => 1,
+            "and" | "or" => 2,
+            "thresh" => args.len().max(2),
+            _ => return Err(Error::UnknownFragment(name.to_string())),
+        };
+        if args.len() != arity {
+            return Err(Error::Descriptor(descriptor::Error::InvalidSyntax));
+        }
+        let sub = |s: &str| Policy::parse_node(s, network, depth + 1).map(Box::new);
+        Ok(match name {
+            "pk" => Policy::Key(DescriptorKey::parse(args[0], network)?),
+            "after" => Policy::After(parse_timelock(args[0])?),
+            "older" => Policy::Older(parse_timelock(args[0])?),
+            "sha256" => Policy::Sha256(parse_hash(args[0])?),
+            "hash256" => Policy::Hash256(parse_hash(args[0])?),
+            "ripemd160" => Policy::Ripemd160(parse_hash(args[0])?),
+            "hash160" => Policy::Hash160(parse_hash(args[0])?),
+            "and" => Policy::And(sub(args[0])?, sub(args[1])?),
+            "or" => {
+                let (left_weight, left) = split_weight(args[0])?;
+                let (right_weight, right) = split_weight(args[1])?;
+                Policy::Or(left_weight, sub(left)?, right_weight, sub(right)?)
+            }
+            _ => {
+                let k = args[0];
+                if k.is_empty() || !k.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(Error::InvalidThreshold);
+                }
+                let k = k.parse().map_err(|_| Error::InvalidThreshold)?;
+                let subs = args[1..]
+                    .iter()
+                    .map(|s| Policy::parse_node(s, network, depth + 1))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if k == 0 || k > subs.len() {
+                    return Err(Error::InvalidThreshold);
+                }
+                Policy::Thresh(k, subs)
+            }
+        })
+    }
+
+    /// Compiles the policy into a top-level miniscript for a context
+    pub fn compile(&self, ctx: Context) -> Result<Miniscript, Error> {
+        let ms = self.compile_node(ctx, 0)?;
+        ms.check_script_size()?;
+        Ok(ms)
+    }
+
+    /// Compiles a subpolicy `depth` levels down, counting each folded
+    /// `thresh` subpolicy as a level
+    fn compile_node(&self, ctx: Context, depth: usize) -> Result<Miniscript, Error> {
+        if depth > MAX_RECURSION_DEPTH {
+            return Err(Error::MaxDepthExceeded);
+        }
+        let ms = |fragment| Miniscript::new(fragment, ctx);
+        match *self {
+            Policy::Key(ref key) => ms(Fragment::Check(Box::new(ms(Fragment::PkK(key.clone()))?))),
+            Policy::After(n) => ms(Fragment::After(n)),
+            Policy::Older(n) => ms(Fragment::Older(n)),
+            Policy::Sha256(h) => ms(Fragment::Sha256(h)),
+            Policy::Hash256(h) => ms(Fragment::Hash256(h)),
+            Policy::Ripemd160(h) => ms(Fragment::Ripemd160(h)),
+            Policy::Hash160(h) => ms(Fragment::Hash160(h)),
+            Policy::And(ref a, ref b) => {
+                let (a, b) = (
+                    a.compile_node(ctx, depth + 1)?,
+                    b.compile_node(ctx, depth + 1)?,
+                );
+                let candidates = vec![and_v(&a, &b), and_v(&b, &a), and_b(&a, &b), and_b(&b, &a)]
+                    .into_iter()
+                    .map(|ms| {
+                        let cost = ms.as_ref().ok().and_then(|ms| ms.max_satisfaction_size());
+                        (ms, cost.map(|cost| cost as f64))
+                    })
+                    .collect();
+                best(candidates, "and")
+            }
+            Policy::Or(weight_a, ref a, weight_b, ref b) => {
+                let (a, b) = (
+                    a.compile_node(ctx, depth + 1)?,
+                    b.compile_node(ctx, depth + 1)?,
+                );
+                let p = weight_a as f64 / (weight_a as f64 + weight_b as f64);
+                compile_or(&a, &b, p)
+            }
+            Policy::Thresh(k, ref subs) => {
+                let keys = subs
+                    .iter()
+                    .filter_map(|sub| match *sub {
+                        Policy::Key(ref key) => Some(key.clone()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                if keys.len() == subs.len() {
+                    let multi = match ctx {
+                        Context::Segwitv0 => Fragment::Multi(k, keys),
+                        Context::Tapscript => Fragment::MultiA(k, keys),
+                    };
+                    if let Ok(multi) = ms(multi) {
+                        return Ok(multi);
+                    }
+                }
+                if subs.len() == 1 {
+                    return subs[0].compile_node(ctx, depth + 1);
+                }
+                if k == subs.len() || k == 1 {
+                    let (first, rest) = subs.split_first().expect("thresh has subpolicies");
+                    let rest = Policy::Thresh(if k == 1 { 1 } else { rest.len() }, rest.to_vec());
+                    let folded = if k == 1 {
+                        Policy::Or(
+                            1,
+                            Box::new(first.clone()),
+                            rest_weight(&rest),
+                            Box::new(rest),
+                        )
+                    } else {
+                        Policy::And(Box::new(first.clone()), Box::new(rest))
+                    };
+                    return folded.compile_node(ctx, depth + 1);
+                }
+                let subs = subs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, sub)| {
+                        let sub = into_du(sub.compile_node(ctx, depth + 1)?)?;
+                        if i == 0 {
+                            Ok(sub)
+                        } else {
+                            into_w(sub)
+                        }
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                ms(Fragment::Thresh(k, subs))
+            }
+        }
+    }
+}
+
+/// Weight of a folded `thresh(1,...)` remainder: one per subpolicy
+fn rest_weight(rest: &Policy) -> usize {
+    match *rest {
+        Policy::Thresh(_, ref subs) => subs.len(),
+        _ => 1,
+    }
+}
+
+/// Splits an optional `weight@` prefix off an `or` branch
+fn split_weight(s: &str) -> Result<(usize, &str), Error> {
+    match s.find('@') {
+        Some(at) if s[..at].chars().all(|c| c.is_ascii_digit()) && at > 0 => {
+            match s[..at].parse::<usize>() {
+                Ok(weight) if weight > 0 => Ok((weight, &s[at + 1..])),
+                _ => Err(Error::InvalidWeight),
+            }
+        }
+        _ => Ok((1, s)),
+    }
+}
+
+fn node(fragment: Fragment, ctx: Context) -> Result<Miniscript, Error> {
+    Miniscript::new(fragment, ctx)
+}
+
+fn and_v(x: &Miniscript, y: &Miniscript) -> Result<Miniscript, Error> {
+    let ctx = x.context();
+    let v = node(Fragment::Verify(Box::new(x.clone())), ctx)?;
+    node(Fragment::AndV(Box::new(v), Box::new(y.clone())), ctx)
+}
+
+fn and_b(x: &Miniscript, y: &Miniscript) -> Result<Miniscript, Error> {
+    let w = into_w(y.clone())?;
+    node(
+        Fragment::AndB(Box::new(x.clone()), Box::new(w)),
+        x.context(),
+    )
+}
+
+/// Wraps a `B` expression into a `W` one
+fn into_w(ms: Miniscript) -> Result<Miniscript, Error> {
+    let ctx = ms.context();
+    if ms.ty().o {
+        node(Fragment::Swap(Box::new(ms)), ctx)
+    } else {
+        node(Fragment::Alt(Box::new(ms)), ctx)
+    }
+}
+
+/// Wraps a `B` expression with `n:` and `l:` until it is unit and
+/// dissatisfiable
+fn into_du(ms: Miniscript) -> Result<Miniscript, Error> {
+    let ctx = ms.context();
+    let ms = if ms.ty().u {
+        ms
+    } else {
+        node(Fragment::ZeroNotEqual(Box::new(ms)), ctx)?
+    };
+    if ms.ty().d {
+        Ok(ms)
+    } else {
+        let zero = node(Fragment::False, ctx)?;
+        node(Fragment::OrI(Box::new(zero), Box::new(ms)), ctx)
+    }
+}
+
+/// Picks the encoding of `or(a,b)` with the smallest expected cost, `p`
+/// being the probability that `a` is the branch used
+fn compile_or(a: &Miniscript, b: &Miniscript, p: f64) -> Result<Miniscript, Error> {
+    let ctx = a.context();
+    let sat = |ms: &Miniscript| ms.max_satisfaction_size().map(|s| s as f64);
+    let dissat = |ms: &Miniscript| ms.max_dissatisfaction_size().map(|s| s as f64);
+    let mut candidates = Vec::new();
+    for &(x, z, px) in [(a, b, p), (b, a, 1.0 - p)].iter() {
+        let pz = 1.0 - px;
+        let or_d = node(Fragment::OrD(Box::new(x.clone()), Box::new(z.clone())), ctx);
+        let cost = match (sat(x), sat(z), dissat(x)) {
+            (Some(xs), Some(zs), Some(xd)) => Some(px * xs + pz * (zs + xd)),
+            _ => None,
+        };
+        candidates.push((or_d, cost));
+
+        let or_b = into_w(z.clone())
+            .and_then(|w| node(Fragment::OrB(Box::new(x.clone()), Box::new(w)), ctx));
+        let cost = match (sat(x), sat(z), dissat(x), dissat(z)) {
+            (Some(xs), Some(zs), Some(xd), Some(zd)) => Some(px * (xs + zd) + pz * (zs + xd)),
+            _ => None,
+        };
+        candidates.push((or_b, cost));
+
+        let or_i = node(Fragment::OrI(Box::new(x.clone()), Box::new(z.clone())), ctx);
+        let cost = match (sat(x), sat(z)) {
+            (Some(xs), Some(zs)) => Some(px * (xs + 2.0) + pz * (zs + 1.0)),
+            _ => None,
+        };
+        candidates.push((or_i, cost));
+    }
+    best(candidates, "or")
+}
+
+/// Picks the valid candidate with the smallest script plus witness cost
+fn best(
+    candidates: Vec<(Result<Miniscript, Error>, Option<f64>)>,
+    name: &'static str,
+) -> Result<Miniscript, Error> {
+    let mut best: Option<(f64, Miniscript)> = None;
+    for (candidate, cost) in candidates {
+        if let (Ok(ms), Some(cost)) = (candidate, cost) {
+            let total = ms.script_size() as f64 + cost;
+            if total < best.as_ref().map_or(f64::INFINITY, |b| b.0) {
+                best = Some((total, ms));
+            }
+        }
+    }
+    best.map(|(_, ms)| ms).ok_or(Error::TypeMismatch(name))
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Policy::Key(ref key) => write!(f, "pk({})", key),
+            Policy::After(n) => write!(f, "after({})", n),
+            Policy::Older(n) => write!(f, "older({})", n),
+            Policy::Sha256(ref h) => write!(f, "sha256({})", hex::encode(h)),
+            Policy::Hash256(ref h) => write!(f, "hash256({})", hex::encode(h)),
+            Policy::Ripemd160(ref h) => write!(f, "ripemd160({})", hex::encode(h)),
+            Policy::Hash160(ref h) => write!(f, "hash160({})", hex::encode(h)),
+            Policy::And(ref a, ref b) => write!(f, "and({},{})", a, b),
+            Policy::Or(1, ref a, 1, ref b) => write!(f, "or({},{})", a, b),
+            Policy::Or(wa, ref a, wb, ref b) => write!(f, "or({}@{},{}@{})", wa, a, wb, b),
+            Policy::Thresh(k, ref subs) => {
+                write!(f, "thresh({}", k)?;
+                for sub in subs {
+                    write!(f, ",{}", sub)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const B: &str = "03a0434d9e47f3c86235477c7b1ae6ae5d3442d49b1943c2b752a68e2a47e247c7";
+    const C: &str = "022f8bde4d1a07209355b4a7250a5c5128e88b84bddc619ab7cba8d569b240efe4";
+
+    fn compile(s: &str, ctx: Context) -> String {
+        Policy::parse(s, Network::Bitcoin)
+            .unwrap()
+            .compile(ctx)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn compile_or_and() {
+        let s = format!("or(pk({}),and(pk({}),older(1000)))", A, B);
+        assert_eq!(
+            compile(&s, Context::Segwitv0),
+            format!("or_d(pk({}),and_v(v:pk({}),older(1000)))", A, B)
+        );
+        assert_eq!(
+            compile(&format!("or(pk({}),pk({}))", A, B), Context::Segwitv0),
+            format!("or_b(pk({}),s:pk({}))", A, B)
+        );
+        assert_eq!(
+            compile(&format!("and(older(10),pk({}))", A), Context::Segwitv0),
+            format!("and_v(v:pk({}),older(10))", A)
+        );
+
+        let policy = Policy::parse(&s, Network::Monacoin).unwrap();
+        let ms = policy.compile(Context::Segwitv0).unwrap();
+        let program = ms.witness_program(0, Network::Monacoin).unwrap();
+        assert!(program.to_address().starts_with("mona1q"));
+    }
+
+    #[test]
+    fn compile_thresh() {
+        let s = format!("thresh(2,pk({}),pk({}),pk({}))", A, B, C);
+        assert_eq!(
+            compile(&s, Context::Segwitv0),
+            format!("multi(2,{},{},{})", A, B, C)
+        );
+        let x_only = format!("thresh(2,pk({}),pk({}))", &A[2..], &B[2..]);
+        assert_eq!(
+            compile(&x_only, Context::Tapscript),
+            format!("multi_a(2,{},{})", &A[2..], &B[2..])
+        );
+        assert_eq!(
+            compile(
+                &format!("thresh(2,pk({}),pk({}),older(1000))", A, B),
+                Context::Segwitv0
+            ),
+            format!("thresh(2,pk({}),s:pk({}),sln:older(1000))", A, B)
+        );
+        let all = compile(
+            &format!("thresh(2,pk({}),older(1000))", A),
+            Context::Segwitv0,
+        );
+        assert_eq!(all, format!("and_v(v:pk({}),older(1000))", A));
+        let any = compile(&format!("thresh(1,pk({}),pk({}))", A, B), Context::Segwitv0);
+        assert_eq!(any, format!("multi(1,{},{})", A, B));
+    }
+
+    #[test]
+    fn parse_policies() {
+        let s = format!("or(9@pk({}),1@sha256({}))", A, &A[2..]);
+        let policy = Policy::parse(&s, Network::Bitcoin).unwrap();
+        assert_eq!(policy.to_string(), s);
+        assert!(policy.compile(Context::Segwitv0).is_ok());
+        assert_eq!(
+            Policy::parse(&format!("or(0@pk({}),pk({}))", A, B), Network::Bitcoin),
+            Err(Error::InvalidWeight)
+        );
+        assert_eq!(
+            Policy::parse(&format!("thresh(3,pk({}),pk({}))", A, B), Network::Bitcoin),
+            Err(Error::InvalidThreshold)
+        );
+        assert_eq!(
+            Policy::parse("after(0)", Network::Bitcoin),
+            Err(Error::InvalidTimelock(0))
+        );
+        assert_eq!(
+            Policy::parse(&format!("pk({})", A), Network::Bitcoin)
+                .unwrap()
+                .compile(Context::Segwitv0)
+                .unwrap()
+                .max_satisfaction_size(),
+            Some(74)
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let s = format!("or({}@pk({}),1@pk({}))", usize::MAX, A, B);
+        let policy = Policy::parse(&s, Network::Bitcoin).unwrap();
+        assert!(policy.compile(Context::Segwitv0).is_ok());
+
+        let nested = |levels: usize| {
+            let mut s = format!("pk({})", A);
+            for _ in 0..levels {
+                s = format!("and(older(10),{})", s);
+            }
+            s
+        };
+        let policy = Policy::parse(&nested(MAX_RECURSION_DEPTH), Network::Bitcoin).unwrap();
+        assert!(policy.compile(Context::Tapscript).is_ok());
+        assert_eq!(
+            Policy::parse(&nested(1_000), Network::Bitcoin),
+            Err(Error::MaxDepthExceeded)
+        );
+
+        let subs = vec![format!("and(pk({}),older(10))", A); 300];
+        let wide = format!("thresh(300,{})", subs.join(","));
+        let policy = Policy::parse(&wide, Network::Bitcoin).unwrap();
+        assert_eq!(
+            policy.compile(Context::Segwitv0),
+            Err(Error::MaxDepthExceeded)
+        );
+    }
+}
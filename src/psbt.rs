@@ -0,0 +1,850 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Partially signed transaction output verification
+//!
+//! Reads the outputs of BIP-0174 (version 0) and BIP-0370 (version 2)
+//! PSBTs and checks each one against the addresses a signer expects to pay
+//! and the descriptors of its own change addresses.
+
+use std::collections::BTreeSet;
+use std::{error, fmt};
+
+use base64;
+use constants::Network;
+use descriptor::{self, Descriptor};
+use encode::Reader;
+use hex;
+use transaction::{self, Transaction};
+use wrapped::{self, Wrapper};
+use WitnessProgram;
+
+/// Magic bytes opening every PSBT
+pub const PSBT_MAGIC: [u8; 5] = [b'p', b's', b'b', b't', 0xff];
+
+const GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const GLOBAL_TX_VERSION: u8 = 0x02;
+const GLOBAL_INPUT_COUNT: u8 = 0x04;
+const GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+const GLOBAL_VERSION: u8 = 0xfb;
+
+const OUTPUT_WITNESS_SCRIPT: u8 = 0x01;
+const OUTPUT_AMOUNT: u8 = 0x03;
+const OUTPUT_SCRIPT: u8 = 0x04;
+
+/// A key-value map of a PSBT, with keys in file order
+type Map<'a> = Vec<(&'a [u8], &'a [u8])>;
+
+/// An output of a PSBT
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct PsbtOutput {
+    value: u64,
+    script_pubkey: Vec<u8>,
+    witness_script: Option<Vec<u8>>,
+}
+
+impl PsbtOutput {
+    /// Amount in the network's base unit
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    /// Output script
+    pub fn script_pubkey(&self) -> &[u8] {
+        &self.script_pubkey
+    }
+
+    /// Witness script supplied for the output, if any
+    pub fn witness_script(&self) -> Option<&[u8]> {
+        self.witness_script.as_ref().map(|s| &s[..])
+    }
+
+    /// Witness program locked to by the output script, if it is one
+    ///
+    /// Name and asset wrappers are stripped as by
+    /// `wrapped::parse_scriptpubkey`.
+    pub fn witness_program(&self, network: Network) -> Option<WitnessProgram> {
+        wrapped::witness_program(&self.script_pubkey, network)
+    }
+}
+
+/// A parsed PSBT
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct Psbt {
+    network: Network,
+    version: u32,
+    input_count: usize,
+    outputs: Vec<PsbtOutput>,
+}
+
+impl Psbt {
+    /// Parses a binary PSBT
+    ///
+    /// A version 0 PSBT is rejected if its unsigned transaction has any
+    /// scriptSig or witness, as BIP-0174 requires.
+    pub fn parse(data: &[u8], network: Network) -> Result<Psbt, Error> {
+        let mut reader = Reader::new(data);
+        if reader.read_bytes(PSBT_MAGIC.len()) != Some(&PSBT_MAGIC[..]) {
+            return Err(Error::InvalidMagic);
+        }
+        let global = read_map(&mut reader)?;
+        let version = match find(&global, GLOBAL_VERSION) {
+            Some(value) => read_exact_u32(value)?,
+            None => 0,
+        };
+
+        let (input_count, mut outputs): (usize, Vec<PsbtOutput>) = match version {
+            0 => {
+                let tx = find(&global, GLOBAL_UNSIGNED_TX).ok_or(Error::MissingField)?;
+                let tx = Transaction::parse(tx, network)?;
+                if tx
+                    .inputs()
+                    .iter()
+                    .any(|input| !input.script_sig().is_empty() || !input.witness().is_empty())
+                {
+                    return Err(Error::SignedTransaction);
+                }
+                if [GLOBAL_TX_VERSION, GLOBAL_INPUT_COUNT, GLOBAL_OUTPUT_COUNT]
+                    .iter()
+                    .any(|&key| find(&global, key).is_some())
+                {
+                    return Err(Error::UnexpectedField);
+                }
+                let outputs = tx
+                    .outputs()
+                    .iter()
+                    .map(|output| PsbtOutput {
+                        value: output.value(),
+                        script_pubkey: output.script_pubkey().to_vec(),
+                        witness_script: None,
+                    })
+                    .collect();
+                (tx.inputs().len(), outputs)
+            }
+            2 => {
+                if find(&global, GLOBAL_UNSIGNED_TX).is_some() {
+                    return Err(Error::UnexpectedField);
+                }
+                find(&global, GLOBAL_TX_VERSION).ok_or(Error::MissingField)?;
+                let inputs = find(&global, GLOBAL_INPUT_COUNT).ok_or(Error::MissingField)?;
+                let outputs = find(&global, GLOBAL_OUTPUT_COUNT).ok_or(Error::MissingField)?;
+                let inputs = read_exact_compact_size(inputs)?;
+                let outputs = read_exact_compact_size(outputs)?;
+                // Every map takes at least its separator byte
+                if inputs.saturating_add(outputs) > reader.remaining() as u64 {
+                    return Err(Error::UnexpectedEnd);
+                }
+                let outputs = (0..outputs)
+                    .map(|_| PsbtOutput {
+                        value: 0,
+                        script_pubkey: Vec::new(),
+                        witness_script: None,
+                    })
+                    .collect();
+                (inputs as usize, outputs)
+            }
+            version => return Err(Error::UnsupportedVersion(version)),
+        };
+
+        for _ in 0..input_count {
+            read_map(&mut reader)?;
+        }
+        for output in &mut outputs {
+            let map = read_map(&mut reader)?;
+            if let Some(script) = find(&map, OUTPUT_WITNESS_SCRIPT) {
+                output.witness_script = Some(script.to_vec());
+            }
+            if version == 2 {
+                let amount = find(&map, OUTPUT_AMOUNT).ok_or(Error::MissingField)?;
+                if amount.len() != 8 {
+                    return Err(Error::InvalidValue);
+                }
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(amount);
+                let amount = i64::from_le_bytes(buf);
+                if amount < 0 {
+                    return Err(Error::InvalidValue);
+                }
+                output.value = amount as u64;
+                let script = find(&map, OUTPUT_SCRIPT).ok_or(Error::MissingField)?;
+                output.script_pubkey = script.to_vec();
+            }
+        }
+        if reader.remaining() != 0 {
+            return Err(Error::TrailingData);
+        }
+        Ok(Psbt {
+            network,
+            version,
+            input_count,
+            outputs,
+        })
+    }
+
+    /// Parses a base64-encoded PSBT
+    pub fn from_base64(s: &str, network: Network) -> Result<Psbt, Error> {
+        let data = base64::decode(s).ok_or(Error::InvalidEncoding)?;
+        Psbt::parse(&data, network)
+    }
+
+    /// Parses a hex-encoded PSBT
+    pub fn from_hex(s: &str, network: Network) -> Result<Psbt, Error> {
+        let data = hex::decode(s).ok_or(Error::InvalidEncoding)?;
+        Psbt::parse(&data, network)
+    }
+
+    /// Network the PSBT was parsed for
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// PSBT version, 0 or 2
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Number of inputs
+    pub fn input_count(&self) -> usize {
+        self.input_count
+    }
+
+    /// Outputs in order
+    pub fn outputs(&self) -> &[PsbtOutput] {
+        &self.outputs
+    }
+
+    /// Checks every output against the expected payments and change
+    /// descriptors
+    ///
+    /// Each expected payment is an address with the amount it should
+    /// receive, and is matched by at most one output. Ranged change
+    /// descriptors are derived for indices below `lookahead`.
+    pub fn verify(
+        &self,
+        expected: &[(WitnessProgram, u64)],
+        change: &[Descriptor],
+        lookahead: u32,
+    ) -> Result<VerificationReport, Error> {
+        let mut change_programs = Vec::new();
+        for (n, descriptor) in change.iter().enumerate() {
+            let count = if descriptor.is_ranged() { lookahead } else { 1 };
+            for (index, program) in descriptor
+                .witness_programs(0, count)?
+                .into_iter()
+                .enumerate()
+            {
+                change_programs.push((n, index as u32, program));
+            }
+        }
+
+        let mut paid = BTreeSet::new();
+        let mut outputs = Vec::with_capacity(self.outputs.len());
+        for (vout, output) in self.outputs.iter().enumerate() {
+            // Outputs are matched on their witness program, so a name or
+            // asset wrapper does not hide a payment; the wrapper is reported
+            // alongside
+            let (program, wrapper) =
+                match wrapped::parse_scriptpubkey(&output.script_pubkey, self.network) {
+                    Ok((program, wrapper)) => (Some(program), wrapper),
+                    Err(_) => (None, None),
+                };
+            let script = program.as_ref().and_then(|p| p.to_scriptpubkey().ok());
+            let pays = |p: &WitnessProgram| script.is_some() && p.to_scriptpubkey().ok() == script;
+            // Each expected payment is consumed by one output, preferring
+            // an entry whose amount matches
+            let matching: Vec<usize> = (0..expected.len())
+                .filter(|&i| pays(&expected[i].0))
+                .collect();
+            let payment = matching
+                .iter()
+                .find(|&&i| !paid.contains(&i) && expected[i].1 == output.value)
+                .or_else(|| matching.iter().find(|&&i| !paid.contains(&i)));
+            let change = change_programs.iter().find(|&(_, _, p)| pays(p));
+            let status = match (payment, change) {
+                (Some(&i), _) => {
+                    let (ref address, amount) = expected[i];
+                    paid.insert(i);
+                    if address.network() != self.network {
+                        OutputStatus::NetworkMismatch(address.network())
+                    } else if amount != output.value {
+                        OutputStatus::AmountMismatch(amount)
+                    } else {
+                        OutputStatus::Expected
+                    }
+                }
+                (None, _) if !matching.is_empty() => OutputStatus::Duplicate,
+                (None, Some(&(descriptor, index, ref address))) => {
+                    if address.network() != self.network {
+                        OutputStatus::NetworkMismatch(address.network())
+                    } else {
+                        OutputStatus::Change { descriptor, index }
+                    }
+                }
+                (None, None) => OutputStatus::Unknown,
+            };
+            outputs.push(OutputCheck {
+                vout: vout as u32,
+                value: output.value,
+                program,
+                wrapper,
+                status,
+            });
+        }
+        let missing = expected
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| !paid.contains(&i))
+            .map(|(_, (program, _))| program.clone())
+            .collect();
+        Ok(VerificationReport { outputs, missing })
+    }
+}
+
+/// Outcome of checking one output
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum OutputStatus {
+    /// Pays an expected address the expected amount
+    Expected,
+    /// Pays one of the change descriptors, identified by its position and
+    /// derivation index
+    Change {
+        /// Position of the descriptor in the list given
+        descriptor: usize,
+        /// Derivation index of the address
+        index: u32,
+    },
+    /// Pays an expected address a different amount, the expected one given
+    AmountMismatch(u64),
+    /// Matches an address or descriptor of another network
+    NetworkMismatch(Network),
+    /// Pays an expected address whose payment an earlier output already
+    /// made
+    Duplicate,
+    /// Pays a script that was not expected
+    Unknown,
+}
+
+/// Verification result for one output
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct OutputCheck {
+    vout: u32,
+    value: u64,
+    program: Option<WitnessProgram>,
+    wrapper: Option<Wrapper>,
+    status: OutputStatus,
+}
+
+impl OutputCheck {
+    /// Index of the output
+    pub fn vout(&self) -> u32 {
+        self.vout
+    }
+
+    /// Amount paid
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    /// Witness program paid, for display as an address
+    pub fn program(&self) -> Option<&WitnessProgram> {
+        self.program.as_ref()
+    }
+
+    /// Name operation or asset transfer carried by the output, if any
+    ///
+    /// The status only covers the witness program and the network's own
+    /// amount, which for a Ravencoin asset transfer is usually zero. A
+    /// wrapped output also moves the name or asset and needs reviewing on
+    /// its own.
+    pub fn wrapper(&self) -> Option<&Wrapper> {
+        self.wrapper.as_ref()
+    }
+
+    /// Outcome of the check
+    pub fn status(&self) -> OutputStatus {
+        self.status
+    }
+}
+
+/// Verification result for a whole PSBT
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct VerificationReport {
+    outputs: Vec<OutputCheck>,
+    missing: Vec<WitnessProgram>,
+}
+
+impl VerificationReport {
+    /// Checks of each output, in order
+    pub fn outputs(&self) -> &[OutputCheck] {
+        &self.outputs
+    }
+
+    /// Expected addresses that no output pays
+    pub fn missing(&self) -> &[WitnessProgram] {
+        &self.missing
+    }
+
+    /// Whether every output is expected or change, no output carries a
+    /// name or asset, and every expected address is paid
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty()
+            && self.outputs.iter().all(|check| {
+                check.wrapper.is_none()
+                    && matches!(
+                        check.status,
+                        OutputStatus::Expected | OutputStatus::Change { .. }
+                    )
+            })
+    }
+}
+
+/// Reads a key-value map up to its separator, rejecting duplicate keys
+fn read_map<'a>(reader: &mut Reader<'a>) -> Result<Map<'a>, Error> {
+    let mut map: Map<'a> = Vec::new();
+    loop {
+        let key = reader.read_var_bytes().ok_or(Error::UnexpectedEnd)?;
+        if key.is_empty() {
+            return Ok(map);
+        }
+        let value = reader.read_var_bytes().ok_or(Error::UnexpectedEnd)?;
+        if map.iter().any(|&(k, _)| k == key) {
+            return Err(Error::DuplicateKey);
+        }
+        map.push((key, value));
+    }
+}
+
+/// Value of the key made of `key_type` alone
+fn find<'a>(map: &Map<'a>, key_type: u8) -> Option<&'a [u8]> {
+    map.iter().find(|&&(k, _)| k == [key_type]).map(|&(_, v)| v)
+}
+
+fn read_exact_u32(value: &[u8]) -> Result<u32, Error> {
+    if value.len() != 4 {
+        return Err(Error::InvalidValue);
+    }
+    Ok(u32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+}
+
+fn read_exact_compact_size(value: &[u8]) -> Result<u64, Error> {
+    let mut reader = Reader::new(value);
+    match reader.read_compact_size() {
+        Some(n) if reader.remaining() == 0 => Ok(n),
+        _ => Err(Error::InvalidValue),
+    }
+}
+
+/// Error types for PSBT parsing and verification
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// The data does not start with the PSBT magic
+    InvalidMagic,
+    /// The input is not valid base64 or hex
+    InvalidEncoding,
+    /// The data ends in the middle of a map
+    UnexpectedEnd,
+    /// Data remains after the last output map
+    TrailingData,
+    /// A map repeats a key
+    DuplicateKey,
+    /// A field required by the PSBT version is absent
+    MissingField,
+    /// A field not allowed in the PSBT version is present
+    UnexpectedField,
+    /// A field has a malformed value
+    InvalidValue,
+    /// The PSBT version is not 0 or 2
+    UnsupportedVersion(u32),
+    /// The unsigned transaction could not be parsed
+    Transaction(transaction::Error),
+    /// The unsigned transaction has a scriptSig or witness
+    SignedTransaction,
+    /// A change descriptor could not be derived
+    Descriptor(descriptor::Error),
+}
+
+impl From<transaction::Error> for Error {
+    fn from(e: transaction::Error) -> Error {
+        Error::Transaction(e)
+    }
+}
+
+impl From<descriptor::Error> for Error {
+    fn from(e: descriptor::Error) -> Error {
+        Error::Descriptor(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidMagic => write!(f, "invalid PSBT magic"),
+            Error::InvalidEncoding => write!(f, "invalid PSBT encoding"),
+            Error::UnexpectedEnd => write!(f, "unexpected end of PSBT"),
+            Error::TrailingData => write!(f, "trailing data after PSBT"),
+            Error::DuplicateKey => write!(f, "duplicate key in PSBT map"),
+            Error::MissingField => write!(f, "missing required PSBT field"),
+            Error::UnexpectedField => write!(f, "field not allowed in this PSBT version"),
+            Error::InvalidValue => write!(f, "invalid PSBT field value"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported PSBT version: {}", version)
+            }
+            Error::Transaction(ref e) => write!(f, "{}", e),
+            Error::SignedTransaction => write!(f, "unsigned transaction has signatures"),
+            Error::Descriptor(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::InvalidMagic => "invalid PSBT magic",
+            Error::InvalidEncoding => "invalid PSBT encoding",
+            Error::UnexpectedEnd => "unexpected end of PSBT",
+            Error::TrailingData => "trailing data after PSBT",
+            Error::DuplicateKey => "duplicate key in PSBT map",
+            Error::MissingField => "missing required PSBT field",
+            Error::UnexpectedField => "field not allowed in this PSBT version",
+            Error::InvalidValue => "invalid PSBT field value",
+            Error::UnsupportedVersion(_) => "unsupported PSBT version",
+            Error::Transaction(_) => "transaction error",
+            Error::SignedTransaction => "unsigned transaction has signatures",
+            Error::Descriptor(_) => "descriptor error",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Transaction(ref e) => Some(e),
+            Error::Descriptor(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encode::write_var_bytes;
+    use script;
+
+    const PAYEE: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+    const CHANGE: &str = "wpkh(03a0434d9e47f3c86235477c7b1ae6ae5d3442d49b1943c2b752a68e2a47e247c7)";
+    const OTHER: &str = "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac";
+
+    fn pair(out: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+        write_var_bytes(out, key);
+        write_var_bytes(out, value);
+    }
+
+    fn scripts() -> Vec<Vec<u8>> {
+        let payee = WitnessProgram::from_address(PAYEE).unwrap();
+        let change = Descriptor::parse(CHANGE, Network::Bitcoin).unwrap();
+        vec![
+            payee.to_scriptpubkey().unwrap(),
+            change
+                .witness_program(0)
+                .unwrap()
+                .to_scriptpubkey()
+                .unwrap(),
+            hex::decode(OTHER).unwrap(),
+        ]
+    }
+
+    /// Pairs each amount with the matching script of `scripts`
+    fn outputs(values: &[u64]) -> Vec<(u64, Vec<u8>)> {
+        values.iter().cloned().zip(scripts()).collect()
+    }
+
+    fn unsigned_tx(outputs: &[(u64, Vec<u8>)], script_sig: &[u8]) -> Vec<u8> {
+        let mut tx = 2u32.to_le_bytes().to_vec();
+        tx.push(1);
+        tx.extend_from_slice(&[0x11; 32]);
+        tx.extend_from_slice(&[0, 0, 0, 0]);
+        write_var_bytes(&mut tx, script_sig);
+        tx.extend_from_slice(&[0xfd, 0xff, 0xff, 0xff]);
+        tx.push(outputs.len() as u8);
+        for &(value, ref script) in outputs {
+            tx.extend_from_slice(&value.to_le_bytes());
+            write_var_bytes(&mut tx, script);
+        }
+        tx.extend_from_slice(&[0, 0, 0, 0]);
+        tx
+    }
+
+    fn psbt_v0_with_tx(tx: &[u8], output_count: usize) -> Vec<u8> {
+        let mut psbt = PSBT_MAGIC.to_vec();
+        pair(&mut psbt, &[GLOBAL_UNSIGNED_TX], tx);
+        // Global separator, then an empty map for the input and each output
+        psbt.extend_from_slice(&vec![0; 2 + output_count]);
+        psbt
+    }
+
+    fn psbt_v0(outputs: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        psbt_v0_with_tx(&unsigned_tx(outputs, &[]), outputs.len())
+    }
+
+    fn psbt_v2(outputs: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut psbt = PSBT_MAGIC.to_vec();
+        pair(&mut psbt, &[GLOBAL_TX_VERSION], &2u32.to_le_bytes());
+        pair(&mut psbt, &[GLOBAL_INPUT_COUNT], &[1]);
+        pair(&mut psbt, &[GLOBAL_OUTPUT_COUNT], &[outputs.len() as u8]);
+        pair(&mut psbt, &[GLOBAL_VERSION], &2u32.to_le_bytes());
+        psbt.push(0);
+        pair(&mut psbt, &[0x0e], &[0x11; 32]);
+        pair(&mut psbt, &[0x0f], &[0, 0, 0, 0]);
+        psbt.push(0);
+        for &(value, ref script) in outputs {
+            pair(&mut psbt, &[OUTPUT_AMOUNT], &value.to_le_bytes());
+            pair(&mut psbt, &[OUTPUT_SCRIPT], script);
+            psbt.push(0);
+        }
+        psbt
+    }
+
+    fn verify(psbt: &Psbt, amount: u64) -> VerificationReport {
+        let payee = WitnessProgram::from_address(PAYEE).unwrap();
+        let change = Descriptor::parse(CHANGE, Network::Bitcoin).unwrap();
+        psbt.verify(&[(payee, amount)], &[change], 20).unwrap()
+    }
+
+    #[test]
+    fn verify_both_versions() {
+        for data in [
+            psbt_v0(&outputs(&[50_000, 10_000])),
+            psbt_v2(&outputs(&[50_000, 10_000])),
+        ]
+        .iter()
+        {
+            let psbt = Psbt::parse(data, Network::Bitcoin).unwrap();
+            assert_eq!(psbt.input_count(), 1);
+            assert_eq!(psbt.outputs().len(), 2);
+            assert_eq!(psbt.outputs()[1].value(), 10_000);
+
+            let report = verify(&psbt, 50_000);
+            assert!(report.is_ok());
+            assert_eq!(report.outputs()[0].status(), OutputStatus::Expected);
+            assert_eq!(report.outputs()[0].program().unwrap().to_address(), PAYEE);
+            assert_eq!(
+                report.outputs()[1].status(),
+                OutputStatus::Change {
+                    descriptor: 0,
+                    index: 0
+                }
+            );
+
+            let report = verify(&psbt, 40_000);
+            assert!(!report.is_ok());
+            assert_eq!(
+                report.outputs()[0].status(),
+                OutputStatus::AmountMismatch(40_000)
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_and_missing_outputs() {
+        let psbt = Psbt::parse(&psbt_v2(&outputs(&[0, 10_000, 5_000])), Network::Bitcoin).unwrap();
+        let report = psbt
+            .verify(
+                &[],
+                &[Descriptor::parse(CHANGE, Network::Bitcoin).unwrap()],
+                20,
+            )
+            .unwrap();
+        assert_eq!(report.outputs()[0].status(), OutputStatus::Unknown);
+        assert_eq!(report.outputs()[2].status(), OutputStatus::Unknown);
+        assert_eq!(report.outputs()[2].program(), None);
+        assert!(!report.is_ok());
+
+        let psbt = Psbt::parse(&psbt_v0(&outputs(&[10_000, 10_000])), Network::Bitcoin).unwrap();
+        let payee = WitnessProgram::from_address(PAYEE).unwrap();
+        let elsewhere =
+            WitnessProgram::new(payee.version(), payee.program().to_vec(), Network::Testnet)
+                .unwrap();
+        let report = psbt.verify(&[(elsewhere, 10_000)], &[], 20).unwrap();
+        assert_eq!(
+            report.outputs()[0].status(),
+            OutputStatus::NetworkMismatch(Network::Testnet)
+        );
+        assert_eq!(report.outputs()[1].status(), OutputStatus::Unknown);
+
+        let report = psbt
+            .verify(&[(payee.clone(), 1), (payee, 2)], &[], 20)
+            .unwrap();
+        assert_eq!(report.missing().len(), 1);
+    }
+
+    #[test]
+    fn duplicate_payments() {
+        let mut data = outputs(&[50_000, 10_000]);
+        let first = data[0].clone();
+        data.push(first);
+        let psbt = Psbt::parse(&psbt_v2(&data), Network::Bitcoin).unwrap();
+
+        let report = verify(&psbt, 50_000);
+        assert!(!report.is_ok());
+        assert_eq!(report.outputs()[0].status(), OutputStatus::Expected);
+        assert_eq!(report.outputs()[2].status(), OutputStatus::Duplicate);
+
+        let payee = WitnessProgram::from_address(PAYEE).unwrap();
+        let report = psbt
+            .verify(&[(payee.clone(), 1), (payee, 50_000)], &[], 20)
+            .unwrap();
+        assert_eq!(report.outputs()[0].status(), OutputStatus::Expected);
+        assert_eq!(
+            report.outputs()[2].status(),
+            OutputStatus::AmountMismatch(1)
+        );
+        assert!(report.missing().is_empty());
+    }
+
+    #[test]
+    fn wrapped_outputs() {
+        let payee = WitnessProgram::from_address(PAYEE).unwrap();
+        let mut name = vec![wrapped::OP_NAME_UPDATE];
+        script::push_slice(&mut name, b"d/example");
+        script::push_slice(&mut name, b"{}");
+        name.extend_from_slice(&[script::OP_2DROP, script::OP_DROP]);
+        name.extend_from_slice(&payee.to_scriptpubkey().unwrap());
+        let data = psbt_v0(&[(50_000, name)]);
+        let psbt = Psbt::parse(&data, Network::Namecoin).unwrap();
+
+        let program = psbt.outputs()[0]
+            .witness_program(Network::Namecoin)
+            .unwrap();
+        assert_eq!(
+            program.to_address(),
+            "nc1qw508d6qejxtdg4y5r3zarvary0c5xw7kttkktk"
+        );
+        let report = psbt.verify(&[(program, 50_000)], &[], 20).unwrap();
+        assert_eq!(report.outputs()[0].status(), OutputStatus::Expected);
+        assert_eq!(
+            report.outputs()[0].wrapper(),
+            Some(&Wrapper::Name(wrapped::NameOperation::Update {
+                name: b"d/example".to_vec(),
+                value: b"{}".to_vec(),
+            }))
+        );
+        // The name leaves the wallet, so the PSBT needs a closer look
+        assert!(!report.is_ok());
+
+        // An asset transfer is carried by an output of no RVN
+        let mut payload = b"rvnt".to_vec();
+        write_var_bytes(&mut payload, b"ASSET");
+        payload.extend_from_slice(&100_000_000u64.to_le_bytes());
+        let mut asset = payee.to_scriptpubkey().unwrap();
+        asset.push(wrapped::OP_RVN_ASSET);
+        script::push_slice(&mut asset, &payload);
+        asset.push(script::OP_DROP);
+        let psbt = Psbt::parse(&psbt_v2(&[(0, asset)]), Network::Ravencoin).unwrap();
+        let program = psbt.outputs()[0]
+            .witness_program(Network::Ravencoin)
+            .unwrap();
+        let report = psbt.verify(&[(program, 0)], &[], 20).unwrap();
+        assert_eq!(report.outputs()[0].status(), OutputStatus::Expected);
+        assert_eq!(
+            report.outputs()[0].wrapper(),
+            Some(&Wrapper::Asset(wrapped::AssetOperation::Transfer {
+                name: "ASSET".to_string(),
+                amount: 100_000_000,
+            }))
+        );
+        assert!(!report.is_ok());
+
+        let plain = Psbt::parse(&psbt_v0(&outputs(&[50_000])), Network::Namecoin).unwrap();
+        let report = plain.verify(&[(payee, 50_000)], &[], 20).unwrap();
+        assert_eq!(report.outputs()[0].wrapper(), None);
+    }
+
+    #[test]
+    fn invalid_psbts() {
+        let data = psbt_v2(&outputs(&[1]));
+        assert_eq!(
+            Psbt::parse(&data[1..], Network::Bitcoin),
+            Err(Error::InvalidMagic)
+        );
+        assert_eq!(
+            Psbt::parse(&data[..data.len() - 1], Network::Bitcoin),
+            Err(Error::UnexpectedEnd)
+        );
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert_eq!(
+            Psbt::parse(&trailing, Network::Bitcoin),
+            Err(Error::TrailingData)
+        );
+
+        let mut duplicate = PSBT_MAGIC.to_vec();
+        pair(&mut duplicate, &[GLOBAL_VERSION], &0u32.to_le_bytes());
+        pair(&mut duplicate, &[GLOBAL_VERSION], &0u32.to_le_bytes());
+        duplicate.push(0);
+        assert_eq!(
+            Psbt::parse(&duplicate, Network::Bitcoin),
+            Err(Error::DuplicateKey)
+        );
+
+        let mut missing = PSBT_MAGIC.to_vec();
+        missing.push(0);
+        assert_eq!(
+            Psbt::parse(&missing, Network::Bitcoin),
+            Err(Error::MissingField)
+        );
+
+        let mut v1 = PSBT_MAGIC.to_vec();
+        pair(&mut v1, &[GLOBAL_VERSION], &1u32.to_le_bytes());
+        v1.push(0);
+        assert_eq!(
+            Psbt::parse(&v1, Network::Bitcoin),
+            Err(Error::UnsupportedVersion(1))
+        );
+
+        // The unsigned transaction must carry no scriptSig or witness
+        let paid = outputs(&[50_000]);
+        let signed = unsigned_tx(&paid, &[0x51]);
+        assert_eq!(
+            Psbt::parse(&psbt_v0_with_tx(&signed, 1), Network::Bitcoin),
+            Err(Error::SignedTransaction)
+        );
+        let tx = unsigned_tx(&paid, &[]);
+        let mut witness = tx[..4].to_vec();
+        witness.extend_from_slice(&[0x00, 0x01]);
+        witness.extend_from_slice(&tx[4..tx.len() - 4]);
+        witness.extend_from_slice(&[1, 1, 0x51]);
+        witness.extend_from_slice(&tx[tx.len() - 4..]);
+        assert!(Transaction::parse(&witness, Network::Bitcoin)
+            .unwrap()
+            .has_witness());
+        assert_eq!(
+            Psbt::parse(&psbt_v0_with_tx(&witness, 1), Network::Bitcoin),
+            Err(Error::SignedTransaction)
+        );
+
+        assert_eq!(
+            Psbt::from_base64("cHNidP8", Network::Bitcoin),
+            Err(Error::InvalidEncoding)
+        );
+        assert_eq!(
+            Psbt::from_base64("cHNidP8A", Network::Bitcoin),
+            Err(Error::MissingField)
+        );
+    }
+}
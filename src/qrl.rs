@@ -0,0 +1,464 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Quantum Resistant Ledger addresses
+//!
+//! A QRL address is the 3-byte XMSS descriptor of the key followed by the
+//! SHA256 of the extended public key (the descriptor and the 64-byte XMSS
+//! public key). The descriptor packs the signature scheme and hash function
+//! into the first byte, the address format and half the tree height into the
+//! second, and reserves the third for parameters, which must be zero.
+//!
+//! The QRL wallet prints the same payload in hex behind a `Q`, followed by
+//! the last four bytes of its SHA256 as a verification code.
+
+use std::str::FromStr;
+use std::{error, fmt};
+
+use bech32::{self, FromBase32, ToBase32, Variant};
+
+use constants::{self, Network};
+use {hash, hex};
+
+/// Length of an XMSS descriptor
+pub const DESCRIPTOR_LEN: usize = 3;
+/// Length of an XMSS public key without its descriptor
+pub const PUBLIC_KEY_LEN: usize = 64;
+/// Length of an extended public key: the descriptor followed by the key
+pub const EXTENDED_PUBLIC_KEY_LEN: usize = DESCRIPTOR_LEN + PUBLIC_KEY_LEN;
+/// Length of the address payload: the descriptor followed by the key hash
+pub const ADDRESS_LEN: usize = DESCRIPTOR_LEN + 32;
+/// Length of the verification code of a `Q` address
+pub const VERIFICATION_LEN: usize = 4;
+
+/// Hash function used inside the XMSS tree
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum XmssHash {
+    /// SHA2-256
+    Sha2_256,
+    /// SHAKE128
+    Shake128,
+    /// SHAKE256
+    Shake256,
+}
+
+impl XmssHash {
+    fn from_u8(value: u8) -> Option<XmssHash> {
+        match value {
+            0 => Some(XmssHash::Sha2_256),
+            1 => Some(XmssHash::Shake128),
+            2 => Some(XmssHash::Shake256),
+            _ => None,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            XmssHash::Sha2_256 => 0,
+            XmssHash::Shake128 => 1,
+            XmssHash::Shake256 => 2,
+        }
+    }
+}
+
+/// Describes the XMSS key behind an address
+///
+/// QRL only defines the XMSS signature scheme and the `SHA256_2X` address
+/// format, so the hash function and tree height are the only free fields.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub struct Descriptor {
+    hash_function: XmssHash,
+    height: u8,
+}
+
+impl Descriptor {
+    /// Creates a descriptor for a tree of `height`, which must be even and
+    /// between 2 and 30
+    pub fn new(hash_function: XmssHash, height: u8) -> Result<Descriptor, Error> {
+        if !(2..=30).contains(&height) || height & 1 != 0 {
+            return Err(Error::InvalidHeight(height));
+        }
+        Ok(Descriptor {
+            hash_function,
+            height,
+        })
+    }
+
+    /// Parses and validates the descriptor bytes
+    pub fn from_bytes(bytes: [u8; DESCRIPTOR_LEN]) -> Result<Descriptor, Error> {
+        let signature_type = bytes[0] >> 4;
+        if signature_type != 0 {
+            return Err(Error::InvalidSignatureType(signature_type));
+        }
+        let hash_function = XmssHash::from_u8(bytes[0] & 0x0f)
+            .ok_or(Error::InvalidHashFunction(bytes[0] & 0x0f))?;
+        let address_format = bytes[1] >> 4;
+        if address_format != 0 {
+            return Err(Error::InvalidAddressFormat(address_format));
+        }
+        if bytes[2] != 0 {
+            return Err(Error::InvalidParameters(bytes[2]));
+        }
+        Descriptor::new(hash_function, (bytes[1] & 0x0f) << 1)
+    }
+
+    /// Serializes the descriptor
+    pub fn to_bytes(self) -> [u8; DESCRIPTOR_LEN] {
+        [self.hash_function.to_u8(), self.height >> 1, 0]
+    }
+
+    /// Hash function of the XMSS tree
+    pub fn hash_function(&self) -> XmssHash {
+        self.hash_function
+    }
+
+    /// Height of the XMSS tree
+    pub fn height(&self) -> u8 {
+        self.height
+    }
+
+    /// Number of signatures the key can make
+    pub fn signatures(&self) -> u64 {
+        1 << self.height
+    }
+}
+
+/// A Quantum Resistant Ledger address
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct QrlAddress {
+    network: Network,
+    descriptor: Descriptor,
+    hash: [u8; 32],
+    bech32: String,
+}
+
+impl QrlAddress {
+    /// Creates an address from a descriptor and public key hash
+    pub fn new(
+        descriptor: Descriptor,
+        hash: [u8; 32],
+        network: Network,
+    ) -> Result<QrlAddress, Error> {
+        match network {
+            Network::QuantumResistantLedger | Network::QuantumResistantLedgerTestnet => (),
+            network => return Err(Error::NotQrl(network)),
+        }
+        let mut payload = descriptor.to_bytes().to_vec();
+        payload.extend_from_slice(&hash);
+        let bech32 = bech32::encode(
+            &constants::hrp(&network),
+            payload.to_base32(),
+            Variant::Bech32,
+        )
+        .map_err(::Error::Bech32)?;
+        Ok(QrlAddress {
+            network,
+            descriptor,
+            hash,
+            bech32,
+        })
+    }
+
+    /// Derives the address of an extended public key
+    pub fn from_extended_public_key(epk: &[u8], network: Network) -> Result<QrlAddress, Error> {
+        if epk.len() != EXTENDED_PUBLIC_KEY_LEN {
+            return Err(Error::InvalidLength);
+        }
+        let descriptor = Descriptor::from_bytes([epk[0], epk[1], epk[2]])?;
+        QrlAddress::new(descriptor, hash::sha256(epk), network)
+    }
+
+    /// Decodes an address string
+    pub fn from_address(address: &str) -> Result<QrlAddress, Error> {
+        QrlAddress::from_str(address)
+    }
+
+    /// Encodes the address as a string
+    pub fn to_address(&self) -> String {
+        self.to_string()
+    }
+
+    /// Converts a hex `Q` address printed by the QRL wallet
+    pub fn from_q_address(address: &str, network: Network) -> Result<QrlAddress, Error> {
+        if !address.starts_with('Q') {
+            return Err(Error::InvalidHex);
+        }
+        let bytes = hex::decode(&address[1..]).ok_or(Error::InvalidHex)?;
+        if bytes.len() != ADDRESS_LEN + VERIFICATION_LEN {
+            return Err(Error::InvalidLength);
+        }
+        let (payload, verification) = bytes.split_at(ADDRESS_LEN);
+        if hash::sha256(payload)[32 - VERIFICATION_LEN..] != *verification {
+            return Err(Error::InvalidVerification);
+        }
+        let descriptor = Descriptor::from_bytes([payload[0], payload[1], payload[2]])?;
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&payload[DESCRIPTOR_LEN..]);
+        QrlAddress::new(descriptor, hash, network)
+    }
+
+    /// Encodes the address in the hex `Q` form
+    pub fn to_q_address(&self) -> String {
+        let mut bytes = self.descriptor.to_bytes().to_vec();
+        bytes.extend_from_slice(&self.hash);
+        let verification = hash::sha256(&bytes);
+        bytes.extend_from_slice(&verification[32 - VERIFICATION_LEN..]);
+        format!("Q{}", hex::encode(&bytes))
+    }
+
+    /// Network of the address
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Descriptor of the key behind the address
+    pub fn descriptor(&self) -> Descriptor {
+        self.descriptor
+    }
+
+    /// SHA256 of the extended public key
+    pub fn hash(&self) -> &[u8; 32] {
+        &self.hash
+    }
+}
+
+impl fmt::Display for QrlAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.bech32)
+    }
+}
+
+impl FromStr for QrlAddress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<QrlAddress, Error> {
+        let (hrp, data, variant) = bech32::decode(s).map_err(::Error::Bech32)?;
+        if variant != Variant::Bech32 {
+            return Err(Error::Address(::Error::InvalidChecksumVariant));
+        }
+        let network = match constants::classify(&hrp) {
+            Some(network @ Network::QuantumResistantLedger)
+            | Some(network @ Network::QuantumResistantLedgerTestnet) => network,
+            Some(network) => return Err(Error::NotQrl(network)),
+            None => return Err(Error::Address(::Error::InvalidHumanReadablePart)),
+        };
+        let payload = Vec::from_base32(&data).map_err(::Error::Bech32)?;
+        if payload.len() != ADDRESS_LEN {
+            return Err(Error::InvalidLength);
+        }
+        let descriptor = Descriptor::from_bytes([payload[0], payload[1], payload[2]])?;
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&payload[DESCRIPTOR_LEN..]);
+        Ok(QrlAddress {
+            network,
+            descriptor,
+            hash,
+            bech32: s.to_lowercase(),
+        })
+    }
+}
+
+/// Error types for QRL addresses
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// The Bech32 encoding is invalid
+    Address(::Error),
+    /// The network is not QRL
+    NotQrl(Network),
+    /// The payload or extended public key has the wrong length
+    InvalidLength,
+    /// The signature scheme is not XMSS
+    InvalidSignatureType(u8),
+    /// Unknown XMSS hash function
+    InvalidHashFunction(u8),
+    /// The address format is not `SHA256_2X`
+    InvalidAddressFormat(u8),
+    /// The tree height is odd or out of range
+    InvalidHeight(u8),
+    /// The reserved parameter byte is not zero
+    InvalidParameters(u8),
+    /// A `Q` address is not `Q` followed by hex
+    InvalidHex,
+    /// The verification code of a `Q` address does not match
+    InvalidVerification,
+}
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::Address(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Address(ref e) => write!(f, "{}", e),
+            Error::NotQrl(network) => write!(f, "{:?} is not QRL", network),
+            Error::InvalidLength => write!(f, "invalid length"),
+            Error::InvalidSignatureType(t) => write!(f, "invalid signature type: {}", t),
+            Error::InvalidHashFunction(h) => write!(f, "invalid hash function: {}", h),
+            Error::InvalidAddressFormat(a) => write!(f, "invalid address format: {}", a),
+            Error::InvalidHeight(h) => write!(f, "invalid tree height: {}", h),
+            Error::InvalidParameters(p) => write!(f, "invalid descriptor parameters: {}", p),
+            Error::InvalidHex => write!(f, "invalid hex"),
+            Error::InvalidVerification => write!(f, "invalid verification code"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Address(_) => "address error",
+            Error::NotQrl(_) => "network is not QRL",
+            Error::InvalidLength => "invalid length",
+            Error::InvalidSignatureType(_) => "invalid signature type",
+            Error::InvalidHashFunction(_) => "invalid hash function",
+            Error::InvalidAddressFormat(_) => "invalid address format",
+            Error::InvalidHeight(_) => "invalid tree height",
+            Error::InvalidParameters(_) => "invalid descriptor parameters",
+            Error::InvalidHex => "invalid hex",
+            Error::InvalidVerification => "invalid verification code",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Address(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use WitnessProgram;
+
+    fn extended_public_key(descriptor: [u8; DESCRIPTOR_LEN]) -> Vec<u8> {
+        let mut epk = descriptor.to_vec();
+        epk.extend_from_slice(&[0x42; PUBLIC_KEY_LEN]);
+        epk
+    }
+
+    #[test]
+    fn descriptor_fields() {
+        let descriptor = Descriptor::from_bytes([0x01, 0x05, 0x00]).unwrap();
+        assert_eq!(descriptor.hash_function(), XmssHash::Shake128);
+        assert_eq!(descriptor.height(), 10);
+        assert_eq!(descriptor.signatures(), 1024);
+        assert_eq!(descriptor.to_bytes(), [0x01, 0x05, 0x00]);
+
+        let invalid: Vec<([u8; DESCRIPTOR_LEN], Error)> = vec![
+            ([0x10, 0x05, 0x00], Error::InvalidSignatureType(1)),
+            ([0x03, 0x05, 0x00], Error::InvalidHashFunction(3)),
+            ([0x00, 0x15, 0x00], Error::InvalidAddressFormat(1)),
+            ([0x00, 0x00, 0x00], Error::InvalidHeight(0)),
+            ([0x00, 0x05, 0x01], Error::InvalidParameters(1)),
+        ];
+        for (bytes, error) in invalid {
+            assert_eq!(Descriptor::from_bytes(bytes), Err(error));
+        }
+        assert_eq!(
+            Descriptor::new(XmssHash::Sha2_256, 11),
+            Err(Error::InvalidHeight(11))
+        );
+    }
+
+    #[test]
+    fn derive_and_round_trip() {
+        let epk = extended_public_key([0x00, 0x05, 0x00]);
+        let address =
+            QrlAddress::from_extended_public_key(&epk, Network::QuantumResistantLedger).unwrap();
+        assert_eq!(address.hash(), &hash::sha256(&epk));
+        assert_eq!(address.descriptor().height(), 10);
+        assert!(address.to_address().starts_with("qrl1"));
+
+        let parsed = QrlAddress::from_address(&address.to_address()).unwrap();
+        assert_eq!(parsed, address);
+        assert_eq!(
+            WitnessProgram::from_address(&address.to_address()),
+            Err(::Error::InvalidPayloadFamily)
+        );
+
+        let testnet =
+            QrlAddress::from_extended_public_key(&epk, Network::QuantumResistantLedgerTestnet)
+                .unwrap();
+        assert!(testnet.to_address().starts_with("tqrl1"));
+    }
+
+    #[test]
+    fn q_addresses() {
+        let q = "Q010500bc576efa69fd6cbc854f2224f149f0b0a4d18fcb30c1feab64781245f4f27a61874227f3";
+        let address = QrlAddress::from_q_address(q, Network::QuantumResistantLedger).unwrap();
+        assert_eq!(address.descriptor().hash_function(), XmssHash::Shake128);
+        assert_eq!(address.descriptor().height(), 10);
+        assert_eq!(address.hash()[..4], [0xbc, 0x57, 0x6e, 0xfa]);
+        assert_eq!(address.to_q_address(), q);
+        let parsed = QrlAddress::from_address(&address.to_address()).unwrap();
+        assert_eq!(parsed.to_q_address(), q);
+
+        let q = "Q010300a1da274e68c88b0ccf448e0b1916fa789b01eb2ed4e9ad565ce264c9390782a9c61ac02f";
+        let address = QrlAddress::from_q_address(q, Network::QuantumResistantLedger).unwrap();
+        assert_eq!(address.descriptor().height(), 6);
+        assert_eq!(address.to_q_address(), q);
+
+        let network = Network::QuantumResistantLedger;
+        assert_eq!(
+            QrlAddress::from_q_address(&q.replace("c02f", "c030"), network),
+            Err(Error::InvalidVerification)
+        );
+        assert_eq!(
+            QrlAddress::from_q_address(&q[..q.len() - 2], network),
+            Err(Error::InvalidLength)
+        );
+        assert_eq!(
+            QrlAddress::from_q_address(&q[1..], network),
+            Err(Error::InvalidHex)
+        );
+    }
+
+    #[test]
+    fn invalid_addresses() {
+        let epk = extended_public_key([0x00, 0x05, 0x00]);
+        assert_eq!(
+            QrlAddress::from_extended_public_key(&epk, Network::Bitcoin),
+            Err(Error::NotQrl(Network::Bitcoin))
+        );
+        assert_eq!(
+            QrlAddress::from_extended_public_key(&epk[1..], Network::QuantumResistantLedger),
+            Err(Error::InvalidLength)
+        );
+        assert_eq!(
+            QrlAddress::from_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"),
+            Err(Error::NotQrl(Network::Bitcoin))
+        );
+
+        let mut payload = vec![0x00, 0x05, 0x07];
+        payload.extend_from_slice(&[0; 32]);
+        let address = bech32::encode("qrl", payload.to_base32(), Variant::Bech32).unwrap();
+        assert_eq!(
+            QrlAddress::from_address(&address),
+            Err(Error::InvalidParameters(7))
+        );
+        let short = bech32::encode("qrl", [0u8; 20].to_base32(), Variant::Bech32).unwrap();
+        assert_eq!(QrlAddress::from_address(&short), Err(Error::InvalidLength));
+    }
+}
@@ -0,0 +1,641 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Witness scripts
+//!
+//! Opcodes, minimal push encoding and a few common witness script templates:
+//! multisig, timelocked keys and vaults. Templates encode to witness
+//! scripts and P2WSH programs, and existing scripts can be matched back to
+//! their template parameters.
+
+use std::convert::TryFrom;
+use std::{error, fmt};
+
+use check_compressed_pubkey;
+use constants::Network;
+use WitnessProgram;
+
+/// Pushes an empty vector, or the number 0
+pub const OP_0: u8 = 0x00;
+/// Pushes the next byte as data length
+pub const OP_PUSHDATA1: u8 = 0x4c;
+/// Pushes the next two bytes as data length
+pub const OP_PUSHDATA2: u8 = 0x4d;
+/// Pushes the next four bytes as data length
+pub const OP_PUSHDATA4: u8 = 0x4e;
+/// Pushes the number -1
+pub const OP_1NEGATE: u8 = 0x4f;
+/// Pushes the number 1
+pub const OP_1: u8 = 0x51;
+/// Starts a branch taken if the top stack item is true
+pub const OP_IF: u8 = 0x63;
+/// Starts a branch taken if the top stack item is false
+pub const OP_NOTIF: u8 = 0x64;
+/// Switches to the other branch
+pub const OP_ELSE: u8 = 0x67;
+/// Ends a branch
+pub const OP_ENDIF: u8 = 0x68;
+/// Fails unless the top stack item is true
+pub const OP_VERIFY: u8 = 0x69;
+/// Moves the top stack item to the alt stack
+pub const OP_TOALTSTACK: u8 = 0x6b;
+/// Moves the top alt stack item back
+pub const OP_FROMALTSTACK: u8 = 0x6c;
+/// Removes the top two stack items
+pub const OP_2DROP: u8 = 0x6d;
+/// Removes the top stack item
+pub const OP_DROP: u8 = 0x75;
+/// Duplicates the top stack item if it is true
+pub const OP_IFDUP: u8 = 0x73;
+/// Duplicates the top stack item
+pub const OP_DUP: u8 = 0x76;
+/// Swaps the top two stack items
+pub const OP_SWAP: u8 = 0x7c;
+/// Pushes the size of the top stack item
+pub const OP_SIZE: u8 = 0x82;
+/// Compares the top two stack items
+pub const OP_EQUAL: u8 = 0x87;
+/// `OP_EQUAL` followed by `OP_VERIFY`
+pub const OP_EQUALVERIFY: u8 = 0x88;
+/// Replaces the top stack item with 1 unless it is 0
+pub const OP_0NOTEQUAL: u8 = 0x92;
+/// Adds the top two stack items
+pub const OP_ADD: u8 = 0x93;
+/// Boolean AND of the top two stack items
+pub const OP_BOOLAND: u8 = 0x9a;
+/// Boolean OR of the top two stack items
+pub const OP_BOOLOR: u8 = 0x9b;
+/// Compares the top two stack items as numbers
+pub const OP_NUMEQUAL: u8 = 0x9c;
+/// `OP_NUMEQUAL` followed by `OP_VERIFY`
+pub const OP_NUMEQUALVERIFY: u8 = 0x9d;
+/// RIPEMD160 of the top stack item
+pub const OP_RIPEMD160: u8 = 0xa6;
+/// SHA256 of the top stack item
+pub const OP_SHA256: u8 = 0xa8;
+/// RIPEMD160 of the SHA256 of the top stack item
+pub const OP_HASH160: u8 = 0xa9;
+/// Double SHA256 of the top stack item
+pub const OP_HASH256: u8 = 0xaa;
+/// Checks a signature against a public key
+pub const OP_CHECKSIG: u8 = 0xac;
+/// `OP_CHECKSIG` followed by `OP_VERIFY`
+pub const OP_CHECKSIGVERIFY: u8 = 0xad;
+/// Checks `k` of `n` signatures against public keys
+pub const OP_CHECKMULTISIG: u8 = 0xae;
+/// `OP_CHECKMULTISIG` followed by `OP_VERIFY`
+pub const OP_CHECKMULTISIGVERIFY: u8 = 0xaf;
+/// Fails unless the transaction lock time reaches the top stack item
+pub const OP_CHECKLOCKTIMEVERIFY: u8 = 0xb1;
+/// Fails unless the input sequence reaches the top stack item
+pub const OP_CHECKSEQUENCEVERIFY: u8 = 0xb2;
+/// Adds one to a counter if a signature is valid, in tapscript
+pub const OP_CHECKSIGADD: u8 = 0xba;
+
+/// Appends the minimal push of a number
+pub fn push_int(script: &mut Vec<u8>, n: i64) {
+    if n == 0 {
+        script.push(OP_0);
+    } else if n == -1 {
+        script.push(OP_1NEGATE);
+    } else if (1..=16).contains(&n) {
+        script.push(OP_1 + (n as u8) - 1);
+    } else {
+        push_slice(script, &serialize_num(n));
+    }
+}
+
+/// Appends the minimal push of a byte string
+pub fn push_slice(script: &mut Vec<u8>, data: &[u8]) {
+    let len = data.len();
+    if len < OP_PUSHDATA1 as usize {
+        script.push(len as u8);
+    } else if len <= 0xff {
+        script.push(OP_PUSHDATA1);
+        script.push(len as u8);
+    } else if len <= 0xffff {
+        script.push(OP_PUSHDATA2);
+        script.extend_from_slice(&(len as u16).to_le_bytes());
+    } else {
+        script.push(OP_PUSHDATA4);
+        script.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+    script.extend_from_slice(data);
+}
+
+/// Serializes a number in the little-endian sign-magnitude script format
+pub fn serialize_num(n: i64) -> Vec<u8> {
+    let mut out = Vec::new();
+    if n == 0 {
+        return out;
+    }
+    let negative = n < 0;
+    let mut abs = n.unsigned_abs();
+    while abs > 0 {
+        out.push((abs & 0xff) as u8);
+        abs >>= 8;
+    }
+    if out[out.len() - 1] & 0x80 != 0 {
+        out.push(if negative { 0x80 } else { 0x00 });
+    } else if negative {
+        let last = out.len() - 1;
+        out[last] |= 0x80;
+    }
+    out
+}
+
+/// Builds a bare `k`-of-`n` `OP_CHECKMULTISIG` script
+pub fn multisig(k: usize, keys: &[Vec<u8>]) -> Vec<u8> {
+    let mut script = Vec::new();
+    push_int(&mut script, k as i64);
+    for key in keys {
+        push_slice(&mut script, key);
+    }
+    push_int(&mut script, keys.len() as i64);
+    script.push(OP_CHECKMULTISIG);
+    script
+}
+
+/// Maximum number of keys in a bare `OP_CHECKMULTISIG` template
+pub const MAX_MULTISIG_KEYS: usize = 20;
+/// Largest relative block delay that BIP-0068 can express
+pub const MAX_RELATIVE_BLOCKS: u32 = 0xffff;
+
+/// A single script operation
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Instruction<'a> {
+    /// Pushes data, including the empty push of `OP_0`
+    Push(&'a [u8]),
+    /// Any other opcode
+    Op(u8),
+}
+
+impl<'a> Instruction<'a> {
+    /// The number pushed by this instruction, if it pushes one
+    ///
+    /// Accepts `OP_1NEGATE`, `OP_1` through `OP_16` and data pushes of up
+    /// to five bytes, enough for lock times.
+    pub fn number(&self) -> Option<i64> {
+        match *self {
+            Instruction::Op(OP_1NEGATE) => Some(-1),
+            Instruction::Op(op) if (OP_1..OP_1 + 16).contains(&op) => {
+                Some(i64::from(op - OP_1 + 1))
+            }
+            Instruction::Push(data) if data.len() <= 5 => Some(deserialize_num(data)),
+            Instruction::Op(_) | Instruction::Push(_) => None,
+        }
+    }
+}
+
+/// Splits a script into instructions
+pub fn instructions<'a>(script: &'a [u8]) -> Result<Vec<Instruction<'a>>, Error> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < script.len() {
+        let op = script[i];
+        i += 1;
+        let (len, width) = match op {
+            OP_0 => (0, 0),
+            0x01..=0x4b => (op as usize, 0),
+            OP_PUSHDATA1 => (read_le(script, i, 1)?, 1),
+            OP_PUSHDATA2 => (read_le(script, i, 2)?, 2),
+            OP_PUSHDATA4 => (read_le(script, i, 4)?, 4),
+            _ => {
+                out.push(Instruction::Op(op));
+                continue;
+            }
+        };
+        i += width;
+        if script.len() - i < len {
+            return Err(Error::TruncatedPush);
+        }
+        out.push(Instruction::Push(&script[i..i + len]));
+        i += len;
+    }
+    Ok(out)
+}
+
+fn read_le(script: &[u8], at: usize, width: usize) -> Result<usize, Error> {
+    if script.len() < at + width {
+        return Err(Error::TruncatedPush);
+    }
+    Ok(script[at..at + width]
+        .iter()
+        .rev()
+        .fold(0, |acc, &b| (acc << 8) | b as usize))
+}
+
+/// Parses a little-endian sign-magnitude script number
+pub fn deserialize_num(data: &[u8]) -> i64 {
+    let mut n = 0i64;
+    for (i, &b) in data.iter().enumerate() {
+        n |= i64::from(b) << (8 * i);
+    }
+    match data.last() {
+        Some(&last) if last & 0x80 != 0 => -(n & !(0x80i64 << (8 * (data.len() - 1)))),
+        _ => n,
+    }
+}
+
+/// A lock time checked by a timelocked key
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum Timelock {
+    /// `OP_CHECKLOCKTIMEVERIFY`: a block height or timestamp
+    Absolute(u32),
+    /// `OP_CHECKSEQUENCEVERIFY`: a BIP-0068 relative lock time
+    Relative(u32),
+}
+
+impl Timelock {
+    pub(crate) fn opcode(self) -> u8 {
+        match self {
+            Timelock::Absolute(_) => OP_CHECKLOCKTIMEVERIFY,
+            Timelock::Relative(_) => OP_CHECKSEQUENCEVERIFY,
+        }
+    }
+
+    pub(crate) fn validate(self) -> Result<u32, Error> {
+        match self {
+            Timelock::Absolute(n) if n > 0 => Ok(n),
+            Timelock::Relative(n) if n > 0 && n < 0x8000_0000 => Ok(n),
+            _ => Err(Error::InvalidTimelock),
+        }
+    }
+}
+
+/// A witness script template and its parameters
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub enum Template {
+    /// `<k> <key>... <n> OP_CHECKMULTISIG`
+    Multisig {
+        /// Number of signatures required
+        threshold: usize,
+        /// Compressed public keys
+        keys: Vec<Vec<u8>>,
+        /// Sort the keys as BIP-0067 does before encoding
+        sorted: bool,
+    },
+    /// `<n> OP_CHECKLOCKTIMEVERIFY|OP_CHECKSEQUENCEVERIFY OP_DROP <key> OP_CHECKSIG`
+    TimelockedKey {
+        /// Compressed public key
+        key: Vec<u8>,
+        /// Lock time the spend waits for
+        timelock: Timelock,
+    },
+    /// The hot key at any time, or the cold key after `delay` blocks:
+    /// `OP_IF <hot> OP_ELSE <delay> OP_CHECKSEQUENCEVERIFY OP_DROP <cold>
+    /// OP_ENDIF OP_CHECKSIG`
+    Vault {
+        /// Compressed public key that can always spend
+        hot_key: Vec<u8>,
+        /// Compressed public key that can spend after the delay
+        cold_key: Vec<u8>,
+        /// Relative delay in blocks
+        delay: u32,
+    },
+}
+
+impl Template {
+    /// Encodes the template as a witness script
+    pub fn to_script(&self) -> Result<Vec<u8>, Error> {
+        let mut script = Vec::new();
+        match *self {
+            Template::Multisig {
+                threshold,
+                ref keys,
+                sorted,
+            } => {
+                if threshold == 0 || threshold > keys.len() || keys.len() > MAX_MULTISIG_KEYS {
+                    return Err(Error::InvalidThreshold);
+                }
+                for key in keys {
+                    check_compressed_pubkey(key)?;
+                }
+                let mut keys = keys.clone();
+                if sorted {
+                    keys.sort();
+                }
+                script = multisig(threshold, &keys);
+            }
+            Template::TimelockedKey { ref key, timelock } => {
+                check_compressed_pubkey(key)?;
+                push_int(&mut script, i64::from(timelock.validate()?));
+                script.extend_from_slice(&[timelock.opcode(), OP_DROP]);
+                push_slice(&mut script, key);
+                script.push(OP_CHECKSIG);
+            }
+            Template::Vault {
+                ref hot_key,
+                ref cold_key,
+                delay,
+            } => {
+                check_compressed_pubkey(hot_key)?;
+                check_compressed_pubkey(cold_key)?;
+                if delay == 0 || delay > MAX_RELATIVE_BLOCKS {
+                    return Err(Error::InvalidTimelock);
+                }
+                script.push(OP_IF);
+                push_slice(&mut script, hot_key);
+                script.push(OP_ELSE);
+                push_int(&mut script, i64::from(delay));
+                script.extend_from_slice(&[OP_CHECKSEQUENCEVERIFY, OP_DROP]);
+                push_slice(&mut script, cold_key);
+                script.extend_from_slice(&[OP_ENDIF, OP_CHECKSIG]);
+            }
+        }
+        Ok(script)
+    }
+
+    /// Matches a witness script against the templates
+    ///
+    /// The script must be exactly what `to_script` produces for the
+    /// recovered parameters, so scripts with non-minimal pushes are
+    /// rejected. `sorted` is reported as true when the multisig keys are in
+    /// BIP-0067 order.
+    pub fn from_script(script: &[u8]) -> Result<Template, Error> {
+        let ins = instructions(script)?;
+        let template = match_template(&ins).ok_or(Error::UnknownTemplate)?;
+        if template.to_script()? != script {
+            return Err(Error::NonMinimalPush);
+        }
+        Ok(template)
+    }
+
+    /// P2WSH program paying to the template on a network
+    pub fn witness_program(&self, network: Network) -> Result<WitnessProgram, Error> {
+        Ok(WitnessProgram::p2wsh_from_script(
+            &self.to_script()?,
+            network,
+        )?)
+    }
+}
+
+fn match_template(ins: &[Instruction]) -> Option<Template> {
+    use self::Instruction::*;
+
+    let number = |i: &Instruction| i.number().and_then(|n| u32::try_from(n).ok());
+    match *ins {
+        [ref k, ref rest @ .., ref n, Op(OP_CHECKMULTISIG)] => {
+            let keys = rest
+                .iter()
+                .map(|i| match *i {
+                    Push(key) => Some(key.to_vec()),
+                    Op(_) => None,
+                })
+                .collect::<Option<Vec<_>>>()?;
+            if number(n)? as usize != keys.len() {
+                return None;
+            }
+            let sorted = keys.windows(2).all(|w| w[0] <= w[1]);
+            Some(Template::Multisig {
+                threshold: number(k)? as usize,
+                keys,
+                sorted,
+            })
+        }
+        [ref n, Op(op), Op(OP_DROP), Push(key), Op(OP_CHECKSIG)] => {
+            let n = number(n)?;
+            let timelock = match op {
+                OP_CHECKLOCKTIMEVERIFY => Timelock::Absolute(n),
+                OP_CHECKSEQUENCEVERIFY => Timelock::Relative(n),
+                _ => return None,
+            };
+            Some(Template::TimelockedKey {
+                key: key.to_vec(),
+                timelock,
+            })
+        }
+        [Op(OP_IF), Push(hot), Op(OP_ELSE), ref delay, Op(OP_CHECKSEQUENCEVERIFY), Op(OP_DROP), Push(cold), Op(OP_ENDIF), Op(OP_CHECKSIG)] => {
+            Some(Template::Vault {
+                hot_key: hot.to_vec(),
+                cold_key: cold.to_vec(),
+                delay: number(delay)?,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Error types for witness scripts
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// A public key is invalid
+    WitnessProgram(::Error),
+    /// A push runs past the end of the script
+    TruncatedPush,
+    /// The script does not match any template
+    UnknownTemplate,
+    /// The script matches a template but is not minimally encoded
+    NonMinimalPush,
+    /// The threshold is zero, above the number of keys, or there are too
+    /// many keys
+    InvalidThreshold,
+    /// The lock time is zero or out of range
+    InvalidTimelock,
+}
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::WitnessProgram(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::WitnessProgram(ref e) => write!(f, "{}", e),
+            Error::TruncatedPush => write!(f, "push past the end of the script"),
+            Error::UnknownTemplate => write!(f, "script does not match a template"),
+            Error::NonMinimalPush => write!(f, "non-minimal push"),
+            Error::InvalidThreshold => write!(f, "invalid multisig threshold"),
+            Error::InvalidTimelock => write!(f, "invalid lock time"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::WitnessProgram(_) => "witness program error",
+            Error::TruncatedPush => "push past the end of the script",
+            Error::UnknownTemplate => "script does not match a template",
+            Error::NonMinimalPush => "non-minimal push",
+            Error::InvalidThreshold => "invalid multisig threshold",
+            Error::InvalidTimelock => "invalid lock time",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::WitnessProgram(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(serialize_num(0), Vec::<u8>::new());
+        assert_eq!(serialize_num(127), vec![0x7f]);
+        assert_eq!(serialize_num(128), vec![0x80, 0x00]);
+        assert_eq!(serialize_num(-1), vec![0x81]);
+        assert_eq!(serialize_num(-256), vec![0x00, 0x81]);
+        assert_eq!(serialize_num(500_000), vec![0x20, 0xa1, 0x07]);
+
+        let mut script = Vec::new();
+        for n in [0, -1, 1, 16, 17].iter() {
+            push_int(&mut script, *n);
+        }
+        assert_eq!(script, vec![0x00, 0x4f, 0x51, 0x60, 0x01, 0x11]);
+
+        let mut script = Vec::new();
+        push_slice(&mut script, &[0u8; 76]);
+        assert_eq!(&script[..2], &[OP_PUSHDATA1, 76]);
+
+        for n in [-256i64, -1, 0, 1, 127, 128, 500_000, 0xffff_ffff].iter() {
+            assert_eq!(deserialize_num(&serialize_num(*n)), *n);
+        }
+    }
+
+    const A: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const B: &str = "03a0434d9e47f3c86235477c7b1ae6ae5d3442d49b1943c2b752a68e2a47e247c7";
+    const C: &str = "022f8bde4d1a07209355b4a7250a5c5128e88b84bddc619ab7cba8d569b240efe4";
+
+    fn key(s: &str) -> Vec<u8> {
+        hex::decode(s).unwrap()
+    }
+
+    #[test]
+    fn multisig_templates() {
+        let unsorted = Template::Multisig {
+            threshold: 2,
+            keys: vec![key(A), key(B), key(C)],
+            sorted: false,
+        };
+        let script = unsorted.to_script().unwrap();
+        assert_eq!(script[0], 0x52);
+        assert_eq!(&script[2..35], &key(A)[..]);
+        assert_eq!(&script[script.len() - 2..], &[0x53, OP_CHECKMULTISIG]);
+        assert_eq!(Template::from_script(&script), Ok(unsorted));
+
+        let sorted = Template::Multisig {
+            threshold: 2,
+            keys: vec![key(A), key(B), key(C)],
+            sorted: true,
+        };
+        let script = sorted.to_script().unwrap();
+        assert_eq!(&script[2..35], &key(C)[..]);
+        assert_eq!(
+            Template::from_script(&script),
+            Ok(Template::Multisig {
+                threshold: 2,
+                keys: vec![key(C), key(A), key(B)],
+                sorted: true,
+            })
+        );
+        assert_eq!(
+            sorted.witness_program(Network::Monacoin).unwrap(),
+            WitnessProgram::p2wsh_from_script(&script, Network::Monacoin).unwrap()
+        );
+
+        let invalid = Template::Multisig {
+            threshold: 3,
+            keys: vec![key(A), key(B)],
+            sorted: false,
+        };
+        assert_eq!(invalid.to_script(), Err(Error::InvalidThreshold));
+        let uncompressed = Template::Multisig {
+            threshold: 1,
+            keys: vec![key(&A[2..])],
+            sorted: false,
+        };
+        assert_eq!(
+            uncompressed.to_script(),
+            Err(Error::WitnessProgram(::Error::InvalidPublicKey))
+        );
+    }
+
+    #[test]
+    fn timelock_templates() {
+        let cltv = Template::TimelockedKey {
+            key: key(A),
+            timelock: Timelock::Absolute(500_000),
+        };
+        let script = cltv.to_script().unwrap();
+        assert_eq!(
+            &script[..6],
+            &[0x03, 0x20, 0xa1, 0x07, OP_CHECKLOCKTIMEVERIFY, OP_DROP]
+        );
+        assert_eq!(Template::from_script(&script), Ok(cltv));
+
+        let csv = Template::TimelockedKey {
+            key: key(B),
+            timelock: Timelock::Relative(16),
+        };
+        let script = csv.to_script().unwrap();
+        assert_eq!(&script[..3], &[0x60, OP_CHECKSEQUENCEVERIFY, OP_DROP]);
+        assert_eq!(Template::from_script(&script), Ok(csv));
+
+        // 16 pushed as data instead of OP_16
+        let mut non_minimal = vec![0x01, 0x10];
+        non_minimal.extend_from_slice(&script[1..]);
+        assert_eq!(
+            Template::from_script(&non_minimal),
+            Err(Error::NonMinimalPush)
+        );
+
+        let vault = Template::Vault {
+            hot_key: key(A),
+            cold_key: key(B),
+            delay: 144,
+        };
+        let script = vault.to_script().unwrap();
+        assert_eq!(script[0], OP_IF);
+        assert_eq!(
+            &script[35..40],
+            &[OP_ELSE, 0x02, 0x90, 0x00, OP_CHECKSEQUENCEVERIFY]
+        );
+        assert_eq!(Template::from_script(&script), Ok(vault.clone()));
+        assert_eq!(
+            vault.witness_program(Network::Litecoin).unwrap().network(),
+            Network::Litecoin
+        );
+
+        let too_long = Template::Vault {
+            hot_key: key(A),
+            cold_key: key(B),
+            delay: 0x10000,
+        };
+        assert_eq!(too_long.to_script(), Err(Error::InvalidTimelock));
+        assert_eq!(
+            Template::from_script(&[OP_1, OP_CHECKSIG]),
+            Err(Error::UnknownTemplate)
+        );
+        assert_eq!(
+            Template::from_script(&[0x21, 0x02]),
+            Err(Error::TruncatedPush)
+        );
+    }
+}
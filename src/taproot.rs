@@ -0,0 +1,518 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Taproot output derivation
+//!
+//! Computes the BIP-0341 output key for an x-only internal key and an
+//! optional tree of scripts, together with the merkle root and the control
+//! block needed to spend each leaf.
+
+use secp256k1::{Scalar, Secp256k1, XOnlyPublicKey};
+
+use constants::Network;
+use encode::write_compact_size;
+use hash::tagged_hash;
+use {Error, WitnessProgram};
+
+/// Leaf version of BIP-0342 tapscript
+pub const TAPSCRIPT_LEAF_VERSION: u8 = 0xc0;
+
+/// Maximum depth of a script tree, and of a control block merkle branch
+pub const TAPROOT_MAX_DEPTH: usize = 128;
+
+/// A script leaf of a Taproot tree
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct TapLeaf {
+    /// Leaf version, with the lowest bit cleared
+    leaf_version: u8,
+    /// Script committed to by the leaf
+    script: Vec<u8>,
+}
+
+impl TapLeaf {
+    /// Construct a leaf with an explicit leaf version
+    ///
+    /// The version must be even and must not be `0x50`, which would make a
+    /// control block indistinguishable from an annex.
+    pub fn new(leaf_version: u8, script: Vec<u8>) -> Result<TapLeaf, Error> {
+        if leaf_version & 1 != 0 || leaf_version == 0x50 {
+            return Err(Error::InvalidLeafVersion);
+        }
+        Ok(TapLeaf {
+            leaf_version,
+            script,
+        })
+    }
+
+    /// Construct a BIP-0342 tapscript leaf
+    pub fn tapscript(script: Vec<u8>) -> TapLeaf {
+        TapLeaf {
+            leaf_version: TAPSCRIPT_LEAF_VERSION,
+            script,
+        }
+    }
+
+    /// Leaf version
+    pub fn leaf_version(&self) -> u8 {
+        self.leaf_version
+    }
+
+    /// Script committed to by the leaf
+    pub fn script(&self) -> &[u8] {
+        &self.script
+    }
+
+    /// TapLeaf tagged hash of the version and script
+    pub fn leaf_hash(&self) -> [u8; 32] {
+        let mut data = vec![self.leaf_version];
+        write_compact_size(&mut data, self.script.len() as u64);
+        data.extend_from_slice(&self.script);
+        tagged_hash("TapLeaf", &data)
+    }
+}
+
+/// A binary tree of script leaves
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub enum TapTree {
+    /// A single script
+    Leaf(TapLeaf),
+    /// Two subtrees
+    Branch(Box<TapTree>, Box<TapTree>),
+}
+
+impl TapTree {
+    /// Construct a tree holding a single leaf
+    pub fn leaf(leaf: TapLeaf) -> TapTree {
+        TapTree::Leaf(leaf)
+    }
+
+    /// Join two subtrees under a new branch
+    pub fn branch(left: TapTree, right: TapTree) -> TapTree {
+        TapTree::Branch(Box::new(left), Box::new(right))
+    }
+
+    /// Merkle root of the tree
+    ///
+    /// Fails with `Error::TaprootTreeTooDeep` if a leaf lies deeper than
+    /// `TAPROOT_MAX_DEPTH`, without descending further.
+    pub fn root_hash(&self) -> Result<[u8; 32], Error> {
+        self.root_hash_at(0)
+    }
+
+    fn root_hash_at(&self, depth: usize) -> Result<[u8; 32], Error> {
+        match *self {
+            TapTree::Leaf(ref leaf) => Ok(leaf.leaf_hash()),
+            TapTree::Branch(ref left, ref right) => {
+                if depth >= TAPROOT_MAX_DEPTH {
+                    return Err(Error::TaprootTreeTooDeep);
+                }
+                Ok(tap_branch_hash(
+                    &left.root_hash_at(depth + 1)?,
+                    &right.root_hash_at(depth + 1)?,
+                ))
+            }
+        }
+    }
+
+    /// Lists every leaf with its merkle branch, ordered from the leaf up
+    ///
+    /// `depth` is the depth of this subtree within the whole tree.
+    fn leaves_with_branches(&self, depth: usize) -> Result<(LeafBranches, [u8; 32]), Error> {
+        match *self {
+            TapTree::Leaf(ref leaf) => Ok((vec![(leaf.clone(), Vec::new())], leaf.leaf_hash())),
+            TapTree::Branch(ref left, ref right) => {
+                if depth >= TAPROOT_MAX_DEPTH {
+                    return Err(Error::TaprootTreeTooDeep);
+                }
+                let (mut left_leaves, left_hash) = left.leaves_with_branches(depth + 1)?;
+                let (mut right_leaves, right_hash) = right.leaves_with_branches(depth + 1)?;
+                for &mut (_, ref mut branch) in left_leaves.iter_mut() {
+                    branch.push(right_hash);
+                }
+                for &mut (_, ref mut branch) in right_leaves.iter_mut() {
+                    branch.push(left_hash);
+                }
+                left_leaves.append(&mut right_leaves);
+                Ok((left_leaves, tap_branch_hash(&left_hash, &right_hash)))
+            }
+        }
+    }
+}
+
+/// Leaves of a tree, each with its merkle branch
+type LeafBranches = Vec<(TapLeaf, Vec<[u8; 32]>)>;
+
+/// TapBranch tagged hash of two child hashes, sorted lexicographically
+pub fn tap_branch_hash(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let mut data = Vec::with_capacity(64);
+    if a <= b {
+        data.extend_from_slice(a);
+        data.extend_from_slice(b);
+    } else {
+        data.extend_from_slice(b);
+        data.extend_from_slice(a);
+    }
+    tagged_hash("TapBranch", &data)
+}
+
+/// Tweaks an internal key with an optional merkle root
+///
+/// Returns the x-only output key and its parity (0 for even, 1 for odd).
+pub fn tweak_key(
+    internal_key: &[u8; 32],
+    merkle_root: Option<&[u8; 32]>,
+) -> Result<([u8; 32], u8), Error> {
+    let key = XOnlyPublicKey::from_slice(internal_key).map_err(|_| Error::InvalidPublicKey)?;
+    let mut data = internal_key.to_vec();
+    if let Some(root) = merkle_root {
+        data.extend_from_slice(root);
+    }
+    let tweak = Scalar::from_be_bytes(tagged_hash("TapTweak", &data))
+        .map_err(|_| Error::InvalidTaprootTweak)?;
+    let secp = Secp256k1::verification_only();
+    let (output_key, parity) = key
+        .add_tweak(&secp, &tweak)
+        .map_err(|_| Error::InvalidTaprootTweak)?;
+    Ok((output_key.serialize(), parity.to_u8()))
+}
+
+/// Data needed to prove a leaf is committed to by an output key
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct ControlBlock {
+    /// Leaf version of the spent script
+    leaf_version: u8,
+    /// Parity of the output key, 0 or 1
+    output_key_parity: u8,
+    /// Untweaked x-only internal key
+    internal_key: [u8; 32],
+    /// Merkle branch from the leaf up to the root
+    merkle_branch: Vec<[u8; 32]>,
+}
+
+impl ControlBlock {
+    /// Parses a serialized control block from a script path witness
+    pub fn parse(data: &[u8]) -> Result<ControlBlock, Error> {
+        if data.len() < 33 || data.len() % 32 != 1 || (data.len() - 33) / 32 > TAPROOT_MAX_DEPTH {
+            return Err(Error::InvalidControlBlock);
+        }
+        let mut internal_key = [0u8; 32];
+        internal_key.copy_from_slice(&data[1..33]);
+        let merkle_branch = data[33..]
+            .chunks(32)
+            .map(|chunk| {
+                let mut node = [0u8; 32];
+                node.copy_from_slice(chunk);
+                node
+            })
+            .collect();
+        Ok(ControlBlock {
+            leaf_version: data[0] & 0xfe,
+            output_key_parity: data[0] & 1,
+            internal_key,
+            merkle_branch,
+        })
+    }
+
+    /// Serializes the control block for use in a witness
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(33 + 32 * self.merkle_branch.len());
+        out.push(self.leaf_version | self.output_key_parity);
+        out.extend_from_slice(&self.internal_key);
+        for node in &self.merkle_branch {
+            out.extend_from_slice(node);
+        }
+        out
+    }
+
+    /// Leaf version of the spent script
+    pub fn leaf_version(&self) -> u8 {
+        self.leaf_version
+    }
+
+    /// Parity of the output key, 0 for even and 1 for odd
+    pub fn output_key_parity(&self) -> u8 {
+        self.output_key_parity
+    }
+
+    /// Untweaked x-only internal key
+    pub fn internal_key(&self) -> &[u8; 32] {
+        &self.internal_key
+    }
+
+    /// Merkle branch from the leaf up to the root
+    pub fn merkle_branch(&self) -> &[[u8; 32]] {
+        &self.merkle_branch
+    }
+
+    /// Computes the output key committing to `script` under this control block
+    pub fn output_key(&self, script: &[u8]) -> Result<[u8; 32], Error> {
+        let leaf = TapLeaf::new(self.leaf_version, script.to_vec())?;
+        let root = self
+            .merkle_branch
+            .iter()
+            .fold(leaf.leaf_hash(), |hash, node| tap_branch_hash(&hash, node));
+        let (output_key, parity) = tweak_key(&self.internal_key, Some(&root))?;
+        if parity != self.output_key_parity {
+            return Err(Error::InvalidControlBlock);
+        }
+        Ok(output_key)
+    }
+}
+
+/// A Taproot output with everything needed to spend it
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct TaprootOutput {
+    /// Untweaked x-only internal key
+    internal_key: [u8; 32],
+    /// Tweaked x-only output key
+    output_key: [u8; 32],
+    /// Parity of the output key, 0 or 1
+    output_key_parity: u8,
+    /// Merkle root of the script tree, if any
+    merkle_root: Option<[u8; 32]>,
+    /// Every leaf of the script tree with its control block
+    leaves: Vec<(TapLeaf, ControlBlock)>,
+    /// Version 1 witness program paying to the output key
+    program: WitnessProgram,
+}
+
+impl TaprootOutput {
+    /// Derives the output for an internal key and optional script tree
+    ///
+    /// Without a tree the output commits to no scripts and can only be spent
+    /// with the key path, as recommended by BIP-0341.
+    pub fn new(
+        internal_key: [u8; 32],
+        tree: Option<&TapTree>,
+        network: Network,
+    ) -> Result<TaprootOutput, Error> {
+        let (leaves, merkle_root) = match tree {
+            Some(tree) => {
+                let (leaves, root) = tree.leaves_with_branches(0)?;
+                (leaves, Some(root))
+            }
+            None => (Vec::new(), None),
+        };
+        let (output_key, output_key_parity) = tweak_key(&internal_key, merkle_root.as_ref())?;
+        let leaves = leaves
+            .into_iter()
+            .map(|(leaf, merkle_branch)| {
+                let control_block = ControlBlock {
+                    leaf_version: leaf.leaf_version,
+                    output_key_parity,
+                    internal_key,
+                    merkle_branch,
+                };
+                (leaf, control_block)
+            })
+            .collect();
+        Ok(TaprootOutput {
+            internal_key,
+            output_key,
+            output_key_parity,
+            merkle_root,
+            leaves,
+            program: WitnessProgram::p2tr(output_key, network)?,
+        })
+    }
+
+    /// Untweaked x-only internal key
+    pub fn internal_key(&self) -> &[u8; 32] {
+        &self.internal_key
+    }
+
+    /// Tweaked x-only output key
+    pub fn output_key(&self) -> &[u8; 32] {
+        &self.output_key
+    }
+
+    /// Parity of the output key, 0 for even and 1 for odd
+    pub fn output_key_parity(&self) -> u8 {
+        self.output_key_parity
+    }
+
+    /// Merkle root of the script tree, if any
+    pub fn merkle_root(&self) -> Option<&[u8; 32]> {
+        self.merkle_root.as_ref()
+    }
+
+    /// Every leaf of the script tree with its control block, in tree order
+    pub fn leaves(&self) -> &[(TapLeaf, ControlBlock)] {
+        &self.leaves
+    }
+
+    /// Control block for spending the given leaf
+    pub fn control_block(&self, leaf: &TapLeaf) -> Option<&ControlBlock> {
+        self.leaves
+            .iter()
+            .find(|(l, _)| l == leaf)
+            .map(|(_, cb)| cb)
+    }
+
+    /// Version 1 witness program paying to the output key
+    pub fn witness_program(&self) -> &WitnessProgram {
+        &self.program
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+
+    fn key(s: &str) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&hex::decode(s).unwrap());
+        out
+    }
+
+    #[test]
+    fn key_path_only() {
+        // BIP-0341 wallet test vector 0
+        let output = TaprootOutput::new(
+            key("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d"),
+            None,
+            Network::Bitcoin,
+        )
+        .unwrap();
+        assert_eq!(
+            output.output_key(),
+            &key("53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343")
+        );
+        assert_eq!(
+            output.witness_program().to_address(),
+            "bc1p2wsldez5mud2yam29q22wgfh9439spgduvct83k3pm50fcxa5dps59h4z5"
+        );
+        assert_eq!(output.merkle_root(), None);
+        assert!(output.leaves().is_empty());
+    }
+
+    #[test]
+    fn single_leaf() {
+        // BIP-0341 wallet test vector 1
+        let leaf = TapLeaf::tapscript(
+            hex::decode("20d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8ac")
+                .unwrap(),
+        );
+        let tree = TapTree::leaf(leaf.clone());
+        let output = TaprootOutput::new(
+            key("187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27"),
+            Some(&tree),
+            Network::Bitcoin,
+        )
+        .unwrap();
+        let leaf_hash = key("5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21");
+        assert_eq!(leaf.leaf_hash(), leaf_hash);
+        assert_eq!(output.merkle_root(), Some(&leaf_hash));
+        assert_eq!(
+            output.output_key(),
+            &key("147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3")
+        );
+        assert_eq!(
+            output.witness_program().to_address(),
+            "bc1pz37fc4cn9ah8anwm4xqqhvxygjf9rjf2resrw8h8w4tmvcs0863sa2e586"
+        );
+        let control_block = output.control_block(&leaf).unwrap();
+        assert_eq!(
+            control_block.serialize(),
+            hex::decode("c1187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27")
+                .unwrap()
+        );
+        assert_eq!(
+            ControlBlock::parse(&control_block.serialize()).unwrap(),
+            *control_block
+        );
+        assert_eq!(
+            control_block.output_key(leaf.script()).unwrap(),
+            *output.output_key()
+        );
+    }
+
+    #[test]
+    fn two_leaves() {
+        let first = TapLeaf::tapscript(
+            hex::decode("20b617298552a72ade070667e86ca63b8f5789a9fe8731ef91202a91c9f3459007ac")
+                .unwrap(),
+        );
+        let second = TapLeaf::new(0xfa, hex::decode("06424950333431").unwrap()).unwrap();
+        let tree = TapTree::branch(TapTree::leaf(first.clone()), TapTree::leaf(second.clone()));
+        let output = TaprootOutput::new(
+            key("93478e9488f956df2396be2ce6c5cced75f900dfa18e7dabd2428aae78451820"),
+            Some(&tree),
+            Network::Monacoin,
+        )
+        .unwrap();
+        assert_eq!(output.leaves().len(), 2);
+        for (leaf, control_block) in output.leaves() {
+            assert_eq!(control_block.merkle_branch().len(), 1);
+            assert_eq!(
+                control_block.output_key(leaf.script()).unwrap(),
+                *output.output_key()
+            );
+        }
+        assert_eq!(
+            output.control_block(&first).unwrap().merkle_branch()[0],
+            second.leaf_hash()
+        );
+        assert_eq!(output.witness_program().network(), Network::Monacoin);
+        assert!(output.witness_program().to_address().starts_with("mona1p"));
+    }
+
+    #[test]
+    fn invalid_inputs() {
+        assert_eq!(TapLeaf::new(0xc1, vec![]), Err(Error::InvalidLeafVersion));
+        assert_eq!(TapLeaf::new(0x50, vec![]), Err(Error::InvalidLeafVersion));
+        // x coordinate not on the curve
+        assert_eq!(
+            TaprootOutput::new([0u8; 32], None, Network::Bitcoin),
+            Err(Error::InvalidPublicKey)
+        );
+        assert_eq!(
+            ControlBlock::parse(&[0xc0; 32]),
+            Err(Error::InvalidControlBlock)
+        );
+        assert_eq!(
+            ControlBlock::parse(&[0xc0; 34]),
+            Err(Error::InvalidControlBlock)
+        );
+
+        let mut tree = TapTree::leaf(TapLeaf::tapscript(vec![0x51]));
+        for _ in 0..TAPROOT_MAX_DEPTH {
+            tree = TapTree::branch(tree, TapTree::leaf(TapLeaf::tapscript(vec![0x52])));
+        }
+        let internal = key("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d");
+        assert!(TaprootOutput::new(internal, Some(&tree), Network::Bitcoin).is_ok());
+        assert!(tree.root_hash().is_ok());
+        let mut tree = TapTree::branch(tree, TapTree::leaf(TapLeaf::tapscript(vec![0x53])));
+        assert_eq!(
+            TaprootOutput::new(internal, Some(&tree), Network::Bitcoin),
+            Err(Error::TaprootTreeTooDeep)
+        );
+        // Far deeper trees fail as quickly, whichever side the depth is on
+        for _ in 0..1_000 {
+            tree = TapTree::branch(TapTree::leaf(TapLeaf::tapscript(vec![0x54])), tree);
+        }
+        assert_eq!(tree.root_hash(), Err(Error::TaprootTreeTooDeep));
+        assert_eq!(
+            TaprootOutput::new(internal, Some(&tree), Network::Bitcoin),
+            Err(Error::TaprootTreeTooDeep)
+        );
+    }
+}
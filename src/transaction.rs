@@ -0,0 +1,559 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Raw transaction parsing
+//!
+//! Decodes serialized transactions, including the segwit marker and the
+//! serialization variants of some altcoins, and resolves each output script
+//! to a witness program on the given network where one applies.
+//!
+//! Peercoin transactions below version 3 carry a timestamp after the
+//! version. Litecoin transactions may set the MWEB flag, in which case an
+//! optional MimbleWimble extension block body follows the witnesses; the
+//! body is kept as opaque bytes.
+
+use std::{error, fmt};
+
+use constants::{self, Network};
+use encode::{write_compact_size, write_var_bytes, Reader};
+use hash::{sha256, sha256d};
+use hex;
+use wrapped;
+use WitnessProgram;
+
+/// Segwit flag bit marking witness data
+const WITNESS_FLAG: u8 = 0x01;
+
+/// Litecoin flag bit marking a MimbleWimble extension block body
+const MWEB_FLAG: u8 = 0x08;
+
+/// First Peercoin transaction version without a timestamp
+const PEERCOIN_NO_TIME_VERSION: i32 = 3;
+
+/// A transaction identifier
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub struct Txid([u8; 32]);
+
+impl Txid {
+    /// Wraps a hash in internal byte order
+    pub fn from_byte_array(bytes: [u8; 32]) -> Txid {
+        Txid(bytes)
+    }
+
+    /// The hash in internal byte order
+    ///
+    /// This is the reverse of the order used when displaying the txid.
+    pub fn as_byte_array(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Txid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for b in self.0.iter().rev() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// A transaction input
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct TxIn {
+    previous_txid: Txid,
+    previous_vout: u32,
+    script_sig: Vec<u8>,
+    sequence: u32,
+    witness: Vec<Vec<u8>>,
+}
+
+impl TxIn {
+    /// Transaction whose output is spent
+    pub fn previous_txid(&self) -> Txid {
+        self.previous_txid
+    }
+
+    /// Index of the spent output
+    pub fn previous_vout(&self) -> u32 {
+        self.previous_vout
+    }
+
+    /// Signature script
+    pub fn script_sig(&self) -> &[u8] {
+        &self.script_sig
+    }
+
+    /// Sequence number
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    /// Witness stack, empty for inputs without witness data
+    pub fn witness(&self) -> &[Vec<u8>] {
+        &self.witness
+    }
+
+    /// Whether this input spends no previous output
+    pub fn is_coinbase(&self) -> bool {
+        self.previous_txid.0 == [0u8; 32] && self.previous_vout == 0xffff_ffff
+    }
+}
+
+/// A transaction output
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct TxOut {
+    value: u64,
+    script_pubkey: Vec<u8>,
+}
+
+impl TxOut {
+    /// Amount in the network's base unit
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    /// Output script
+    pub fn script_pubkey(&self) -> &[u8] {
+        &self.script_pubkey
+    }
+
+    /// Witness program locked to by the output script, if it is one
+    ///
+    /// See `wrapped::parse_scriptpubkey` for the wrappers that are stripped.
+    pub fn witness_program(&self, network: Network) -> Option<WitnessProgram> {
+        wrapped::witness_program(&self.script_pubkey, network)
+    }
+}
+
+/// A decoded transaction
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct Transaction {
+    network: Network,
+    version: i32,
+    timestamp: Option<u32>,
+    inputs: Vec<TxIn>,
+    outputs: Vec<TxOut>,
+    lock_time: u32,
+    mweb: Option<Vec<u8>>,
+}
+
+impl Transaction {
+    /// Parses a serialized transaction
+    ///
+    /// The whole of `data` must be a single transaction.
+    pub fn parse(data: &[u8], network: Network) -> Result<Transaction, Error> {
+        let mut reader = Reader::new(data);
+        let tx = Transaction::read(&mut reader, network, true)?;
+        if reader.remaining() != 0 {
+            return Err(Error::TrailingData);
+        }
+        Ok(tx)
+    }
+
+    /// Parses a hex-encoded transaction
+    pub fn from_hex(s: &str, network: Network) -> Result<Transaction, Error> {
+        let data = hex::decode(s).ok_or(Error::InvalidHex)?;
+        Transaction::parse(&data, network)
+    }
+
+    /// Reads a transaction from a stream of consensus-encoded data
+    ///
+    /// MWEB bodies have no length prefix, so they can only be skipped when
+    /// `standalone` says the transaction runs to the end of the data.
+    pub(crate) fn read(
+        reader: &mut Reader,
+        network: Network,
+        standalone: bool,
+    ) -> Result<Transaction, Error> {
+        if !constants::has_bitcoin_script(&network) {
+            return Err(Error::UnsupportedNetwork(network));
+        }
+        let version = reader.read_u32().ok_or(Error::UnexpectedEnd)? as i32;
+        let timestamp = if has_timestamp(network, version) {
+            Some(reader.read_u32().ok_or(Error::UnexpectedEnd)?)
+        } else {
+            None
+        };
+
+        let mut flags = 0;
+        if reader.peek() == Some(0) {
+            reader.read_u8();
+            flags = reader.read_u8().ok_or(Error::UnexpectedEnd)?;
+            let known = if allows_mweb(network) {
+                WITNESS_FLAG | MWEB_FLAG
+            } else {
+                WITNESS_FLAG
+            };
+            if flags == 0 || flags & !known != 0 {
+                return Err(Error::InvalidFlag(flags));
+            }
+        }
+
+        let mut inputs = Vec::new();
+        for _ in 0..read_count(reader)? {
+            inputs.push(TxIn {
+                previous_txid: Txid(reader.read_hash().ok_or(Error::UnexpectedEnd)?),
+                previous_vout: reader.read_u32().ok_or(Error::UnexpectedEnd)?,
+                script_sig: read_var_bytes(reader)?,
+                sequence: reader.read_u32().ok_or(Error::UnexpectedEnd)?,
+                witness: Vec::new(),
+            });
+        }
+        let mut outputs = Vec::new();
+        for _ in 0..read_count(reader)? {
+            outputs.push(TxOut {
+                value: reader.read_u64().ok_or(Error::UnexpectedEnd)?,
+                script_pubkey: read_var_bytes(reader)?,
+            });
+        }
+
+        if flags & WITNESS_FLAG != 0 {
+            for input in &mut inputs {
+                for _ in 0..read_count(reader)? {
+                    input.witness.push(read_var_bytes(reader)?);
+                }
+            }
+        }
+
+        let mut mweb = None;
+        if flags & MWEB_FLAG != 0 && reader.read_u8().ok_or(Error::UnexpectedEnd)? != 0 {
+            if !standalone {
+                return Err(Error::UnsupportedMweb);
+            }
+            let len = reader
+                .remaining()
+                .checked_sub(4)
+                .ok_or(Error::UnexpectedEnd)?;
+            mweb = Some(reader.read_bytes(len).ok_or(Error::UnexpectedEnd)?.to_vec());
+        }
+
+        let lock_time = reader.read_u32().ok_or(Error::UnexpectedEnd)?;
+        Ok(Transaction {
+            network,
+            version,
+            timestamp,
+            inputs,
+            outputs,
+            lock_time,
+            mweb,
+        })
+    }
+
+    /// Network the transaction was parsed for
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Transaction version
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Peercoin transaction timestamp, if the serialization has one
+    pub fn timestamp(&self) -> Option<u32> {
+        self.timestamp
+    }
+
+    /// Inputs in order
+    pub fn inputs(&self) -> &[TxIn] {
+        &self.inputs
+    }
+
+    /// Outputs in order
+    pub fn outputs(&self) -> &[TxOut] {
+        &self.outputs
+    }
+
+    /// Lock time
+    pub fn lock_time(&self) -> u32 {
+        self.lock_time
+    }
+
+    /// Opaque Litecoin MWEB body, if the transaction has one
+    pub fn mweb(&self) -> Option<&[u8]> {
+        self.mweb.as_ref().map(|m| &m[..])
+    }
+
+    /// Whether any input carries witness data
+    pub fn has_witness(&self) -> bool {
+        self.inputs.iter().any(|input| !input.witness.is_empty())
+    }
+
+    /// Transaction identifier
+    ///
+    /// Hashes the serialization without witnesses or MWEB data. Groestlcoin
+    /// uses a single SHA256 here; other networks use double SHA256.
+    pub fn txid(&self) -> Txid {
+        let mut data = Vec::new();
+        data.extend_from_slice(&self.version.to_le_bytes());
+        if let Some(timestamp) = self.timestamp {
+            data.extend_from_slice(&timestamp.to_le_bytes());
+        }
+        write_compact_size(&mut data, self.inputs.len() as u64);
+        for input in &self.inputs {
+            data.extend_from_slice(&input.previous_txid.0);
+            data.extend_from_slice(&input.previous_vout.to_le_bytes());
+            write_var_bytes(&mut data, &input.script_sig);
+            data.extend_from_slice(&input.sequence.to_le_bytes());
+        }
+        write_compact_size(&mut data, self.outputs.len() as u64);
+        for output in &self.outputs {
+            data.extend_from_slice(&output.value.to_le_bytes());
+            write_var_bytes(&mut data, &output.script_pubkey);
+        }
+        data.extend_from_slice(&self.lock_time.to_le_bytes());
+        match self.network {
+            Network::Groestlcoin | Network::GroestlcoinTestnet => Txid(sha256(&data)),
+            _ => Txid(sha256d(&data)),
+        }
+    }
+
+    /// Outputs that lock to a witness program, with their indices
+    pub fn witness_outputs(&self) -> Vec<(u32, WitnessProgram)> {
+        self.outputs
+            .iter()
+            .enumerate()
+            .filter_map(|(vout, output)| {
+                output
+                    .witness_program(self.network)
+                    .map(|program| (vout as u32, program))
+            })
+            .collect()
+    }
+}
+
+/// Whether a transaction of this version carries a Peercoin timestamp
+fn has_timestamp(network: Network, version: i32) -> bool {
+    match network {
+        Network::Peercoin | Network::PeercoinTestnet => version < PEERCOIN_NO_TIME_VERSION,
+        _ => false,
+    }
+}
+
+/// Whether the network accepts the Litecoin MWEB flag
+fn allows_mweb(network: Network) -> bool {
+    matches!(network, Network::Litecoin | Network::LitecoinTestnet)
+}
+
+/// Reads a count of items that each take at least one byte
+fn read_count(reader: &mut Reader) -> Result<u64, Error> {
+    let n = reader.read_compact_size().ok_or(Error::UnexpectedEnd)?;
+    if n > reader.remaining() as u64 {
+        return Err(Error::UnexpectedEnd);
+    }
+    Ok(n)
+}
+
+fn read_var_bytes(reader: &mut Reader) -> Result<Vec<u8>, Error> {
+    reader
+        .read_var_bytes()
+        .map(|b| b.to_vec())
+        .ok_or(Error::UnexpectedEnd)
+}
+
+/// Error types for transaction parsing
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// The data ends in the middle of the transaction, or a length is
+    /// malformed
+    UnexpectedEnd,
+    /// Data remains after the transaction
+    TrailingData,
+    /// The input is not valid hex
+    InvalidHex,
+    /// The segwit flag byte has unknown bits set, or none
+    InvalidFlag(u8),
+    /// An MWEB body cannot be delimited in this context
+    UnsupportedMweb,
+    /// The network does not use Bitcoin-style transactions
+    UnsupportedNetwork(Network),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnexpectedEnd => write!(f, "unexpected end of transaction data"),
+            Error::TrailingData => write!(f, "trailing data after transaction"),
+            Error::InvalidHex => write!(f, "invalid hex"),
+            Error::InvalidFlag(flags) => write!(f, "invalid segwit flag: {:#04x}", flags),
+            Error::UnsupportedMweb => write!(f, "MWEB body cannot be delimited"),
+            Error::UnsupportedNetwork(network) => {
+                write!(f, "{:?} does not use Bitcoin-style transactions", network)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::UnexpectedEnd => "unexpected end of transaction data",
+            Error::TrailingData => "trailing data after transaction",
+            Error::InvalidHex => "invalid hex",
+            Error::InvalidFlag(_) => "invalid segwit flag",
+            Error::UnsupportedMweb => "MWEB body cannot be delimited",
+            Error::UnsupportedNetwork(_) => "network does not use Bitcoin-style transactions",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENESIS_COINBASE: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+
+    const P2WPKH_SCRIPT: &str = "0014751e76e8199196d454941c45d1b3a323f1433bd6";
+    const P2PKH_SCRIPT: &str = "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac";
+
+    /// Serializes a one-input, two-output transaction
+    fn build(version: i32, timestamp: Option<u32>, flags: u8, tail: &[u8]) -> Vec<u8> {
+        let mut tx = version.to_le_bytes().to_vec();
+        if let Some(timestamp) = timestamp {
+            tx.extend_from_slice(&timestamp.to_le_bytes());
+        }
+        if flags != 0 {
+            tx.extend_from_slice(&[0x00, flags]);
+        }
+        tx.push(1);
+        tx.extend_from_slice(&[0x11; 32]);
+        tx.extend_from_slice(&3u32.to_le_bytes());
+        tx.push(0);
+        tx.extend_from_slice(&0xffff_fffeu32.to_le_bytes());
+        tx.push(2);
+        for (value, script) in [(50_000u64, P2WPKH_SCRIPT), (20_000, P2PKH_SCRIPT)].iter() {
+            tx.extend_from_slice(&value.to_le_bytes());
+            write_var_bytes(&mut tx, &hex::decode(script).unwrap());
+        }
+        if flags & WITNESS_FLAG != 0 {
+            tx.push(2);
+            write_var_bytes(&mut tx, &[0x30; 71]);
+            write_var_bytes(&mut tx, &[0x02; 33]);
+        }
+        tx.extend_from_slice(tail);
+        tx.extend_from_slice(&600_000u32.to_le_bytes());
+        tx
+    }
+
+    #[test]
+    fn genesis_coinbase() {
+        let tx = Transaction::from_hex(GENESIS_COINBASE, Network::Bitcoin).unwrap();
+        assert_eq!(
+            tx.txid().to_string(),
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"
+        );
+        assert!(tx.inputs()[0].is_coinbase());
+        assert_eq!(tx.outputs()[0].value(), 5_000_000_000);
+        assert!(!tx.has_witness());
+        assert!(tx.witness_outputs().is_empty());
+    }
+
+    #[test]
+    fn segwit_outputs() {
+        let legacy = Transaction::parse(&build(2, None, 0, &[]), Network::Bitcoin).unwrap();
+        let tx = Transaction::parse(&build(2, None, WITNESS_FLAG, &[]), Network::Bitcoin).unwrap();
+        assert!(tx.has_witness());
+        assert_eq!(tx.inputs()[0].witness().len(), 2);
+        assert_eq!(tx.inputs()[0].sequence(), 0xffff_fffe);
+        assert_eq!(tx.lock_time(), 600_000);
+        assert_eq!(tx.txid(), legacy.txid());
+
+        let outputs = tx.witness_outputs();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].0, 0);
+        assert_eq!(
+            outputs[0].1.to_address(),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+        assert_eq!(tx.outputs()[1].witness_program(Network::Bitcoin), None);
+
+        let tx = Transaction::parse(&build(2, None, WITNESS_FLAG, &[]), Network::Vertcoin).unwrap();
+        assert_eq!(
+            tx.witness_outputs()[0].1.to_address(),
+            "vtc1qw508d6qejxtdg4y5r3zarvary0c5xw7kuk9r06"
+        );
+    }
+
+    #[test]
+    fn peercoin_timestamp() {
+        let data = build(1, Some(1_600_000_000), WITNESS_FLAG, &[]);
+        let tx = Transaction::parse(&data, Network::Peercoin).unwrap();
+        assert_eq!(tx.timestamp(), Some(1_600_000_000));
+        assert_eq!(tx.witness_outputs().len(), 1);
+        assert!(Transaction::parse(&data, Network::Bitcoin).is_err());
+
+        let tx = Transaction::parse(&build(3, None, 0, &[]), Network::Peercoin).unwrap();
+        assert_eq!(tx.timestamp(), None);
+    }
+
+    #[test]
+    fn litecoin_mweb() {
+        let flags = WITNESS_FLAG | MWEB_FLAG;
+        let tx = Transaction::parse(&build(2, None, flags, &[0]), Network::Litecoin).unwrap();
+        assert_eq!(tx.mweb(), None);
+        assert_eq!(tx.witness_outputs().len(), 1);
+
+        let data = build(2, None, flags, &[1, 0xaa, 0xbb]);
+        let tx = Transaction::parse(&data, Network::Litecoin).unwrap();
+        assert_eq!(tx.mweb(), Some(&[0xaa, 0xbb][..]));
+        assert_eq!(tx.lock_time(), 600_000);
+        assert_eq!(
+            tx.txid(),
+            Transaction::parse(&build(2, None, 0, &[]), Network::Litecoin)
+                .unwrap()
+                .txid()
+        );
+        assert_eq!(
+            Transaction::read(&mut Reader::new(&data), Network::Litecoin, false),
+            Err(Error::UnsupportedMweb)
+        );
+        assert_eq!(
+            Transaction::parse(&build(2, None, flags, &[0]), Network::Bitcoin),
+            Err(Error::InvalidFlag(flags))
+        );
+    }
+
+    #[test]
+    fn invalid_transactions() {
+        let data = build(2, None, WITNESS_FLAG, &[]);
+        assert_eq!(
+            Transaction::parse(&data[..data.len() - 1], Network::Bitcoin),
+            Err(Error::UnexpectedEnd)
+        );
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert_eq!(
+            Transaction::parse(&trailing, Network::Bitcoin),
+            Err(Error::TrailingData)
+        );
+        assert_eq!(
+            Transaction::parse(&build(2, None, 0x02, &[]), Network::Bitcoin),
+            Err(Error::InvalidFlag(0x02))
+        );
+        assert_eq!(
+            Transaction::from_hex("0100000", Network::Bitcoin),
+            Err(Error::InvalidHex)
+        );
+        assert_eq!(
+            Transaction::parse(&data, Network::Handshake),
+            Err(Error::UnsupportedNetwork(Network::Handshake))
+        );
+    }
+}
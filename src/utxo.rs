@@ -0,0 +1,616 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! UTXO snapshot reading
+//!
+//! Streams the coins of a snapshot written by `dumptxoutset`, decompressing
+//! each amount and script. Both the original layout, a bare base block hash
+//! and coin count followed by one outpoint per coin, and the layout
+//! introduced in Bitcoin Core 28, with a versioned header naming the network
+//! and coins grouped by transaction, are read.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::{error, fmt};
+
+use secp256k1::PublicKey;
+
+use constants::{self, Network};
+use transaction::Txid;
+use wrapped;
+use WitnessProgram;
+
+/// Magic bytes opening a versioned snapshot
+pub const SNAPSHOT_MAGIC: [u8; 5] = [b'u', b't', b'x', b'o', 0xff];
+
+/// Snapshot format version read here
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+/// Number of special script encodings in compressed scripts
+const SPECIAL_SCRIPTS: u64 = 6;
+
+/// Longest script a compressed script may expand to
+const MAX_SCRIPT_SIZE: u64 = 10_000;
+
+/// An unspent output from a snapshot
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct Coin {
+    txid: Txid,
+    vout: u32,
+    height: u32,
+    coinbase: bool,
+    value: u64,
+    script_pubkey: Vec<u8>,
+}
+
+impl Coin {
+    /// Transaction that created the output
+    pub fn txid(&self) -> Txid {
+        self.txid
+    }
+
+    /// Index of the output in its transaction
+    pub fn vout(&self) -> u32 {
+        self.vout
+    }
+
+    /// Height of the block that created the output
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Whether the output was created by a coinbase transaction
+    pub fn is_coinbase(&self) -> bool {
+        self.coinbase
+    }
+
+    /// Amount in the network's base unit
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    /// Decompressed output script
+    pub fn script_pubkey(&self) -> &[u8] {
+        &self.script_pubkey
+    }
+
+    /// Witness program the coin is locked to, if any, with name or asset
+    /// data removed
+    pub fn witness_program(&self, network: Network) -> Option<WitnessProgram> {
+        wrapped::witness_program(&self.script_pubkey, network)
+    }
+}
+
+/// Streams the coins of a `dumptxoutset` snapshot
+pub struct SnapshotReader<R> {
+    reader: R,
+    network: Network,
+    versioned: bool,
+    base_block_hash: [u8; 32],
+    coins_count: u64,
+    coins_read: u64,
+    group_txid: Txid,
+    group_left: u64,
+    failed: bool,
+}
+
+impl<R: Read> SnapshotReader<R> {
+    /// Reads the snapshot header
+    ///
+    /// A versioned snapshot must name the magic of `network`, where the
+    /// magic is known.
+    pub fn new(mut reader: R, network: Network) -> Result<SnapshotReader<R>, Error> {
+        let mut start = [0u8; 5];
+        read_exact(&mut reader, &mut start)?;
+        let mut base_block_hash = [0u8; 32];
+        let versioned = start == SNAPSHOT_MAGIC;
+        if versioned {
+            let mut version = [0u8; 2];
+            read_exact(&mut reader, &mut version)?;
+            let version = u16::from_le_bytes(version);
+            if version != SNAPSHOT_VERSION {
+                return Err(Error::UnsupportedVersion(version));
+            }
+            let mut magic = [0u8; 4];
+            read_exact(&mut reader, &mut magic)?;
+            if let Some(expected) = constants::magic(&network) {
+                if expected != magic {
+                    return Err(Error::NetworkMismatch);
+                }
+            }
+            read_exact(&mut reader, &mut base_block_hash)?;
+        } else {
+            base_block_hash[..5].copy_from_slice(&start);
+            read_exact(&mut reader, &mut base_block_hash[5..])?;
+        }
+        let mut coins_count = [0u8; 8];
+        read_exact(&mut reader, &mut coins_count)?;
+        Ok(SnapshotReader {
+            reader,
+            network,
+            versioned,
+            base_block_hash,
+            coins_count: u64::from_le_bytes(coins_count),
+            coins_read: 0,
+            group_txid: Txid::from_byte_array([0; 32]),
+            group_left: 0,
+            failed: false,
+        })
+    }
+
+    /// Hash of the block the snapshot was taken at, in internal byte order
+    pub fn base_block_hash(&self) -> &[u8; 32] {
+        &self.base_block_hash
+    }
+
+    /// Number of coins the snapshot declares
+    pub fn coins_count(&self) -> u64 {
+        self.coins_count
+    }
+
+    /// Network the snapshot is read for
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Sums the value held by each address
+    ///
+    /// Consumes the remaining coins. Outputs that are not witness programs
+    /// have no address on this network and are left out. A balance that
+    /// overflows fails with `Error::InvalidCoin`.
+    pub fn balances(self) -> Result<BTreeMap<String, u64>, Error> {
+        let network = self.network;
+        let mut balances = BTreeMap::new();
+        for coin in self {
+            let coin = coin?;
+            if let Some(program) = coin.witness_program(network) {
+                let balance = balances.entry(program.to_address()).or_insert(0u64);
+                *balance = balance.checked_add(coin.value).ok_or(Error::InvalidCoin)?;
+            }
+        }
+        Ok(balances)
+    }
+
+    fn read_coin(&mut self) -> Result<Coin, Error> {
+        let (txid, vout) = if self.versioned {
+            if self.group_left == 0 {
+                self.group_txid = Txid::from_byte_array(read_hash(&mut self.reader)?);
+                self.group_left = read_compact_size(&mut self.reader)?;
+                if self.group_left == 0 {
+                    return Err(Error::InvalidCoin);
+                }
+            }
+            self.group_left -= 1;
+            let vout = read_compact_size(&mut self.reader)?;
+            if vout > u64::from(u32::MAX) {
+                return Err(Error::InvalidCoin);
+            }
+            (self.group_txid, vout as u32)
+        } else {
+            let txid = Txid::from_byte_array(read_hash(&mut self.reader)?);
+            let mut vout = [0u8; 4];
+            read_exact(&mut self.reader, &mut vout)?;
+            (txid, u32::from_le_bytes(vout))
+        };
+        let code = read_varint(&mut self.reader)?;
+        if code >> 1 > u64::from(u32::MAX) {
+            return Err(Error::InvalidCoin);
+        }
+        let value = decompress_amount(read_varint(&mut self.reader)?, &self.network)
+            .ok_or(Error::InvalidCoin)?;
+        let script_pubkey = read_script(&mut self.reader)?;
+        Ok(Coin {
+            txid,
+            vout,
+            height: (code >> 1) as u32,
+            coinbase: code & 1 == 1,
+            value,
+            script_pubkey,
+        })
+    }
+}
+
+impl<R: Read> Iterator for SnapshotReader<R> {
+    type Item = Result<Coin, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.coins_read == self.coins_count {
+            return None;
+        }
+        let coin = self.read_coin();
+        match coin {
+            Ok(_) => self.coins_read += 1,
+            Err(_) => self.failed = true,
+        }
+        Some(coin)
+    }
+}
+
+/// Expands an amount compressed by `CompressAmount`
+///
+/// Returns `None` if the amount overflows or is above the network's
+/// `constants::max_money`.
+pub fn decompress_amount(x: u64, network: &Network) -> Option<u64> {
+    if x == 0 {
+        return Some(0);
+    }
+    let mut x = x - 1;
+    let e = x % 10;
+    x /= 10;
+    let mut n = if e < 9 {
+        let d = x % 9 + 1;
+        x /= 9;
+        x.checked_mul(10)?.checked_add(d)?
+    } else {
+        x.checked_add(1)?
+    };
+    for _ in 0..e {
+        n = n.checked_mul(10)?;
+    }
+    if n > constants::max_money(network) {
+        return None;
+    }
+    Some(n)
+}
+
+/// Reads a script compressed by `ScriptCompression`
+fn read_script<R: Read>(reader: &mut R) -> Result<Vec<u8>, Error> {
+    let size = read_varint(reader)?;
+    let script = match size {
+        0x00 => {
+            let mut hash = [0u8; 20];
+            read_exact(reader, &mut hash)?;
+            let mut script = vec![0x76, 0xa9, 0x14];
+            script.extend_from_slice(&hash);
+            script.extend_from_slice(&[0x88, 0xac]);
+            script
+        }
+        0x01 => {
+            let mut hash = [0u8; 20];
+            read_exact(reader, &mut hash)?;
+            let mut script = vec![0xa9, 0x14];
+            script.extend_from_slice(&hash);
+            script.push(0x87);
+            script
+        }
+        0x02..=0x05 => {
+            let mut key = [0u8; 33];
+            read_exact(reader, &mut key[1..])?;
+            if size < 4 {
+                key[0] = size as u8;
+                let mut script = vec![0x21];
+                script.extend_from_slice(&key);
+                script.push(0xac);
+                script
+            } else {
+                key[0] = size as u8 - 2;
+                let key = PublicKey::from_slice(&key).map_err(|_| Error::InvalidCoin)?;
+                let mut script = vec![0x41];
+                script.extend_from_slice(&key.serialize_uncompressed());
+                script.push(0xac);
+                script
+            }
+        }
+        _ => {
+            let len = size - SPECIAL_SCRIPTS;
+            if len > MAX_SCRIPT_SIZE {
+                return Err(Error::InvalidCoin);
+            }
+            let mut script = vec![0u8; len as usize];
+            read_exact(reader, &mut script)?;
+            script
+        }
+    };
+    Ok(script)
+}
+
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), Error> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => Error::UnexpectedEnd,
+        kind => Error::Io(kind),
+    })
+}
+
+fn read_hash<R: Read>(reader: &mut R) -> Result<[u8; 32], Error> {
+    let mut hash = [0u8; 32];
+    read_exact(reader, &mut hash)?;
+    Ok(hash)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, Error> {
+    let mut b = [0u8; 1];
+    read_exact(reader, &mut b)?;
+    Ok(b[0])
+}
+
+/// Reads a compact size integer, rejecting non-canonical encodings
+fn read_compact_size<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let (len, min) = match read_u8(reader)? {
+        0xfd => (2, 0xfd),
+        0xfe => (4, 0x1_0000),
+        0xff => (8, 0x1_0000_0000),
+        n => return Ok(u64::from(n)),
+    };
+    let mut buf = [0u8; 8];
+    read_exact(reader, &mut buf[..len])?;
+    let n = u64::from_le_bytes(buf);
+    if n < min {
+        return Err(Error::InvalidCoin);
+    }
+    Ok(n)
+}
+
+/// Reads Bitcoin Core's base-128 `VARINT`
+fn read_varint<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let mut n: u64 = 0;
+    loop {
+        let b = read_u8(reader)?;
+        if n > u64::MAX >> 7 {
+            return Err(Error::InvalidCoin);
+        }
+        n = (n << 7) | u64::from(b & 0x7f);
+        if b & 0x80 == 0 {
+            return Ok(n);
+        }
+        n = n.checked_add(1).ok_or(Error::InvalidCoin)?;
+    }
+}
+
+/// Error types for UTXO snapshot reading
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// Reading the snapshot failed
+    Io(io::ErrorKind),
+    /// The snapshot ends before its declared coin count
+    UnexpectedEnd,
+    /// The snapshot format version is not supported
+    UnsupportedVersion(u16),
+    /// The snapshot was taken on another network
+    NetworkMismatch,
+    /// A coin is malformed
+    InvalidCoin,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(kind) => write!(f, "I/O error: {:?}", kind),
+            Error::UnexpectedEnd => write!(f, "unexpected end of snapshot"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version: {}", version)
+            }
+            Error::NetworkMismatch => write!(f, "snapshot is for another network"),
+            Error::InvalidCoin => write!(f, "invalid coin"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(_) => "I/O error",
+            Error::UnexpectedEnd => "unexpected end of snapshot",
+            Error::UnsupportedVersion(_) => "unsupported snapshot version",
+            Error::NetworkMismatch => "snapshot is for another network",
+            Error::InvalidCoin => "invalid coin",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+
+    const KEY_HASH: &str = "751e76e8199196d454941c45d1b3a323f1433bd6";
+    const G_X: &str = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+        let mut tmp = Vec::new();
+        loop {
+            let flag = if tmp.is_empty() { 0 } else { 0x80 };
+            tmp.push((n & 0x7f) as u8 | flag);
+            if n <= 0x7f {
+                break;
+            }
+            n = (n >> 7) - 1;
+        }
+        tmp.reverse();
+        out.extend_from_slice(&tmp);
+    }
+
+    /// Serializes a coin with an already compressed amount and script
+    fn coin(out: &mut Vec<u8>, height: u64, amount: u64, script: &[u8]) {
+        write_varint(out, height << 1);
+        write_varint(out, amount);
+        out.extend_from_slice(script);
+    }
+
+    fn raw_script(script: &str) -> Vec<u8> {
+        let script = hex::decode(script).unwrap();
+        let mut out = Vec::new();
+        write_varint(&mut out, script.len() as u64 + SPECIAL_SCRIPTS);
+        out.extend_from_slice(&script);
+        out
+    }
+
+    #[test]
+    fn amounts() {
+        let network = Network::Bitcoin;
+        assert_eq!(decompress_amount(0, &network), Some(0));
+        assert_eq!(decompress_amount(1, &network), Some(1));
+        assert_eq!(decompress_amount(50, &network), Some(5_000_000_000));
+        assert_eq!(decompress_amount(11_101, &network), Some(1234));
+        // 21 million coins, then one more satoshi
+        assert_eq!(
+            decompress_amount(21_000_000, &network),
+            Some(constants::max_money(&network))
+        );
+        assert_eq!(decompress_amount(18_900_000_000_000_001, &network), None);
+        assert_eq!(decompress_amount(100_000_000_000, &network), None);
+        assert_eq!(decompress_amount(u64::MAX, &network), None);
+    }
+
+    #[test]
+    fn scripts() {
+        let mut data = vec![0x00];
+        data.extend_from_slice(&hex::decode(KEY_HASH).unwrap());
+        assert_eq!(
+            hex::encode(&read_script(&mut &data[..]).unwrap()),
+            format!("76a914{}88ac", KEY_HASH)
+        );
+        data[0] = 0x01;
+        assert_eq!(
+            hex::encode(&read_script(&mut &data[..]).unwrap()),
+            format!("a914{}87", KEY_HASH)
+        );
+
+        let mut data = vec![0x02];
+        data.extend_from_slice(&hex::decode(G_X).unwrap());
+        assert_eq!(
+            hex::encode(&read_script(&mut &data[..]).unwrap()),
+            format!("2102{}ac", G_X)
+        );
+        data[0] = 0x04;
+        assert_eq!(
+            hex::encode(&read_script(&mut &data[..]).unwrap()),
+            format!(
+                "4104{}483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8ac",
+                G_X
+            )
+        );
+
+        let data = raw_script(&format!("0014{}", KEY_HASH));
+        assert_eq!(
+            hex::encode(&read_script(&mut &data[..]).unwrap()),
+            format!("0014{}", KEY_HASH)
+        );
+    }
+
+    #[test]
+    fn legacy_snapshot() {
+        let mut data = vec![0xab; 32];
+        data.extend_from_slice(&2u64.to_le_bytes());
+        data.extend_from_slice(&[0x01; 32]);
+        data.extend_from_slice(&1u32.to_le_bytes());
+        coin(
+            &mut data,
+            100,
+            50,
+            &raw_script(&format!("0014{}", KEY_HASH)),
+        );
+        data.extend_from_slice(&[0x02; 32]);
+        data.extend_from_slice(&0u32.to_le_bytes());
+        let mut p2pkh = vec![0x00];
+        p2pkh.extend_from_slice(&hex::decode(KEY_HASH).unwrap());
+        coin(&mut data, 7, 11_101, &p2pkh);
+
+        let reader = SnapshotReader::new(&data[..], Network::Bitcoin).unwrap();
+        assert_eq!(reader.base_block_hash(), &[0xab; 32]);
+        assert_eq!(reader.coins_count(), 2);
+        let coins: Vec<Coin> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(coins.len(), 2);
+        assert_eq!(coins[0].vout(), 1);
+        assert_eq!(coins[0].height(), 100);
+        assert_eq!(coins[0].value(), 5_000_000_000);
+        assert!(coins[0].witness_program(Network::Bitcoin).is_some());
+        assert_eq!(coins[1].value(), 1234);
+        assert_eq!(coins[1].witness_program(Network::Bitcoin), None);
+
+        let mut truncated = data.clone();
+        truncated.pop();
+        let result: Result<Vec<Coin>, Error> =
+            SnapshotReader::new(&truncated[..], Network::Bitcoin)
+                .unwrap()
+                .collect();
+        assert_eq!(result, Err(Error::UnexpectedEnd));
+    }
+
+    #[test]
+    fn versioned_snapshot_balances() {
+        let mut data = SNAPSHOT_MAGIC.to_vec();
+        data.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        data.extend_from_slice(&constants::magic(&Network::Litecoin).unwrap());
+        data.extend_from_slice(&[0xcd; 32]);
+        data.extend_from_slice(&3u64.to_le_bytes());
+        let p2wpkh = raw_script(&format!("0014{}", KEY_HASH));
+        data.extend_from_slice(&[0x01; 32]);
+        data.push(2);
+        data.push(0);
+        coin(&mut data, 10, 50, &p2wpkh);
+        data.push(3);
+        coin(&mut data, 10, 11_101, &raw_script("6a00"));
+        data.extend_from_slice(&[0x02; 32]);
+        data.push(1);
+        data.push(0);
+        coin(&mut data, 11, 11_101, &p2wpkh);
+
+        let coins: Vec<Coin> = SnapshotReader::new(&data[..], Network::Litecoin)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(coins.len(), 3);
+        assert_eq!(coins[1].txid(), coins[0].txid());
+        assert_eq!(coins[1].vout(), 3);
+        assert_eq!(coins[2].txid().as_byte_array(), &[0x02; 32]);
+
+        let balances = SnapshotReader::new(&data[..], Network::Litecoin)
+            .unwrap()
+            .balances()
+            .unwrap();
+        assert_eq!(balances.len(), 1);
+        assert_eq!(
+            balances.get("ltc1qw508d6qejxtdg4y5r3zarvary0c5xw7kgmn4n9"),
+            Some(&5_000_001_234)
+        );
+
+        // Coins above the network's money supply are rejected
+        let mut invalid = data[..51].to_vec();
+        invalid[43..51].copy_from_slice(&2u64.to_le_bytes());
+        invalid.extend_from_slice(&[0x03; 32]);
+        invalid.push(2);
+        invalid.push(0);
+        coin(&mut invalid, 12, 84_000_000, &p2wpkh);
+        invalid.push(1);
+        coin(&mut invalid, 12, 75_600_000_000_000_001, &p2wpkh);
+        let mut coins = SnapshotReader::new(&invalid[..], Network::Litecoin).unwrap();
+        assert_eq!(
+            coins.next().unwrap().unwrap().value(),
+            constants::max_money(&Network::Litecoin)
+        );
+        assert_eq!(coins.next(), Some(Err(Error::InvalidCoin)));
+        assert_eq!(
+            SnapshotReader::new(&invalid[..], Network::Litecoin)
+                .unwrap()
+                .balances(),
+            Err(Error::InvalidCoin)
+        );
+
+        assert_eq!(
+            SnapshotReader::new(&data[..], Network::Bitcoin).err(),
+            Some(Error::NetworkMismatch)
+        );
+        data[5] = 3;
+        assert_eq!(
+            SnapshotReader::new(&data[..], Network::Litecoin).err(),
+            Some(Error::UnsupportedVersion(3))
+        );
+    }
+}
@@ -0,0 +1,233 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Witness versions
+
+use bech32::{u5, Variant};
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use Error;
+
+/// The bech32 alphabet, indexed by 5-bit value
+const CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Version of a witness program, as defined by BIP-0141
+///
+/// Only versions 0 through 16 exist, so every value of this type can be
+/// encoded as an address or a script public key.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum WitnessVersion {
+    /// Version 0, used by P2WPKH and P2WSH
+    V0 = 0,
+    /// Version 1, used by Taproot
+    V1 = 1,
+    /// Version 2
+    V2 = 2,
+    /// Version 3
+    V3 = 3,
+    /// Version 4
+    V4 = 4,
+    /// Version 5
+    V5 = 5,
+    /// Version 6
+    V6 = 6,
+    /// Version 7
+    V7 = 7,
+    /// Version 8
+    V8 = 8,
+    /// Version 9
+    V9 = 9,
+    /// Version 10
+    V10 = 10,
+    /// Version 11
+    V11 = 11,
+    /// Version 12
+    V12 = 12,
+    /// Version 13
+    V13 = 13,
+    /// Version 14
+    V14 = 14,
+    /// Version 15
+    V15 = 15,
+    /// Version 16
+    V16 = 16,
+}
+
+impl WitnessVersion {
+    /// Numeric value of the version
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    /// Bech32 character encoding the version in an address
+    pub fn to_char(self) -> char {
+        self.to_u5().to_char()
+    }
+
+    /// Parses the bech32 character following the separator of an address
+    pub fn from_char(c: char) -> Result<WitnessVersion, Error> {
+        match CHARSET.find(c.to_ascii_lowercase()) {
+            Some(value) => WitnessVersion::try_from(value as u8),
+            None => Err(Error::Bech32(bech32::Error::InvalidChar(c))),
+        }
+    }
+
+    /// Script opcode pushing the version: `OP_0` or `OP_1` through `OP_16`
+    pub fn to_opcode(self) -> u8 {
+        match self {
+            WitnessVersion::V0 => 0x00,
+            v => v.to_u8() + 0x50,
+        }
+    }
+
+    /// Parses the version opcode at the start of a script public key
+    pub fn from_opcode(opcode: u8) -> Result<WitnessVersion, Error> {
+        match opcode {
+            0x00 => Ok(WitnessVersion::V0),
+            0x51..=0x60 => WitnessVersion::try_from(opcode - 0x50),
+            _ => Err(Error::ScriptPubkeyInvalidVersion),
+        }
+    }
+
+    /// Checksum variant required by BIP-0350 for this version
+    ///
+    /// Version 0 keeps the original Bech32 checksum, every later version
+    /// uses Bech32m.
+    pub fn variant(self) -> Variant {
+        match self {
+            WitnessVersion::V0 => Variant::Bech32,
+            _ => Variant::Bech32m,
+        }
+    }
+
+    pub(crate) fn to_u5(self) -> u5 {
+        u5::try_from_u8(self.to_u8()).expect("versions are below 32")
+    }
+}
+
+impl TryFrom<u8> for WitnessVersion {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<WitnessVersion, Error> {
+        Ok(match value {
+            0 => WitnessVersion::V0,
+            1 => WitnessVersion::V1,
+            2 => WitnessVersion::V2,
+            3 => WitnessVersion::V3,
+            4 => WitnessVersion::V4,
+            5 => WitnessVersion::V5,
+            6 => WitnessVersion::V6,
+            7 => WitnessVersion::V7,
+            8 => WitnessVersion::V8,
+            9 => WitnessVersion::V9,
+            10 => WitnessVersion::V10,
+            11 => WitnessVersion::V11,
+            12 => WitnessVersion::V12,
+            13 => WitnessVersion::V13,
+            14 => WitnessVersion::V14,
+            15 => WitnessVersion::V15,
+            16 => WitnessVersion::V16,
+            _ => return Err(Error::InvalidScriptVersion),
+        })
+    }
+}
+
+impl TryFrom<char> for WitnessVersion {
+    type Error = Error;
+
+    fn try_from(c: char) -> Result<WitnessVersion, Error> {
+        WitnessVersion::from_char(c)
+    }
+}
+
+impl From<WitnessVersion> for u8 {
+    fn from(version: WitnessVersion) -> u8 {
+        version.to_u8()
+    }
+}
+
+impl From<WitnessVersion> for char {
+    fn from(version: WitnessVersion) -> char {
+        version.to_char()
+    }
+}
+
+impl fmt::Display for WitnessVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_u8())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        for value in 0..=16u8 {
+            let version = WitnessVersion::try_from(value).unwrap();
+            assert_eq!(u8::from(version), value);
+            assert_eq!(version.to_string(), value.to_string());
+            assert_eq!(
+                WitnessVersion::from_opcode(version.to_opcode()),
+                Ok(version)
+            );
+            assert_eq!(WitnessVersion::from_char(version.to_char()), Ok(version));
+            assert_eq!(
+                WitnessVersion::from_char(version.to_char().to_ascii_uppercase()),
+                Ok(version)
+            );
+        }
+        assert_eq!(WitnessVersion::V0.to_opcode(), 0x00);
+        assert_eq!(WitnessVersion::V1.to_opcode(), 0x51);
+        assert_eq!(WitnessVersion::V16.to_opcode(), 0x60);
+        assert_eq!(WitnessVersion::V0.to_char(), 'q');
+        assert_eq!(WitnessVersion::V1.to_char(), 'p');
+        assert_eq!(WitnessVersion::V16.to_char(), 's');
+    }
+
+    #[test]
+    fn invalid_versions() {
+        assert_eq!(
+            WitnessVersion::try_from(17u8),
+            Err(Error::InvalidScriptVersion)
+        );
+        assert_eq!(
+            WitnessVersion::from_char('3'),
+            Err(Error::InvalidScriptVersion)
+        );
+        assert_eq!(
+            WitnessVersion::from_char('b'),
+            Err(Error::Bech32(bech32::Error::InvalidChar('b')))
+        );
+        assert_eq!(
+            WitnessVersion::from_char('\u{e9}'),
+            Err(Error::Bech32(bech32::Error::InvalidChar('\u{e9}')))
+        );
+        for opcode in [0x01u8, 0x10, 0x4f, 0x50, 0x61, 0xff].iter() {
+            assert_eq!(
+                WitnessVersion::from_opcode(*opcode),
+                Err(Error::ScriptPubkeyInvalidVersion)
+            );
+        }
+    }
+}
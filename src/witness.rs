@@ -0,0 +1,261 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Spent outputs recovered from input witnesses
+//!
+//! An input's witness reveals enough of the output it spends to rebuild
+//! the witness program for every spend type except the Taproot key path:
+//! the public key of a P2WPKH spend, the witness script of a P2WSH spend,
+//! and the leaf script and control block of a Taproot script path spend.
+
+use constants::Network;
+use taproot::{ControlBlock, TAPSCRIPT_LEAF_VERSION};
+use {check_compressed_pubkey, Error, WitnessProgram};
+
+/// First byte of a Taproot annex
+pub const ANNEX_TAG: u8 = 0x50;
+
+/// How an input spends its output
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum SpendType {
+    /// `[signature, public key]`
+    P2wpkh,
+    /// `[inputs..., witness script]`
+    P2wsh,
+    /// `[signature]`, optionally followed by an annex
+    TaprootKeyPath,
+    /// `[inputs..., leaf script, control block]`, optionally followed by an
+    /// annex
+    TaprootScriptPath,
+}
+
+/// The output an input spends, as far as its witness reveals it
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct SpentOutput {
+    /// Detected spend type
+    spend_type: SpendType,
+    /// Witness program of the spent output
+    program: Option<WitnessProgram>,
+    /// Witness script or leaf script executed by the spend
+    script: Option<Vec<u8>>,
+}
+
+impl SpentOutput {
+    /// Recovers the spent output from an input witness
+    ///
+    /// The spend type is guessed from the shape of the stack, as the
+    /// witness alone does not name it. A two item stack of a DER signature
+    /// and a compressed key is a P2WPKH spend. Otherwise a single 64 or 65
+    /// byte item is a Taproot key path spend, and a last item that parses as
+    /// a tapscript control block whose parity matches the output key
+    /// computed from the preceding item is a Taproot script path spend.
+    /// Nothing ties that output key to the spent output, so a P2WSH witness
+    /// script that happens to have this shape is misread; such a script
+    /// starts with opcode 0xc0 or 0xc1 and fails as soon as it runs.
+    /// Anything else is read as a P2WSH spend of its last item.
+    pub fn from_witness<T: AsRef<[u8]>>(
+        witness: &[T],
+        network: Network,
+    ) -> Result<SpentOutput, Error> {
+        let items = witness.iter().map(|item| item.as_ref()).collect::<Vec<_>>();
+        let last = match items.last() {
+            Some(last) => *last,
+            None => return Err(Error::EmptyWitness),
+        };
+        if items.len() == 2 && is_der_signature(items[0]) && check_compressed_pubkey(last).is_ok() {
+            return Ok(SpentOutput {
+                spend_type: SpendType::P2wpkh,
+                program: Some(WitnessProgram::p2wpkh_from_pubkey(last, network)?),
+                script: None,
+            });
+        }
+
+        let stack = match items.split_last() {
+            Some((annex, rest)) if !rest.is_empty() && annex.first() == Some(&ANNEX_TAG) => rest,
+            _ => &items[..],
+        };
+        if stack.len() == 1 && (stack[0].len() == 64 || stack[0].len() == 65) {
+            return Ok(SpentOutput {
+                spend_type: SpendType::TaprootKeyPath,
+                program: None,
+                script: None,
+            });
+        }
+        if stack.len() >= 2 {
+            let (control_block, script) = (stack[stack.len() - 1], stack[stack.len() - 2]);
+            if let Some(output_key) = tapscript_output_key(control_block, script) {
+                return Ok(SpentOutput {
+                    spend_type: SpendType::TaprootScriptPath,
+                    program: Some(WitnessProgram::p2tr(output_key, network)?),
+                    script: Some(script.to_vec()),
+                });
+            }
+        }
+
+        Ok(SpentOutput {
+            spend_type: SpendType::P2wsh,
+            program: Some(WitnessProgram::p2wsh_from_script(last, network)?),
+            script: Some(last.to_vec()),
+        })
+    }
+
+    /// Detected spend type
+    pub fn spend_type(&self) -> SpendType {
+        self.spend_type
+    }
+
+    /// Witness program of the spent output
+    ///
+    /// `None` for Taproot key path spends, whose witness only holds a
+    /// signature.
+    pub fn program(&self) -> Option<&WitnessProgram> {
+        self.program.as_ref()
+    }
+
+    /// Witness script or leaf script executed by the spend, if any
+    pub fn script(&self) -> Option<&[u8]> {
+        self.script.as_ref().map(|script| &script[..])
+    }
+}
+
+/// Whether `sig` is shaped like a DER signature followed by a sighash byte
+fn is_der_signature(sig: &[u8]) -> bool {
+    sig.len() >= 9 && sig.len() <= 73 && sig[0] == 0x30 && sig[1] as usize == sig.len() - 3
+}
+
+/// Output key a tapscript control block and leaf script would commit to
+///
+/// Only the control block's shape and output key parity are checked.
+fn tapscript_output_key(control_block: &[u8], script: &[u8]) -> Option<[u8; 32]> {
+    let control_block = ControlBlock::parse(control_block).ok()?;
+    if control_block.leaf_version() != TAPSCRIPT_LEAF_VERSION {
+        return None;
+    }
+    control_block.output_key(script).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+    use taproot::{TapLeaf, TapTree, TaprootOutput};
+    use AddressKind;
+
+    const DER_SIG: [u8; 9] = [0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01, 0x01];
+
+    fn internal_key() -> [u8; 32] {
+        let mut key = [0u8; 32];
+        key.copy_from_slice(
+            &hex::decode("187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27")
+                .unwrap(),
+        );
+        key
+    }
+
+    #[test]
+    fn segwit_v0() {
+        let pubkey =
+            hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+                .unwrap();
+        let spent =
+            SpentOutput::from_witness(&[DER_SIG.to_vec(), pubkey.clone()], Network::Bitcoin)
+                .unwrap();
+        assert_eq!(spent.spend_type(), SpendType::P2wpkh);
+        assert_eq!(
+            spent.program().unwrap().to_address(),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+        assert_eq!(spent.script(), None);
+
+        let mut script = vec![0x21];
+        script.extend_from_slice(&pubkey);
+        script.push(0xac);
+        let witness = vec![DER_SIG.to_vec(), script.clone()];
+        let spent = SpentOutput::from_witness(&witness, Network::Monacoin).unwrap();
+        assert_eq!(spent.spend_type(), SpendType::P2wsh);
+        assert_eq!(
+            spent.program(),
+            Some(&WitnessProgram::p2wsh_from_script(&script, Network::Monacoin).unwrap())
+        );
+        assert_eq!(spent.script(), Some(&script[..]));
+
+        let empty: [Vec<u8>; 0] = [];
+        assert_eq!(
+            SpentOutput::from_witness(&empty, Network::Bitcoin),
+            Err(Error::EmptyWitness)
+        );
+    }
+
+    #[test]
+    fn taproot() {
+        let spent = SpentOutput::from_witness(&[vec![1u8; 64]], Network::Bitcoin).unwrap();
+        assert_eq!(spent.spend_type(), SpendType::TaprootKeyPath);
+        assert_eq!(spent.program(), None);
+        let with_annex = [vec![1u8; 65], vec![ANNEX_TAG, 0x00]];
+        let spent = SpentOutput::from_witness(&with_annex, Network::Bitcoin).unwrap();
+        assert_eq!(spent.spend_type(), SpendType::TaprootKeyPath);
+
+        let internal_key = internal_key();
+        let a = TapLeaf::tapscript(vec![0x51]);
+        let b = TapLeaf::tapscript(vec![0x52, 0x87]);
+        let tree = TapTree::branch(TapTree::leaf(a), TapTree::leaf(b.clone()));
+        let output = TaprootOutput::new(internal_key, Some(&tree), Network::Litecoin).unwrap();
+        let control_block = output.control_block(&b).unwrap().serialize();
+
+        let witness = vec![vec![0x02], b.script().to_vec(), control_block.clone()];
+        let spent = SpentOutput::from_witness(&witness, Network::Litecoin).unwrap();
+        assert_eq!(spent.spend_type(), SpendType::TaprootScriptPath);
+        assert_eq!(spent.program(), Some(output.witness_program()));
+        assert_eq!(spent.program().unwrap().kind(), AddressKind::P2tr);
+        assert_eq!(spent.script(), Some(b.script()));
+
+        let witness = vec![b.script().to_vec(), control_block, vec![ANNEX_TAG]];
+        let spent = SpentOutput::from_witness(&witness, Network::Litecoin).unwrap();
+        assert_eq!(spent.program(), Some(output.witness_program()));
+
+        // A control block for another script falls back to P2WSH
+        let witness = vec![vec![0x53], output.control_block(&b).unwrap().serialize()];
+        let spent = SpentOutput::from_witness(&witness, Network::Litecoin).unwrap();
+        assert_eq!(spent.spend_type(), SpendType::P2wsh);
+    }
+
+    #[test]
+    fn ambiguous_p2wsh() {
+        // A witness script shaped like a control block is read as a Taproot
+        // spend whenever the parity bit happens to match
+        let mut spend_types = Vec::new();
+        for &leaf_version in [0xc0, 0xc1].iter() {
+            let mut script = vec![leaf_version];
+            script.extend_from_slice(&internal_key());
+            let witness = vec![vec![0x51], script.clone()];
+            let spent = SpentOutput::from_witness(&witness, Network::Bitcoin).unwrap();
+            match spent.spend_type() {
+                SpendType::TaprootScriptPath => assert_eq!(spent.script(), Some(&[0x51][..])),
+                _ => assert_eq!(spent.script(), Some(&script[..])),
+            }
+            spend_types.push(spent.spend_type());
+        }
+        spend_types.sort();
+        assert_eq!(
+            spend_types,
+            vec![SpendType::P2wsh, SpendType::TaprootScriptPath]
+        );
+    }
+}
@@ -0,0 +1,483 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Witness programs wrapped in chain-specific script data
+//!
+//! Namecoin name operations prefix the output script with the name data,
+//! which is then dropped so the rest of the script runs as usual:
+//!
+//! ```text
+//! OP_NAME_NEW <hash> OP_2DROP <program>
+//! OP_NAME_FIRSTUPDATE <name> <rand> <value> OP_2DROP OP_2DROP <program>
+//! OP_NAME_UPDATE <name> <value> OP_2DROP OP_DROP <program>
+//! ```
+//!
+//! Ravencoin asset operations append the asset payload instead:
+//!
+//! ```text
+//! <program> OP_RVN_ASSET <"rvn" type data> OP_DROP
+//! ```
+
+use std::{error, fmt};
+
+use constants::Network;
+use encode::Reader;
+use script::{self, instructions, Instruction, OP_2DROP, OP_DROP};
+use WitnessProgram;
+
+/// Registers the hash of a name and a random salt
+pub const OP_NAME_NEW: u8 = 0x51;
+/// Reveals and registers a name committed to by `OP_NAME_NEW`
+pub const OP_NAME_FIRSTUPDATE: u8 = 0x52;
+/// Updates the value of a registered name
+pub const OP_NAME_UPDATE: u8 = 0x53;
+/// Marks the asset payload of a Ravencoin output
+pub const OP_RVN_ASSET: u8 = 0xc0;
+
+/// Prefix of every Ravencoin asset payload
+const ASSET_PREFIX: &[u8; 3] = b"rvn";
+
+/// A Namecoin name operation
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub enum NameOperation {
+    /// Commitment to a name and salt
+    New {
+        /// Hash of the salt and name
+        hash: Vec<u8>,
+    },
+    /// First registration of a name
+    FirstUpdate {
+        /// Name registered
+        name: Vec<u8>,
+        /// Salt of the commitment
+        rand: Vec<u8>,
+        /// Initial value
+        value: Vec<u8>,
+    },
+    /// Change of the value of a name
+    Update {
+        /// Name updated
+        name: Vec<u8>,
+        /// New value
+        value: Vec<u8>,
+    },
+}
+
+/// A Ravencoin asset operation
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub enum AssetOperation {
+    /// Creation of a new asset
+    Issue {
+        /// Asset name
+        name: String,
+        /// Amount created, in the smallest unit
+        amount: u64,
+        /// Number of decimal places
+        units: u8,
+        /// Whether more can be issued later
+        reissuable: bool,
+        /// Attached IPFS hash, if any
+        ipfs_hash: Option<Vec<u8>>,
+    },
+    /// Transfer of an amount of an asset
+    ///
+    /// Any message and expiry time after the amount are not decoded.
+    Transfer {
+        /// Asset name
+        name: String,
+        /// Amount transferred, in the smallest unit
+        amount: u64,
+    },
+    /// Transfer of the owner token of an asset
+    Owner {
+        /// Owner token name
+        name: String,
+    },
+    /// Issue of more of an existing asset
+    Reissue {
+        /// Asset name
+        name: String,
+        /// Amount added, in the smallest unit
+        amount: u64,
+        /// Number of decimal places
+        units: u8,
+        /// Whether more can be issued later
+        reissuable: bool,
+        /// Replacement IPFS hash, if any
+        ipfs_hash: Option<Vec<u8>>,
+    },
+}
+
+/// Chain-specific data wrapped around a witness program
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub enum Wrapper {
+    /// A Namecoin name operation
+    Name(NameOperation),
+    /// A Ravencoin asset operation
+    Asset(AssetOperation),
+}
+
+/// Parses an output script, stripping any name or asset wrapper the
+/// network uses
+///
+/// Returns the underlying witness program and the decoded wrapper, if the
+/// script had one.
+pub fn parse_scriptpubkey(
+    script: &[u8],
+    network: Network,
+) -> Result<(WitnessProgram, Option<Wrapper>), Error> {
+    if let Ok(program) = WitnessProgram::from_scriptpubkey(script, network) {
+        return Ok((program, None));
+    }
+    let (program, wrapper) = match network {
+        Network::Namecoin | Network::NamecoinTestnet if is_name_script(script) => {
+            let ins = instructions(script)?;
+            let program_len = match ins.last() {
+                Some(&Instruction::Push(data)) if ins.len() >= 2 => data.len() + 2,
+                _ => return Err(Error::InvalidNameScript),
+            };
+            let operation = parse_name_operation(&ins[..ins.len() - 2])?;
+            (
+                &script[script.len() - program_len..],
+                Wrapper::Name(operation),
+            )
+        }
+        Network::Ravencoin | Network::RavencoinTestnet if script.len() > 2 => {
+            let program_len = 2 + script[1] as usize;
+            if script.len() <= program_len {
+                return Err(Error::InvalidAssetScript);
+            }
+            let payload = match instructions(&script[program_len..])?[..] {
+                [Instruction::Op(OP_RVN_ASSET), Instruction::Push(payload), Instruction::Op(OP_DROP)] => {
+                    payload
+                }
+                _ => return Err(Error::InvalidAssetScript),
+            };
+            let operation = parse_asset_operation(payload).ok_or(Error::InvalidAssetScript)?;
+            (&script[..program_len], Wrapper::Asset(operation))
+        }
+        _ => return Ok((WitnessProgram::from_scriptpubkey(script, network)?, None)),
+    };
+    Ok((
+        WitnessProgram::from_scriptpubkey(program, network)?,
+        Some(wrapper),
+    ))
+}
+
+/// Witness program of an output script, ignoring any wrapper
+pub(crate) fn witness_program(script: &[u8], network: Network) -> Option<WitnessProgram> {
+    parse_scriptpubkey(script, network)
+        .ok()
+        .map(|(program, _)| program)
+}
+
+/// Whether the script starts with a name opcode
+fn is_name_script(script: &[u8]) -> bool {
+    matches!(
+        script.first(),
+        Some(&OP_NAME_NEW) | Some(&OP_NAME_FIRSTUPDATE) | Some(&OP_NAME_UPDATE)
+    )
+}
+
+fn parse_name_operation(prefix: &[Instruction]) -> Result<NameOperation, Error> {
+    use script::Instruction::{Op, Push};
+    let operation = match *prefix {
+        [Op(OP_NAME_NEW), Push(hash), Op(OP_2DROP)] => NameOperation::New {
+            hash: hash.to_vec(),
+        },
+        [Op(OP_NAME_FIRSTUPDATE), Push(name), Push(rand), Push(value), Op(OP_2DROP), Op(OP_2DROP)] => {
+            NameOperation::FirstUpdate {
+                name: name.to_vec(),
+                rand: rand.to_vec(),
+                value: value.to_vec(),
+            }
+        }
+        [Op(OP_NAME_UPDATE), Push(name), Push(value), Op(OP_2DROP), Op(OP_DROP)] => {
+            NameOperation::Update {
+                name: name.to_vec(),
+                value: value.to_vec(),
+            }
+        }
+        _ => return Err(Error::InvalidNameScript),
+    };
+    Ok(operation)
+}
+
+fn parse_asset_operation(payload: &[u8]) -> Option<AssetOperation> {
+    let mut reader = Reader::new(payload);
+    if reader.read_bytes(ASSET_PREFIX.len())? != &ASSET_PREFIX[..] {
+        return None;
+    }
+    let kind = reader.read_u8()?;
+    let name = String::from_utf8(reader.read_var_bytes()?.to_vec()).ok()?;
+    let operation = match kind {
+        b'q' => {
+            let amount = read_amount(&mut reader)?;
+            let units = reader.read_u8()?;
+            let reissuable = reader.read_u8()? != 0;
+            let ipfs_hash = match reader.read_u8()? {
+                0 => None,
+                _ => Some(reader.read_bytes(reader.remaining())?.to_vec()),
+            };
+            AssetOperation::Issue {
+                name,
+                amount,
+                units,
+                reissuable,
+                ipfs_hash,
+            }
+        }
+        b't' => AssetOperation::Transfer {
+            name,
+            amount: read_amount(&mut reader)?,
+        },
+        b'o' => AssetOperation::Owner { name },
+        b'r' => {
+            let amount = read_amount(&mut reader)?;
+            let units = reader.read_u8()?;
+            let reissuable = reader.read_u8()? != 0;
+            let ipfs_hash = match reader.remaining() {
+                0 => None,
+                n => Some(reader.read_bytes(n)?.to_vec()),
+            };
+            AssetOperation::Reissue {
+                name,
+                amount,
+                units,
+                reissuable,
+                ipfs_hash,
+            }
+        }
+        _ => return None,
+    };
+    Some(operation)
+}
+
+/// Reads a non-negative amount
+fn read_amount(reader: &mut Reader) -> Option<u64> {
+    let amount = reader.read_u64()?;
+    if amount > i64::MAX as u64 {
+        return None;
+    }
+    Some(amount)
+}
+
+/// Error types for wrapped output scripts
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// The underlying script is not a valid witness program
+    WitnessProgram(::Error),
+    /// A push runs past the end of the script
+    Script(script::Error),
+    /// The script starts with a name opcode but is not a name operation
+    InvalidNameScript,
+    /// The script has data after the witness program that is not an
+    /// asset operation
+    InvalidAssetScript,
+}
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::WitnessProgram(e)
+    }
+}
+
+impl From<script::Error> for Error {
+    fn from(e: script::Error) -> Error {
+        Error::Script(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::WitnessProgram(ref e) => write!(f, "{}", e),
+            Error::Script(ref e) => write!(f, "{}", e),
+            Error::InvalidNameScript => write!(f, "invalid name script"),
+            Error::InvalidAssetScript => write!(f, "invalid asset script"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::WitnessProgram(_) => "witness program error",
+            Error::Script(_) => "script error",
+            Error::InvalidNameScript => "invalid name script",
+            Error::InvalidAssetScript => "invalid asset script",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::WitnessProgram(ref e) => Some(e),
+            Error::Script(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encode::write_var_bytes;
+    use hex;
+
+    const P2WPKH: &str = "0014751e76e8199196d454941c45d1b3a323f1433bd6";
+
+    fn p2wpkh() -> Vec<u8> {
+        hex::decode(P2WPKH).unwrap()
+    }
+
+    fn asset_script(kind: u8, data: &[u8]) -> Vec<u8> {
+        let mut payload = b"rvn".to_vec();
+        payload.push(kind);
+        payload.extend_from_slice(data);
+        let mut script = p2wpkh();
+        script.push(OP_RVN_ASSET);
+        script::push_slice(&mut script, &payload);
+        script.push(OP_DROP);
+        script
+    }
+
+    #[test]
+    fn namecoin_operations() {
+        let mut script = vec![OP_NAME_UPDATE];
+        script::push_slice(&mut script, b"d/example");
+        script::push_slice(&mut script, b"{\"ip\":\"192.0.2.1\"}");
+        script.extend_from_slice(&[OP_2DROP, OP_DROP]);
+        script.extend_from_slice(&p2wpkh());
+        let (program, wrapper) = parse_scriptpubkey(&script, Network::Namecoin).unwrap();
+        assert_eq!(
+            program.to_address(),
+            "nc1qw508d6qejxtdg4y5r3zarvary0c5xw7kttkktk"
+        );
+        assert_eq!(
+            wrapper,
+            Some(Wrapper::Name(NameOperation::Update {
+                name: b"d/example".to_vec(),
+                value: b"{\"ip\":\"192.0.2.1\"}".to_vec(),
+            }))
+        );
+
+        let mut script = vec![OP_NAME_NEW];
+        script::push_slice(&mut script, &[0x42; 20]);
+        script.push(OP_2DROP);
+        script.extend_from_slice(&p2wpkh());
+        let (_, wrapper) = parse_scriptpubkey(&script, Network::Namecoin).unwrap();
+        assert_eq!(
+            wrapper,
+            Some(Wrapper::Name(NameOperation::New {
+                hash: vec![0x42; 20]
+            }))
+        );
+
+        let mut script = vec![OP_NAME_FIRSTUPDATE];
+        script::push_slice(&mut script, b"d/example");
+        script::push_slice(&mut script, &[0x07; 20]);
+        script::push_slice(&mut script, b"");
+        script.extend_from_slice(&[OP_2DROP, OP_2DROP]);
+        script.extend_from_slice(&p2wpkh());
+        let (_, wrapper) = parse_scriptpubkey(&script, Network::Namecoin).unwrap();
+        match wrapper {
+            Some(Wrapper::Name(NameOperation::FirstUpdate { rand, value, .. })) => {
+                assert_eq!(rand, vec![0x07; 20]);
+                assert!(value.is_empty());
+            }
+            other => panic!("unexpected wrapper {:?}", other),
+        }
+
+        assert_eq!(
+            parse_scriptpubkey(&p2wpkh(), Network::Namecoin).unwrap().1,
+            None
+        );
+        let taproot = format!("5120{}", "a6".repeat(32));
+        assert_eq!(
+            parse_scriptpubkey(&hex::decode(&taproot).unwrap(), Network::Namecoin)
+                .unwrap()
+                .1,
+            None
+        );
+        assert!(parse_scriptpubkey(&script, Network::Bitcoin).is_err());
+        script.remove(script.len() - 23);
+        assert_eq!(
+            parse_scriptpubkey(&script, Network::Namecoin),
+            Err(Error::InvalidNameScript)
+        );
+    }
+
+    #[test]
+    fn ravencoin_operations() {
+        let mut data = Vec::new();
+        write_var_bytes(&mut data, b"ASSET");
+        data.extend_from_slice(&250_000_000u64.to_le_bytes());
+        let script = asset_script(b't', &data);
+        let (program, wrapper) = parse_scriptpubkey(&script, Network::Ravencoin).unwrap();
+        assert_eq!(program.to_scriptpubkey().unwrap(), p2wpkh());
+        assert_eq!(
+            wrapper,
+            Some(Wrapper::Asset(AssetOperation::Transfer {
+                name: "ASSET".to_string(),
+                amount: 250_000_000,
+            }))
+        );
+
+        data.extend_from_slice(&[8, 1, 0]);
+        let (_, wrapper) =
+            parse_scriptpubkey(&asset_script(b'q', &data), Network::Ravencoin).unwrap();
+        assert_eq!(
+            wrapper,
+            Some(Wrapper::Asset(AssetOperation::Issue {
+                name: "ASSET".to_string(),
+                amount: 250_000_000,
+                units: 8,
+                reissuable: true,
+                ipfs_hash: None,
+            }))
+        );
+
+        let mut owner = Vec::new();
+        write_var_bytes(&mut owner, b"ASSET!");
+        let (_, wrapper) =
+            parse_scriptpubkey(&asset_script(b'o', &owner), Network::Ravencoin).unwrap();
+        assert_eq!(
+            wrapper,
+            Some(Wrapper::Asset(AssetOperation::Owner {
+                name: "ASSET!".to_string()
+            }))
+        );
+
+        assert_eq!(
+            parse_scriptpubkey(&p2wpkh(), Network::Ravencoin).unwrap().1,
+            None
+        );
+        assert_eq!(
+            parse_scriptpubkey(&asset_script(b'x', &data), Network::Ravencoin),
+            Err(Error::InvalidAssetScript)
+        );
+        let mut script = asset_script(b't', &owner);
+        script.pop();
+        assert_eq!(
+            parse_scriptpubkey(&script, Network::Ravencoin),
+            Err(Error::InvalidAssetScript)
+        );
+    }
+}
@@ -0,0 +1,369 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Zen Protocol addresses
+//!
+//! A Zen address locks outputs either to the hash of a public key or to a
+//! contract. Both kinds start with a zero version group. Public key addresses
+//! use the network's `zen`/`tzn` prefix followed by the 32-byte hash.
+//! Contract addresses use `czen`/`ctzn` and carry the contract ID: the
+//! contract version as a big-endian `u32` followed by the 32-byte contract
+//! hash.
+
+use std::str::FromStr;
+use std::{error, fmt};
+
+use bech32::{self, u5, FromBase32, ToBase32, Variant};
+
+use constants::{self, Network};
+use hex;
+
+/// Length of public key and contract hashes
+pub const HASH_LEN: usize = 32;
+
+/// Identifies a contract by its version and hash
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub struct ContractId {
+    version: u32,
+    hash: [u8; HASH_LEN],
+}
+
+impl ContractId {
+    /// Creates a contract ID
+    pub fn new(version: u32, hash: [u8; HASH_LEN]) -> ContractId {
+        ContractId { version, hash }
+    }
+
+    /// Parses the hex form printed by the Zen wallet
+    pub fn from_hex(s: &str) -> Result<ContractId, Error> {
+        let bytes = hex::decode(s).ok_or(Error::InvalidHex)?;
+        ContractId::from_bytes(&bytes)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<ContractId, Error> {
+        if bytes.len() != 4 + HASH_LEN {
+            return Err(Error::InvalidLength);
+        }
+        let mut hash = [0u8; HASH_LEN];
+        hash.copy_from_slice(&bytes[4..]);
+        Ok(ContractId {
+            version: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            hash,
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = self.version.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.hash);
+        bytes
+    }
+
+    /// Contract version
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Contract hash
+    pub fn hash(&self) -> &[u8; HASH_LEN] {
+        &self.hash
+    }
+}
+
+impl fmt::Display for ContractId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&hex::encode(&self.to_bytes()))
+    }
+}
+
+/// The spending condition an address stands for
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum Lock {
+    /// Spendable with the key hashing to the given value
+    PK([u8; HASH_LEN]),
+    /// Spendable by the contract
+    Contract(ContractId),
+}
+
+/// A Zen Protocol address
+#[derive(PartialEq, Eq, Debug, Clone, PartialOrd, Ord, Hash)]
+pub struct ZenAddress {
+    network: Network,
+    lock: Lock,
+    bech32: String,
+}
+
+impl ZenAddress {
+    /// Encodes the address of a lock
+    pub fn from_lock(lock: Lock, network: Network) -> Result<ZenAddress, Error> {
+        let hrp = prefix(network, &lock)?;
+        let payload = match lock {
+            Lock::PK(hash) => hash.to_vec(),
+            Lock::Contract(id) => id.to_bytes(),
+        };
+        let mut data = vec![u5::try_from_u8(0).expect("0 is a valid u5")];
+        data.extend_from_slice(&payload.to_base32());
+        let bech32 = bech32::encode(hrp, data, Variant::Bech32).map_err(::Error::Bech32)?;
+        Ok(ZenAddress {
+            network,
+            lock,
+            bech32,
+        })
+    }
+
+    /// Address of a public key hash
+    pub fn from_pk_hash(hash: [u8; HASH_LEN], network: Network) -> Result<ZenAddress, Error> {
+        ZenAddress::from_lock(Lock::PK(hash), network)
+    }
+
+    /// Address of a contract
+    pub fn from_contract(id: ContractId, network: Network) -> Result<ZenAddress, Error> {
+        ZenAddress::from_lock(Lock::Contract(id), network)
+    }
+
+    /// Decodes a public key or contract address
+    pub fn from_address(address: &str) -> Result<ZenAddress, Error> {
+        ZenAddress::from_str(address)
+    }
+
+    /// Encodes the address as a string
+    pub fn to_address(&self) -> String {
+        self.to_string()
+    }
+
+    /// Network of the address
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Lock the address stands for
+    pub fn lock(&self) -> &Lock {
+        &self.lock
+    }
+}
+
+impl fmt::Display for ZenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.bech32)
+    }
+}
+
+impl FromStr for ZenAddress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ZenAddress, Error> {
+        let (hrp, data, variant) = bech32::decode(s).map_err(::Error::Bech32)?;
+        if variant != Variant::Bech32 {
+            return Err(Error::Address(::Error::InvalidChecksumVariant));
+        }
+        let (network, contract) = match &hrp[..] {
+            "czen" => (Network::ZenProtocol, true),
+            "ctzn" => (Network::ZenProtocolTestnet, true),
+            hrp => match constants::classify(hrp) {
+                Some(network @ Network::ZenProtocol)
+                | Some(network @ Network::ZenProtocolTestnet) => (network, false),
+                _ => return Err(Error::UnknownPrefix),
+            },
+        };
+        let (version, payload) = match data.split_first() {
+            Some((version, payload)) => (version.to_u8(), payload),
+            None => return Err(Error::InvalidLength),
+        };
+        if version != 0 {
+            return Err(Error::InvalidVersion(version));
+        }
+        let bytes = Vec::from_base32(payload).map_err(::Error::Bech32)?;
+        let lock = if contract {
+            Lock::Contract(ContractId::from_bytes(&bytes)?)
+        } else {
+            if bytes.len() != HASH_LEN {
+                return Err(Error::InvalidLength);
+            }
+            let mut hash = [0u8; HASH_LEN];
+            hash.copy_from_slice(&bytes);
+            Lock::PK(hash)
+        };
+        Ok(ZenAddress {
+            network,
+            lock,
+            bech32: s.to_lowercase(),
+        })
+    }
+}
+
+/// Human-readable part of an address for `lock`
+fn prefix(network: Network, lock: &Lock) -> Result<&'static str, Error> {
+    let prefix = match (network, lock) {
+        (Network::ZenProtocol, &Lock::PK(_)) => "zen",
+        (Network::ZenProtocolTestnet, &Lock::PK(_)) => "tzn",
+        (Network::ZenProtocol, &Lock::Contract(_)) => "czen",
+        (Network::ZenProtocolTestnet, &Lock::Contract(_)) => "ctzn",
+        (network, _) => return Err(Error::NotZen(network)),
+    };
+    Ok(prefix)
+}
+
+/// Error types for Zen Protocol addresses
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// The Bech32 encoding is invalid
+    Address(::Error),
+    /// The network is not Zen Protocol
+    NotZen(Network),
+    /// The human-readable part is not a Zen prefix
+    UnknownPrefix,
+    /// The address has a version other than 0
+    InvalidVersion(u8),
+    /// The hash or contract ID has the wrong length
+    InvalidLength,
+    /// The contract ID is not valid hex
+    InvalidHex,
+}
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::Address(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Address(ref e) => write!(f, "{}", e),
+            Error::NotZen(network) => write!(f, "{:?} is not Zen Protocol", network),
+            Error::UnknownPrefix => write!(f, "unknown address prefix"),
+            Error::InvalidVersion(version) => write!(f, "invalid address version: {}", version),
+            Error::InvalidLength => write!(f, "invalid hash length"),
+            Error::InvalidHex => write!(f, "invalid hex"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Address(_) => "address error",
+            Error::NotZen(_) => "network is not Zen Protocol",
+            Error::UnknownPrefix => "unknown address prefix",
+            Error::InvalidVersion(_) => "invalid address version",
+            Error::InvalidLength => "invalid hash length",
+            Error::InvalidHex => "invalid hex",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Address(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use WitnessProgram;
+
+    #[test]
+    fn public_key_addresses() {
+        let address = ZenAddress::from_pk_hash([0x5a; HASH_LEN], Network::ZenProtocol).unwrap();
+        assert!(address.to_address().starts_with("zen1q"));
+        let parsed = ZenAddress::from_address(&address.to_address()).unwrap();
+        assert_eq!(parsed, address);
+        assert_eq!(parsed.lock(), &Lock::PK([0x5a; HASH_LEN]));
+
+        let testnet =
+            ZenAddress::from_pk_hash([0x5a; HASH_LEN], Network::ZenProtocolTestnet).unwrap();
+        assert!(testnet.to_address().starts_with("tzn1q"));
+        assert_eq!(
+            WitnessProgram::from_address(&address.to_address()),
+            Err(::Error::InvalidPayloadFamily)
+        );
+    }
+
+    #[test]
+    fn contract_addresses() {
+        let hex_id = format!("00000001{}", "c3".repeat(HASH_LEN));
+        let id = ContractId::from_hex(&hex_id).unwrap();
+        assert_eq!(id.version(), 1);
+        assert_eq!(id.to_string(), hex_id);
+
+        let address = ZenAddress::from_contract(id, Network::ZenProtocol).unwrap();
+        assert!(address.to_address().starts_with("czen1"));
+        let parsed = ZenAddress::from_address(&address.to_address()).unwrap();
+        assert_eq!(parsed.lock(), &Lock::Contract(id));
+        assert_eq!(parsed.network(), Network::ZenProtocol);
+        let testnet = ZenAddress::from_lock(Lock::Contract(id), Network::ZenProtocolTestnet);
+        assert!(testnet.unwrap().to_address().starts_with("ctzn1"));
+    }
+
+    #[test]
+    fn known_answers() {
+        let mut pk = [0u8; HASH_LEN];
+        let mut contract = [0u8; HASH_LEN];
+        for i in 0..HASH_LEN {
+            pk[i] = i as u8;
+            contract[i] = 0x20 + i as u8;
+        }
+
+        let address = "zen1qqqqsyqcyq5rqwzqfpg9scrgwpugpzysnzs23v9ccrydpk8qarc0sq9p3e6";
+        let parsed = ZenAddress::from_address(address).unwrap();
+        assert_eq!(parsed.lock(), &Lock::PK(pk));
+        assert_eq!(
+            ZenAddress::from_pk_hash(pk, Network::ZenProtocol)
+                .unwrap()
+                .to_address(),
+            address
+        );
+
+        let id = ContractId::new(0, contract);
+        let address = "czen1qqqqqqqpqyy3zxfp9ycnjs2f29vkz6t30xqcnyve5x5mrwwpe8ganc0f78ul0s3d8";
+        let parsed = ZenAddress::from_address(address).unwrap();
+        assert_eq!(parsed.lock(), &Lock::Contract(id));
+        assert_eq!(
+            ZenAddress::from_contract(id, Network::ZenProtocol)
+                .unwrap()
+                .to_address(),
+            address
+        );
+
+        // The same contract ID without the leading version group
+        let unversioned = "czen1qqqqqqpqyy3zxfp9ycnjs2f29vkz6t30xqcnyve5x5mrwwpe8ganc0f78u4e7ns0";
+        assert!(ZenAddress::from_address(unversioned).is_err());
+    }
+
+    #[test]
+    fn invalid_addresses() {
+        assert_eq!(
+            ZenAddress::from_pk_hash([0; HASH_LEN], Network::Bitcoin),
+            Err(Error::NotZen(Network::Bitcoin))
+        );
+        assert_eq!(
+            ZenAddress::from_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"),
+            Err(Error::UnknownPrefix)
+        );
+        assert_eq!(ContractId::from_hex("0000"), Err(Error::InvalidLength));
+        assert_eq!(ContractId::from_hex("zz"), Err(Error::InvalidHex));
+
+        let mut data = vec![u5::try_from_u8(1).unwrap()];
+        data.extend_from_slice(&[0u8; HASH_LEN].to_base32());
+        let v1 = bech32::encode("zen", data, Variant::Bech32).unwrap();
+        assert_eq!(ZenAddress::from_address(&v1), Err(Error::InvalidVersion(1)));
+    }
+}
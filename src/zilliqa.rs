@@ -0,0 +1,204 @@
+// Copyright (c) 2017 Clark Moody
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Zilliqa address conversion
+//!
+//! Zilliqa accounts are 20-byte addresses, written either in Bech32 with
+//! the `zil` prefix or in hex with a mixed-case checksum: each letter of the
+//! hex is uppercased when bit `255 - 6i` of the SHA256 of the address bytes
+//! is set, `i` being the position of the letter.
+
+use std::{error, fmt};
+
+use constants::Network;
+use hash::sha256;
+use hex;
+use AccountAddress;
+
+/// Length of a Zilliqa address in bytes
+pub const ADDRESS_LEN: usize = 20;
+
+/// Encodes an address as `0x`-prefixed hex with the Zilliqa checksum
+pub fn to_checksum_address(address: &[u8; ADDRESS_LEN]) -> String {
+    let hash = sha256(address);
+    let checksummed: String = hex::encode(address)
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let bit = 255 - 6 * i;
+            if hash[31 - bit / 8] & (1 << (bit % 8)) != 0 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+    format!("0x{}", checksummed)
+}
+
+/// Decodes checksummed hex, with or without the `0x` prefix
+pub fn from_checksum_address(s: &str) -> Result<[u8; ADDRESS_LEN], Error> {
+    let digits = if s.starts_with("0x") || s.starts_with("0X") {
+        &s[2..]
+    } else {
+        s
+    };
+    let bytes = hex::decode(digits).ok_or(Error::InvalidHex)?;
+    if bytes.len() != ADDRESS_LEN {
+        return Err(Error::InvalidLength);
+    }
+    let mut address = [0u8; ADDRESS_LEN];
+    address.copy_from_slice(&bytes);
+    if to_checksum_address(&address)[2..] != *digits {
+        return Err(Error::InvalidChecksum);
+    }
+    Ok(address)
+}
+
+/// Converts a `zil` Bech32 address to checksummed hex
+pub fn bech32_to_hex(address: &str) -> Result<String, Error> {
+    let address = AccountAddress::from_address(address)?;
+    check_network(address.network())?;
+    if address.payload().len() != ADDRESS_LEN {
+        return Err(Error::InvalidLength);
+    }
+    let mut bytes = [0u8; ADDRESS_LEN];
+    bytes.copy_from_slice(address.payload());
+    Ok(to_checksum_address(&bytes))
+}
+
+/// Converts checksummed hex to a Bech32 address on `network`
+pub fn hex_to_bech32(s: &str, network: Network) -> Result<String, Error> {
+    check_network(network)?;
+    let bytes = from_checksum_address(s)?;
+    Ok(AccountAddress::new(bytes.to_vec(), network)?.to_address())
+}
+
+fn check_network(network: Network) -> Result<(), Error> {
+    match network {
+        Network::Zilliqa | Network::ZilliqaTestnet => Ok(()),
+        network => Err(Error::NotZilliqa(network)),
+    }
+}
+
+/// Error types for Zilliqa address conversion
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    /// The Bech32 address is malformed
+    Address(::Error),
+    /// The network is not Zilliqa
+    NotZilliqa(Network),
+    /// The address is not 20 bytes long
+    InvalidLength,
+    /// The hex address has characters that are not hex digits
+    InvalidHex,
+    /// The letter case of the hex address does not match its checksum
+    InvalidChecksum,
+}
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::Address(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Address(ref e) => write!(f, "{}", e),
+            Error::NotZilliqa(network) => write!(f, "{:?} is not Zilliqa", network),
+            Error::InvalidLength => write!(f, "address is not 20 bytes"),
+            Error::InvalidHex => write!(f, "invalid hex"),
+            Error::InvalidChecksum => write!(f, "invalid address checksum"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Address(_) => "address error",
+            Error::NotZilliqa(_) => "network is not Zilliqa",
+            Error::InvalidLength => "address is not 20 bytes",
+            Error::InvalidHex => "invalid hex",
+            Error::InvalidChecksum => "invalid address checksum",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Address(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BECH32: &str = "zil1fwh4ltdguhde9s7nysnp33d5wye6uqpugufkz7";
+    const CHECKSUMMED: &str = "0x4BAF5faDA8e5Db92C3d3242618c5B47133AE003C";
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(bech32_to_hex(BECH32).unwrap(), CHECKSUMMED);
+        assert_eq!(
+            hex_to_bech32(CHECKSUMMED, Network::Zilliqa).unwrap(),
+            BECH32
+        );
+        assert_eq!(
+            hex_to_bech32(&CHECKSUMMED[2..], Network::Zilliqa).unwrap(),
+            BECH32
+        );
+        let testnet = hex_to_bech32(CHECKSUMMED, Network::ZilliqaTestnet).unwrap();
+        assert!(testnet.starts_with("tzil1"));
+        assert_eq!(bech32_to_hex(&testnet).unwrap(), CHECKSUMMED);
+    }
+
+    #[test]
+    fn invalid_addresses() {
+        assert_eq!(
+            from_checksum_address(&CHECKSUMMED.to_lowercase()),
+            Err(Error::InvalidChecksum)
+        );
+        assert_eq!(
+            from_checksum_address(&CHECKSUMMED[..40]),
+            Err(Error::InvalidLength)
+        );
+        assert_eq!(
+            from_checksum_address("0x4BAF5faDA8e5Db92C3d3242618c5B47133AE00zz"),
+            Err(Error::InvalidHex)
+        );
+        assert_eq!(
+            hex_to_bech32(CHECKSUMMED, Network::CryptoComChain),
+            Err(Error::NotZilliqa(Network::CryptoComChain))
+        );
+        let short = AccountAddress::new(vec![0; 19], Network::Zilliqa).unwrap();
+        assert_eq!(
+            bech32_to_hex(&short.to_address()),
+            Err(Error::InvalidLength)
+        );
+        assert_eq!(
+            bech32_to_hex("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"),
+            Err(Error::Address(::Error::InvalidPayloadFamily))
+        );
+    }
+}